@@ -16,7 +16,7 @@ fn main() {
         @rustlang
         "\n"
         "With hashtag: "
-        #rust
+        hashtag { "rust" }
     };
 
     let generator = Generator::new(ParseMode::MarkdownV2);
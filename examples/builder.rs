@@ -0,0 +1,25 @@
+use msg::{Generate, Message, ParseMode};
+
+fn main() {
+    // The builder is the imperative twin of the msg! macro
+    let message = Message::builder()
+        .bold("Weekly report")
+        .newline()
+        .text("Details: ")
+        .link("dashboard", "https://example.com/dashboard")
+        .newline()
+        .list(["Build is green", "Two new contributors"])
+        .newline()
+        .table(["Service", "Uptime"], [["api", "99.9%"], ["web", "100%"]])
+        .build();
+
+    match message.generate(ParseMode::MarkdownV2) {
+        Ok(output) => println!("MarkdownV2:\n{}\n", output),
+        Err(e) => eprintln!("Error: {}", e),
+    }
+
+    match message.generate(ParseMode::Html) {
+        Ok(output) => println!("HTML:\n{}", output),
+        Err(e) => eprintln!("Error: {}", e),
+    }
+}
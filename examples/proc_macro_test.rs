@@ -27,7 +27,7 @@ fn main() {
         " and "
         @username
         " or "
-        #hashtag
+        hashtag { "hashtag" }
     };
     println!("Links: {:?}", msg4);
 
@@ -73,14 +73,7 @@ fn main() {
     println!("Contact info: {:?}", msg8);
 
     let generator = Generator::new(ParseMode::MarkdownV2);
-    let mut output = String::new();
-    for element in &msg2 {
-        match generator.generate(&mut output, element) {
-            Ok(()) => {}
-            Err(e) => println!("Error: {}", e),
-        }
-    }
-    println!("Generated: {}", output);
+    println!("Generated: {}", generator.render_lossy(&msg2));
 
     let element = msg!(bold { "Single bold element" });
     println!("Single element: {:?}", element);
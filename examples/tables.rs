@@ -6,18 +6,21 @@ fn main() {
             TableCell {
                 content: vec![Element::text("Товар")],
                 align: CellAlign::Left,
+                style: CellStyle::Normal,
                 colspan: 1,
                 rowspan: 1,
             },
             TableCell {
                 content: vec![Element::text("Количество")],
                 align: CellAlign::Center,
+                style: CellStyle::Normal,
                 colspan: 1,
                 rowspan: 1,
             },
             TableCell {
                 content: vec![Element::text("Цена")],
                 align: CellAlign::Right,
+                style: CellStyle::Normal,
                 colspan: 1,
                 rowspan: 1,
             },
@@ -28,18 +31,21 @@ fn main() {
                     TableCell {
                         content: vec![Element::text("Яблоки")],
                         align: CellAlign::Left,
+                        style: CellStyle::Normal,
                         colspan: 1,
                         rowspan: 1,
                     },
                     TableCell {
                         content: vec![Element::text("10")],
                         align: CellAlign::Center,
+                        style: CellStyle::Normal,
                         colspan: 1,
                         rowspan: 1,
                     },
                     TableCell {
                         content: vec![Element::text("150₽")],
                         align: CellAlign::Right,
+                        style: CellStyle::Normal,
                         colspan: 1,
                         rowspan: 1,
                     },
@@ -50,18 +56,21 @@ fn main() {
                     TableCell {
                         content: vec![Element::text("Груши")],
                         align: CellAlign::Left,
+                        style: CellStyle::Normal,
                         colspan: 1,
                         rowspan: 1,
                     },
                     TableCell {
                         content: vec![Element::text("5")],
                         align: CellAlign::Center,
+                        style: CellStyle::Normal,
                         colspan: 1,
                         rowspan: 1,
                     },
                     TableCell {
                         content: vec![Element::text("200₽")],
                         align: CellAlign::Right,
+                        style: CellStyle::Normal,
                         colspan: 1,
                         rowspan: 1,
                     },
@@ -70,20 +79,23 @@ fn main() {
             TableRow {
                 cells: vec![
                     TableCell {
-                        content: vec![Element::bold(vec![Element::text("Итого")])],
+                        content: vec![Element::text("Итого")],
                         align: CellAlign::Left,
+                        style: CellStyle::Bold,
                         colspan: 1,
                         rowspan: 1,
                     },
                     TableCell {
-                        content: vec![Element::bold(vec![Element::text("15")])],
+                        content: vec![Element::text("15")],
                         align: CellAlign::Center,
+                        style: CellStyle::Bold,
                         colspan: 1,
                         rowspan: 1,
                     },
                     TableCell {
-                        content: vec![Element::bold(vec![Element::text("350₽")])],
+                        content: vec![Element::text("350₽")],
                         align: CellAlign::Right,
+                        style: CellStyle::Bold,
                         colspan: 1,
                         rowspan: 1,
                     },
@@ -92,6 +104,7 @@ fn main() {
         ],
         style: TableStyle::Unicode,
         rules: vec![],
+        default_align: CellAlign::Left,
     });
 
     let generator = Generator::new(ParseMode::MarkdownV2);
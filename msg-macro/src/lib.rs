@@ -1,10 +1,10 @@
 use proc_macro::TokenStream;
 use quote::{quote, ToTokens};
 use syn::{
-    parse::{Parse, ParseStream},
+    parse::{Parse, ParseStream, Parser},
     parse_macro_input,
     punctuated::Punctuated,
-    token, Expr, Ident, Lit, Result, Token,
+    token, Expr, Ident, Lit, LitStr, Result, Token,
 };
 
 #[derive(Debug)]
@@ -15,6 +15,10 @@ enum TgMessageItem {
     Underline(Vec<TgMessageItem>),
     Strikethrough(Vec<TgMessageItem>),
     Spoiler(Vec<TgMessageItem>),
+    Quote {
+        cite: Option<Expr>,
+        items: Vec<TgMessageItem>,
+    },
     Code(Lit),
     Pre {
         code: Lit,
@@ -25,6 +29,10 @@ enum TgMessageItem {
         url: Expr,
     },
     Mention(Expr),
+    MentionId {
+        user_id: Expr,
+        text: Expr,
+    },
     MentionAt(Ident),
     Hashtag(Expr),
     HashtagHash(Ident),
@@ -37,13 +45,46 @@ enum TgMessageItem {
         headers: Vec<Expr>,
         rows: Vec<Vec<Expr>>,
     },
+    TableFrom {
+        headers: Expr,
+        rows: Expr,
+    },
     Phone {
         prefix: Option<String>,
         number: Expr,
+        strict: bool,
+    },
+    Date {
+        value: Expr,
+        format: Option<LitStr>,
+    },
+    DateTime {
+        value: Expr,
+        format: Option<LitStr>,
+    },
+    Time {
+        value: Expr,
+        format: Option<LitStr>,
+    },
+    DateTimeTz {
+        value: Expr,
+        format: Option<LitStr>,
     },
-    Date(Expr),
-    DateTime(Expr),
-    Time(Expr),
+    HtmlRaw(Expr),
+    Image {
+        url: Expr,
+        alt: Expr,
+    },
+    Command {
+        name: Expr,
+        bot: Option<Expr>,
+    },
+    TmeLink {
+        username: Expr,
+        post_id: Option<Expr>,
+    },
+    Nbsp,
+    RawElement(Expr),
     Expression(Expr),
 }
 
@@ -52,6 +93,7 @@ enum ListStyle {
     Bullet,
     Numbered,
     Custom(Ident),
+    None,
 }
 
 impl Parse for TgMessageItem {
@@ -76,6 +118,7 @@ impl Parse for TgMessageItem {
                         | "underline"
                         | "strikethrough"
                         | "spoiler"
+                        | "quote"
                         | "code"
                         | "pre"
                         | "link"
@@ -86,6 +129,7 @@ impl Parse for TgMessageItem {
                         | "date"
                         | "datetime"
                         | "time"
+                        | "nbsp"
                 ) || has_parens)
             {
                 match name.as_str() {
@@ -125,11 +169,30 @@ impl Parse for TgMessageItem {
                         let items = parse_message_items(&content)?;
                         Ok(TgMessageItem::Spoiler(items))
                     }
+                    "quote" => {
+                        let _: Ident = input.parse()?;
+                        let cite = if input.peek(token::Paren) {
+                            let args;
+                            syn::parenthesized!(args in input);
+                            let key: Ident = args.parse()?;
+                            if key != "cite" {
+                                return Err(syn::Error::new(key.span(), "expected `cite = ...`"));
+                            }
+                            args.parse::<Token![=]>()?;
+                            Some(args.parse()?)
+                        } else {
+                            None
+                        };
+                        let content;
+                        syn::braced!(content in input);
+                        let items = parse_message_items(&content)?;
+                        Ok(TgMessageItem::Quote { cite, items })
+                    }
                     "code" => {
                         let _: Ident = input.parse()?;
                         let content;
                         syn::braced!(content in input);
-                        let text: Lit = content.parse()?;
+                        let text = parse_block_literal(&content, "code")?;
                         Ok(TgMessageItem::Code(text))
                     }
                     "pre" => {
@@ -143,7 +206,7 @@ impl Parse for TgMessageItem {
                         };
                         let content;
                         syn::braced!(content in input);
-                        let code: Lit = content.parse()?;
+                        let code = parse_block_literal(&content, "pre")?;
                         Ok(TgMessageItem::Pre { code, lang })
                     }
                     "link" => {
@@ -159,9 +222,23 @@ impl Parse for TgMessageItem {
                     "mention" => {
                         let _: Ident = input.parse()?;
                         let content;
-                        syn::braced!(content in input);
-                        let username: Expr = content.parse()?;
-                        Ok(TgMessageItem::Mention(username))
+                        if input.peek(token::Paren) {
+                            syn::parenthesized!(content in input);
+                        } else {
+                            syn::braced!(content in input);
+                        }
+                        let first: Expr = content.parse()?;
+                        // Two arguments mean a text mention: user id and display name
+                        if content.peek(Token![,]) {
+                            content.parse::<Token![,]>()?;
+                            let text: Expr = content.parse()?;
+                            Ok(TgMessageItem::MentionId {
+                                user_id: first,
+                                text,
+                            })
+                        } else {
+                            Ok(TgMessageItem::Mention(first))
+                        }
                     }
                     "hashtag" => {
                         let _: Ident = input.parse()?;
@@ -179,6 +256,7 @@ impl Parse for TgMessageItem {
                             match style_ident.to_string().as_str() {
                                 "bullet" => ListStyle::Bullet,
                                 "numbered" => ListStyle::Numbered,
+                                "none" => ListStyle::None,
                                 _ => ListStyle::Custom(style_ident),
                             }
                         } else {
@@ -199,7 +277,7 @@ impl Parse for TgMessageItem {
                         let _: Token![:] = content.parse()?;
                         let headers_content;
                         syn::bracketed!(headers_content in content);
-                        let headers =
+                        let headers: Vec<Expr> =
                             Punctuated::<Expr, Token![,]>::parse_terminated(&headers_content)?
                                 .into_iter()
                                 .collect();
@@ -211,46 +289,141 @@ impl Parse for TgMessageItem {
                         let mut rows = Vec::new();
                         while !rows_content.is_empty() {
                             let row_content;
-                            syn::bracketed!(row_content in rows_content);
-                            let row =
+                            let bracket = syn::bracketed!(row_content in rows_content);
+                            let row: Vec<Expr> =
                                 Punctuated::<Expr, Token![,]>::parse_terminated(&row_content)?
                                     .into_iter()
                                     .collect();
+                            if row.len() != headers.len() {
+                                return Err(syn::Error::new(
+                                    bracket.span.join(),
+                                    format!(
+                                        "table row has {} but the table has {}",
+                                        plural(row.len(), "cell"),
+                                        plural(headers.len(), "header")
+                                    ),
+                                ));
+                            }
                             rows.push(row);
+                            if rows_content.peek(Token![,]) {
+                                rows_content.parse::<Token![,]>()?;
+                            }
                         }
 
                         Ok(TgMessageItem::Table { headers, rows })
                     }
-                    "date" => {
+                    "table_from" => {
                         let _: Ident = input.parse()?;
                         let content;
                         syn::parenthesized!(content in input);
-                        let value: Expr = content.parse()?;
-                        Ok(TgMessageItem::Date(value))
+                        let headers: Expr = content.parse()?;
+                        let _: Token![,] = content.parse()?;
+                        let rows: Expr = content.parse()?;
+                        if content.peek(Token![,]) {
+                            let _: Token![,] = content.parse()?;
+                        }
+                        Ok(TgMessageItem::TableFrom { headers, rows })
+                    }
+                    "date" => {
+                        let _: Ident = input.parse()?;
+                        let (value, format) = parse_temporal_args(input)?;
+                        Ok(TgMessageItem::Date { value, format })
                     }
                     "datetime" => {
+                        let _: Ident = input.parse()?;
+                        let (value, format) = parse_temporal_args(input)?;
+                        Ok(TgMessageItem::DateTime { value, format })
+                    }
+                    "datetime_tz" => {
+                        let _: Ident = input.parse()?;
+                        let (value, format) = parse_temporal_args(input)?;
+                        Ok(TgMessageItem::DateTimeTz { value, format })
+                    }
+                    "html_raw" => {
                         let _: Ident = input.parse()?;
                         let content;
                         syn::parenthesized!(content in input);
                         let value: Expr = content.parse()?;
-                        Ok(TgMessageItem::DateTime(value))
+                        Ok(TgMessageItem::HtmlRaw(value))
                     }
-                    "time" => {
+                    "image" => {
+                        let _: Ident = input.parse()?;
+                        let content;
+                        syn::parenthesized!(content in input);
+                        let url: Expr = content.parse()?;
+                        let content;
+                        syn::braced!(content in input);
+                        let alt: Expr = content.parse()?;
+                        Ok(TgMessageItem::Image { url, alt })
+                    }
+                    "phone_strict" => {
+                        let _: Ident = input.parse()?;
+                        let content;
+                        syn::parenthesized!(content in input);
+                        let number: Expr = content.parse()?;
+                        Ok(TgMessageItem::Phone {
+                            prefix: None,
+                            number,
+                            strict: true,
+                        })
+                    }
+                    "command" => {
+                        let _: Ident = input.parse()?;
+                        let content;
+                        syn::parenthesized!(content in input);
+                        let name: Expr = content.parse()?;
+                        let bot = if input.peek(Token![@]) {
+                            input.parse::<Token![@]>()?;
+                            if input.peek(Ident) {
+                                let bot: Ident = input.parse()?;
+                                let bot = bot.to_string();
+                                Some(syn::parse_quote!(#bot))
+                            } else {
+                                Some(parse_expression(input)?)
+                            }
+                        } else {
+                            None
+                        };
+                        Ok(TgMessageItem::Command { name, bot })
+                    }
+                    "tme" => {
+                        let _: Ident = input.parse()?;
+                        let content;
+                        syn::parenthesized!(content in input);
+                        let username: Expr = content.parse()?;
+                        let post_id = if content.peek(Token![,]) {
+                            content.parse::<Token![,]>()?;
+                            Some(content.parse()?)
+                        } else {
+                            None
+                        };
+                        Ok(TgMessageItem::TmeLink { username, post_id })
+                    }
+                    "nbsp" => {
+                        let _: Ident = input.parse()?;
+                        Ok(TgMessageItem::Nbsp)
+                    }
+                    "raw_el" => {
                         let _: Ident = input.parse()?;
                         let content;
                         syn::parenthesized!(content in input);
                         let value: Expr = content.parse()?;
-                        Ok(TgMessageItem::Time(value))
+                        Ok(TgMessageItem::RawElement(value))
+                    }
+                    "time" => {
+                        let _: Ident = input.parse()?;
+                        let (value, format) = parse_temporal_args(input)?;
+                        Ok(TgMessageItem::Time { value, format })
                     }
                     _ => {
                         // This is not a known keyword, parse as expression
-                        let expr: Expr = input.parse()?;
+                        let expr = parse_expression(input)?;
                         Ok(TgMessageItem::Expression(expr))
                     }
                 }
             } else {
                 // This is an expression (has . or :: after identifier)
-                let expr: Expr = input.parse()?;
+                let expr = parse_expression(input)?;
                 Ok(TgMessageItem::Expression(expr))
             }
         } else if input.peek(Token![@]) {
@@ -266,14 +439,14 @@ impl Parse for TgMessageItem {
                 let _ident: Ident = fork.parse()?;
                 // If next token suggests this is an expression (e.g., field access), parse as expression
                 if fork.peek(Token![.]) || fork.peek(Token![::]) || fork.peek(token::Paren) {
-                    let expr: Expr = input.parse()?;
+                    let expr = parse_expression(input)?;
                     Ok(TgMessageItem::MessageReference(expr))
                 } else {
                     // Simple identifier - could be either hashtag or message reference
                     // We'll treat simple identifiers as message references if they don't start with uppercase
                     let ident: Ident = input.parse()?;
                     let name = ident.to_string();
-                    if name.chars().next().map_or(false, |c| c.is_uppercase()) {
+                    if name.chars().next().is_some_and(|c| c.is_uppercase()) {
                         // Likely a hashtag
                         Ok(TgMessageItem::HashtagHash(ident))
                     } else {
@@ -283,9 +456,12 @@ impl Parse for TgMessageItem {
                 }
             } else {
                 // Not an identifier after #, parse as expression
-                let expr: Expr = input.parse()?;
+                let expr = parse_expression(input)?;
                 Ok(TgMessageItem::MessageReference(expr))
             }
+        } else if input.peek(Token![||]) {
+            let items = parse_spoiler_span(input)?;
+            Ok(TgMessageItem::Spoiler(items))
         } else if input.peek(Token![+]) {
             // Parse phone number format: +7(phone), +8(phone), +(phone)
             input.parse::<Token![+]>()?;
@@ -298,12 +474,16 @@ impl Parse for TgMessageItem {
             let content;
             syn::parenthesized!(content in input);
             let number: Expr = content.parse()?;
-            Ok(TgMessageItem::Phone { prefix, number })
+            Ok(TgMessageItem::Phone {
+                prefix,
+                number,
+                strict: false,
+            })
         } else if input.peek(Lit) {
             let lit: Lit = input.parse()?;
             Ok(TgMessageItem::Text(lit))
         } else {
-            let expr: Expr = input.parse()?;
+            let expr = parse_expression(input)?;
             Ok(TgMessageItem::Expression(expr))
         }
     }
@@ -317,6 +497,57 @@ fn parse_message_items(input: ParseStream) -> Result<Vec<TgMessageItem>> {
     Ok(items)
 }
 
+// A lone identifier or group right before `||` is an item of its own, not the
+// left-hand side of a logical or.
+fn parse_expression(input: ParseStream) -> Result<Expr> {
+    let fork = input.fork();
+    if fork.parse::<proc_macro2::TokenTree>().is_ok() && fork.peek(Token![||]) {
+        let tree: proc_macro2::TokenTree = input.parse()?;
+        return syn::parse2(tree.into_token_stream());
+    }
+    input.parse()
+}
+
+// Collect everything up to the closing `||` first, so expressions inside the
+// span can't swallow the delimiter as a logical-or operator.
+fn parse_spoiler_span(input: ParseStream) -> Result<Vec<TgMessageItem>> {
+    let open: Token![||] = input.parse()?;
+    let mut tokens = proc_macro2::TokenStream::new();
+    while !input.peek(Token![||]) {
+        if input.is_empty() {
+            return Err(syn::Error::new_spanned(open, "unclosed `||` spoiler"));
+        }
+        tokens.extend(std::iter::once(input.parse::<proc_macro2::TokenTree>()?));
+    }
+    input.parse::<Token![||]>()?;
+    parse_message_items.parse2(tokens)
+}
+
+fn parse_temporal_args(input: ParseStream) -> Result<(Expr, Option<LitStr>)> {
+    let content;
+    syn::parenthesized!(content in input);
+    let value: Expr = content.parse()?;
+    let format = if content.peek(Token![,]) {
+        content.parse::<Token![,]>()?;
+        Some(content.parse()?)
+    } else {
+        None
+    };
+    Ok((value, format))
+}
+
+fn parse_block_literal(input: ParseStream, block: &str) -> Result<Lit> {
+    let message = format!("{} blocks may only contain a string literal", block);
+    if !input.peek(Lit) {
+        return Err(input.error(message));
+    }
+    let lit: Lit = input.parse()?;
+    if !input.is_empty() {
+        return Err(input.error(message));
+    }
+    Ok(lit)
+}
+
 fn parse_list_items(input: ParseStream) -> Result<Vec<Vec<TgMessageItem>>> {
     let mut items = Vec::new();
     while !input.is_empty() {
@@ -336,93 +567,101 @@ fn parse_list_items(input: ParseStream) -> Result<Vec<Vec<TgMessageItem>>> {
 impl ToTokens for TgMessageItem {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let result = match self {
-            TgMessageItem::Text(lit) => {
-                let text = match lit {
-                    Lit::Str(s) => {
-                        let _value = s.value();
-                        quote! {
-                            {
-                                let text = #s;
-                                // URL regex pattern
-                                let url_regex = ::regex::Regex::new(r"https?://[^\s]+").unwrap();
-
-                                if url_regex.is_match(&text) {
-                                    let mut elements = Vec::new();
-                                    let mut last_end = 0;
-
-                                    for mat in url_regex.find_iter(&text) {
-                                        // Add text before URL if any
-                                        if mat.start() > last_end {
-                                            let before = &text[last_end..mat.start()];
-                                            if !before.is_empty() {
-                                                elements.push(::msg::Element::text(before));
-                                            }
-                                        }
+            TgMessageItem::Text(lit) => match lit {
+                Lit::Str(s) => {
+                    let _value = s.value();
+                    quote! {
+                        {
+                            let text = #s;
+                            // URL regex pattern
+                            let url_regex = ::regex::Regex::new(r"https?://[^\s]+").unwrap();
 
-                                        // Add URL as link
-                                        let url = mat.as_str();
-                                        elements.push(::msg::Element::TextLink {
-                                            text: url.to_string(),
-                                            url: url.to_string(),
-                                        });
+                            if url_regex.is_match(&text) {
+                                let mut elements = Vec::new();
+                                let mut last_end = 0;
 
-                                        last_end = mat.end();
+                                for mat in url_regex.find_iter(&text) {
+                                    // Add text before URL if any
+                                    if mat.start() > last_end {
+                                        let before = &text[last_end..mat.start()];
+                                        if !before.is_empty() {
+                                            elements.push(::msg::Element::text(before));
+                                        }
                                     }
 
-                                    // Add remaining text after last URL
-                                    if last_end < text.len() {
-                                        let after = &text[last_end..];
-                                        if !after.is_empty() {
-                                            elements.push(::msg::Element::text(after));
-                                        }
+                                    // Add URL as link
+                                    let url = mat.as_str();
+                                    elements.push(::msg::Element::TextLink {
+                                        text: url.to_string(),
+                                        url: url.to_string(),
+                                    });
+
+                                    last_end = mat.end();
+                                }
+
+                                // Add remaining text after last URL
+                                if last_end < text.len() {
+                                    let after = &text[last_end..];
+                                    if !after.is_empty() {
+                                        elements.push(::msg::Element::text(after));
                                     }
+                                }
 
-                                    if elements.len() == 1 {
-                                        elements.into_iter().next().unwrap()
-                                    } else {
-                                        ::msg::Element::Group(elements)
+                                if elements.len() == 1 {
+                                    elements.into_iter().next().unwrap()
+                                } else {
+                                    ::msg::Element::Group(elements)
+                                }
+                            } else if text.contains('\n') {
+                                let parts: Vec<&str> = text.split('\n').collect();
+                                let mut elements = Vec::new();
+                                for (i, part) in parts.iter().enumerate() {
+                                    // Blank lines come from the separators below, so
+                                    // only zero-length text is skipped here
+                                    if !part.is_empty() {
+                                        elements.push(::msg::Element::text(*part));
                                     }
-                                } else if text.contains('\n') {
-                                    let parts: Vec<&str> = text.split('\n').collect();
-                                    let mut elements = Vec::new();
-                                    for (i, part) in parts.iter().enumerate() {
-                                        if !part.is_empty() {
-                                            elements.push(::msg::Element::text(*part));
-                                        }
-                                        if i < parts.len() - 1 {
-                                            elements.push(::msg::Element::text("\n"));
-                                        }
+                                    if i < parts.len() - 1 {
+                                        elements.push(::msg::Element::text("\n"));
                                     }
-                                    ::msg::Element::Group(elements)
-                                } else {
-                                    ::msg::Element::text(text)
                                 }
+                                ::msg::Element::Group(elements)
+                            } else {
+                                ::msg::Element::text(text)
                             }
                         }
                     }
-                    _ => quote! { ::msg::Element::text(#lit.to_string()) },
-                };
-                text
-            }
+                }
+                _ => quote! { ::msg::Element::text(#lit.to_string()) },
+            },
             TgMessageItem::Bold(items) => {
                 let elements = generate_elements(items);
-                quote! { ::msg::Element::bold(vec![#(#elements),*]) }
+                quote! { ::msg::Element::bold(#elements) }
             }
             TgMessageItem::Italic(items) => {
                 let elements = generate_elements(items);
-                quote! { ::msg::Element::italic(vec![#(#elements),*]) }
+                quote! { ::msg::Element::italic(#elements) }
             }
             TgMessageItem::Underline(items) => {
                 let elements = generate_elements(items);
-                quote! { ::msg::Element::underline(vec![#(#elements),*]) }
+                quote! { ::msg::Element::underline(#elements) }
             }
             TgMessageItem::Strikethrough(items) => {
                 let elements = generate_elements(items);
-                quote! { ::msg_macro::Element::strikethrough(vec![#(#elements),*]) }
+                quote! { ::msg::Element::strikethrough(#elements) }
             }
             TgMessageItem::Spoiler(items) => {
                 let elements = generate_elements(items);
-                quote! { ::msg::Element::spoiler(vec![#(#elements),*]) }
+                quote! { ::msg::Element::spoiler(#elements) }
+            }
+            TgMessageItem::Quote { cite, items } => {
+                let elements = generate_elements(items);
+                match cite {
+                    Some(cite) => quote! {
+                        ::msg::Element::quote_with_cite(#elements, (#cite).to_string())
+                    },
+                    None => quote! { ::msg::Element::quote(#elements) },
+                }
             }
             TgMessageItem::Code(lit) => {
                 quote! { ::msg::Element::code(#lit) }
@@ -436,11 +675,14 @@ impl ToTokens for TgMessageItem {
             }
             TgMessageItem::Link { text, url } => {
                 let elements = generate_elements(text);
-                quote! { ::msg::Element::link(vec![#(#elements),*], #url) }
+                quote! { ::msg::Element::link(#elements, #url) }
             }
             TgMessageItem::Mention(username) => {
                 quote! { ::msg::Element::mention(#username) }
             }
+            TgMessageItem::MentionId { user_id, text } => {
+                quote! { ::msg::Element::mention_id(#user_id, (#text).to_string()) }
+            }
             TgMessageItem::MentionAt(username) => {
                 let username_str = username.to_string();
                 quote! { ::msg::Element::mention(#username_str) }
@@ -456,6 +698,7 @@ impl ToTokens for TgMessageItem {
                 let style_expr = match style {
                     ListStyle::Bullet => quote! { ::msg::ListStyle::Bullet },
                     ListStyle::Numbered => quote! { ::msg::ListStyle::Numbered },
+                    ListStyle::None => quote! { ::msg::ListStyle::None },
                     ListStyle::Custom(ident) => {
                         quote! { ::msg::ListStyle::Custom(#ident.to_string()) }
                     }
@@ -465,7 +708,7 @@ impl ToTokens for TgMessageItem {
                     let elements = generate_elements(item);
                     quote! {
                         ::msg::ListItem {
-                            content: vec![#(#elements),*],
+                            content: #elements,
                             nested: None,
                         }
                     }
@@ -483,7 +726,8 @@ impl ToTokens for TgMessageItem {
                     quote! {
                         ::msg::TableCell {
                             content: vec![::msg::Element::text(#h.to_string())],
-                            align: ::msg::CellAlign::Left,
+                            align: ::msg::CellAlign::Inherit,
+                            style: ::msg::CellStyle::Normal,
                             colspan: 1,
                             rowspan: 1,
                         }
@@ -495,7 +739,8 @@ impl ToTokens for TgMessageItem {
                         quote! {
                             ::msg::TableCell {
                                 content: vec![::msg::Element::text(#cell.to_string())],
-                                align: ::msg::CellAlign::Left,
+                                align: ::msg::CellAlign::Inherit,
+                                style: ::msg::CellStyle::Normal,
                                 colspan: 1,
                                 rowspan: 1,
                             }
@@ -514,114 +759,91 @@ impl ToTokens for TgMessageItem {
                         rows: vec![#(#table_rows),*],
                         style: ::msg::TableStyle::Unicode,
                         rules: Vec::new(),
+                        default_align: ::msg::CellAlign::Left,
                     })
                 }
             }
-            TgMessageItem::Phone { prefix, number } => {
+            TgMessageItem::TableFrom { headers, rows } => {
+                quote! {
+                    {
+                        let mut header_cells = Vec::new();
+                        for header in #headers {
+                            header_cells.push(::msg::TableCell {
+                                content: vec![::msg::Element::text(header.to_string())],
+                                ..::msg::TableCell::default()
+                            });
+                        }
+
+                        let mut table_rows = Vec::new();
+                        for row in #rows {
+                            let mut cells = Vec::new();
+                            for cell in row {
+                                cells.push(::msg::TableCell {
+                                    content: vec![::msg::Element::text(cell.to_string())],
+                                    ..::msg::TableCell::default()
+                                });
+                            }
+                            table_rows.push(::msg::TableRow { cells });
+                        }
+
+                        ::msg::Element::Table(::msg::TableNode {
+                            headers: header_cells,
+                            rows: table_rows,
+                            style: ::msg::TableStyle::Unicode,
+                            rules: Vec::new(),
+                            default_align: ::msg::CellAlign::Left,
+                        })
+                    }
+                }
+            }
+            TgMessageItem::Phone {
+                prefix,
+                number,
+                strict,
+            } => {
                 let prefix_expr = match prefix {
                     Some(p) => quote! { Some(#p.to_string()) },
                     None => quote! { None::<String> },
                 };
+                // Strict phones fail loudly instead of rendering the "-" placeholder
+                let invalid = if *strict {
+                    quote! { panic!("phone_strict: invalid phone number {:?}", phone_str) }
+                } else {
+                    quote! { ::msg::Element::text("-") }
+                };
                 quote! {
                     {
                         let phone_str = #number.to_string();
-                        
-                        // Handle empty string
-                        if phone_str.is_empty() {
-                            ::msg::Element::Text("-".to_string())
-                        } else {
-                            // Remove non-digit characters
-                            let digits: String = phone_str.chars().filter(|c| c.is_digit(10)).collect();
-                            
-                            // Return "-" if no digits
-                            if digits.is_empty() {
-                                ::msg::Element::Text("-".to_string())
-                            } else {
-                                // Determine the actual prefix and format accordingly
-                                let (final_prefix, phone_digits, tel_prefix) = match #prefix_expr {
-                                    Some(prefix) => {
-                                        // If prefix is provided explicitly (e.g., +7), use it
-                                        (prefix.clone(), digits.clone(), format!("{}{}", prefix.replace("+", ""), digits))
-                                    }
-                                    None => {
-                                        // If +(phone) format, check if number starts with 7 or 8
-                                        if digits.len() == 11 && digits.starts_with("7") {
-                                            // Russian number format with 7: extract country code
-                                            ("+7".to_string(), digits[1..].to_string(), format!("7{}", &digits[1..]))
-                                        } else if digits.len() == 11 && digits.starts_with("8") {
-                                            // Russian number format with 8: convert to +7
-                                            ("+7".to_string(), digits[1..].to_string(), format!("7{}", &digits[1..]))
-                                        } else if digits.len() == 10 {
-                                            // Assume it's a local number without country code, default to +7
-                                            ("+7".to_string(), digits.clone(), format!("7{}", digits))
-                                        } else {
-                                            // Other format, use as is with +
-                                            ("+".to_string(), digits.clone(), digits.clone())
-                                        }
-                                    }
-                                };
-
-                                // Format the phone number if we have enough digits
-                                let formatted = if phone_digits.len() == 10 {
-                                    // Format as (XXX) XXX-XX-XX for 10-digit numbers
-                                    let area = &phone_digits[0..3];
-                                    let prefix_part = &phone_digits[3..6];
-                                    let part1 = &phone_digits[6..8];
-                                    let part2 = &phone_digits[8..10];
-                                    // Check if prefix was explicitly provided (e.g., +7(phone))
-                                    let space_after_prefix = if #prefix_expr.is_some() { " " } else { "" };
-                                    format!("{}{}({}) {}-{}-{}", final_prefix, space_after_prefix, area, prefix_part, part1, part2)
-                                } else if phone_digits.len() >= 7 {
-                                    // Format with dashes for other lengths >= 7
-                                    let area_len = 3.min(phone_digits.len());
-                                    let area = &phone_digits[0..area_len];
-                                    let rest = &phone_digits[area_len..];
-                                    
-                                    // Split rest into chunks with dashes
-                                    let mut formatted_rest = String::new();
-                                    let mut chars = rest.chars();
-                                    
-                                    // First chunk of 3 digits if available
-                                    if rest.len() >= 3 {
-                                        for _ in 0..3 {
-                                            if let Some(c) = chars.next() {
-                                                formatted_rest.push(c);
-                                            }
-                                        }
-                                        // Add remaining digits with dashes every 2 digits
-                                        let remaining: String = chars.collect();
-                                        if !remaining.is_empty() {
-                                            formatted_rest.push('-');
-                                            for (i, c) in remaining.chars().enumerate() {
-                                                if i > 0 && i % 2 == 0 {
-                                                    formatted_rest.push('-');
-                                                }
-                                                formatted_rest.push(c);
-                                            }
-                                        }
-                                    } else {
-                                        formatted_rest = rest.to_string();
-                                    }
-                                    
-                                    format!("{}({}) {}", final_prefix, area, formatted_rest)
-                                } else {
-                                    // Short number, return without formatting
-                                    format!("{}{}", final_prefix, phone_digits)
-                                };
-
-                                // Create tel: URL with proper prefix
-                                let tel_url = format!("tel:+{}", tel_prefix);
-                                
-                                ::msg::Element::TextLink {
-                                    text: formatted,
-                                    url: tel_url,
-                                }
-                            }
+                        let prefix: Option<String> = #prefix_expr;
+                        match ::msg::formatter::format_phone(&phone_str, prefix.as_deref()) {
+                            Some((text, url)) => ::msg::Element::TextLink { text, url },
+                            None => #invalid,
                         }
                     }
                 }
             }
-            TgMessageItem::Date(value) => {
+            TgMessageItem::Date {
+                value,
+                format: Some(format),
+            }
+            | TgMessageItem::DateTime {
+                value,
+                format: Some(format),
+            }
+            | TgMessageItem::Time {
+                value,
+                format: Some(format),
+            }
+            | TgMessageItem::DateTimeTz {
+                value,
+                format: Some(format),
+            } => {
+                quote! { ::msg::Element::text((#value).format(#format).to_string()) }
+            }
+            TgMessageItem::Date {
+                value,
+                format: None,
+            } => {
                 quote! {
                     {
                         use ::chrono::Datelike;
@@ -632,7 +854,10 @@ impl ToTokens for TgMessageItem {
                     }
                 }
             }
-            TgMessageItem::DateTime(value) => {
+            TgMessageItem::DateTime {
+                value,
+                format: None,
+            } => {
                 quote! {
                     {
                         use ::chrono::{Datelike, Timelike};
@@ -646,7 +871,10 @@ impl ToTokens for TgMessageItem {
                     }
                 }
             }
-            TgMessageItem::Time(value) => {
+            TgMessageItem::Time {
+                value,
+                format: None,
+            } => {
                 quote! {
                     {
                         use ::chrono::Timelike;
@@ -657,6 +885,48 @@ impl ToTokens for TgMessageItem {
                     }
                 }
             }
+            TgMessageItem::DateTimeTz {
+                value,
+                format: None,
+            } => {
+                quote! {
+                    ::msg::Element::text((#value).format("%Y-%m-%d %H:%M:%S %:z").to_string())
+                }
+            }
+            TgMessageItem::TmeLink { username, post_id } => {
+                let path = match post_id {
+                    Some(post_id) => quote! { format!("{}/{}", __tme_username, #post_id) },
+                    None => quote! { __tme_username.to_string() },
+                };
+                let text = match post_id {
+                    Some(_) => quote! { format!("t.me/{}", __tme_path) },
+                    None => quote! { format!("@{}", __tme_path) },
+                };
+                quote! {
+                    {
+                        let __tme_username = (#username).to_string();
+                        let __tme_username = __tme_username.trim_start_matches('@');
+                        let __tme_path = #path;
+                        ::msg::Element::text_link(#text, format!("https://t.me/{}", __tme_path))
+                    }
+                }
+            }
+            TgMessageItem::Command { name, bot } => match bot {
+                Some(bot) => quote! {
+                    ::msg::Element::command_for_bot((#name).to_string(), (#bot).to_string())
+                },
+                None => quote! { ::msg::Element::command((#name).to_string()) },
+            },
+            TgMessageItem::HtmlRaw(value) => {
+                quote! { ::msg::Element::raw_html((#value).to_string()) }
+            }
+            TgMessageItem::Image { url, alt } => {
+                quote! { ::msg::Element::image(#url, (#alt).to_string()) }
+            }
+            TgMessageItem::Nbsp => quote! { ::msg::Element::nbsp() },
+            TgMessageItem::RawElement(value) => {
+                quote! { { let __raw_el: ::msg::Element = #value; __raw_el } }
+            }
             TgMessageItem::Expression(expr) => {
                 quote! { ::msg::Element::text(#expr.to_string()) }
             }
@@ -671,19 +941,28 @@ impl ToTokens for TgMessageItem {
     }
 }
 
-fn generate_elements(items: &[TgMessageItem]) -> Vec<proc_macro2::TokenStream> {
-    items
-        .iter()
-        .map(|item| {
-            match item {
-                TgMessageItem::MessageReference(_) => {
-                    // For message references, we want to expand the vector inline
-                    quote! { #item }
-                }
-                _ => quote! { #item },
+// Builds a `Vec<Element>`, splicing in referenced messages and the groups that
+// string literals expand to, so containers never hold a nested `Group`.
+fn generate_elements(items: &[TgMessageItem]) -> proc_macro2::TokenStream {
+    let pushes = items.iter().map(|item| match item {
+        TgMessageItem::MessageReference(expr) => {
+            quote! { __msg_elements.extend(#expr); }
+        }
+        _ => quote! {
+            match #item {
+                ::msg::Element::Group(mut group) => __msg_elements.append(&mut group),
+                other => __msg_elements.push(other),
             }
-        })
-        .collect()
+        },
+    });
+
+    quote! {
+        {
+            let mut __msg_elements: Vec<::msg::Element> = Vec::new();
+            #(#pushes)*
+            __msg_elements
+        }
+    }
 }
 
 struct TgMessage {
@@ -701,43 +980,7 @@ impl Parse for TgMessage {
 pub fn msg(input: TokenStream) -> TokenStream {
     let message = parse_macro_input!(input as TgMessage);
 
-    let elements = message.items.iter().map(|item| {
-        match item {
-            TgMessageItem::MessageReference(expr) => {
-                // For message references, we directly extend from the vector
-                quote! {
-                    {
-                        let referenced_items = #expr;
-                        for item in referenced_items {
-                            result.push(item);
-                        }
-                    }
-                }
-            }
-            _ => {
-                // For normal items, convert to Element and push
-                quote! {
-                    {
-                        let element = #item;
-                        match element {
-                            ::msg::Element::Group(mut elements) => result.append(&mut elements),
-                            other => result.push(other),
-                        }
-                    }
-                }
-            }
-        }
-    });
-
-    let output = quote! {
-        {
-            let mut result = Vec::new();
-            #(#elements)*
-            result
-        }
-    };
-
-    output.into()
+    generate_elements(&message.items).into()
 }
 
 #[proc_macro]
@@ -746,3 +989,78 @@ pub fn el(input: TokenStream) -> TokenStream {
     let output = quote! { #item };
     output.into()
 }
+
+#[proc_macro]
+pub fn el_one(input: TokenStream) -> TokenStream {
+    match expand_el_one(input.into()) {
+        Ok(output) => output.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_el_one(input: proc_macro2::TokenStream) -> Result<proc_macro2::TokenStream> {
+    let item: TgMessageItem = syn::parse2(input.clone())?;
+    let multiple = match &item {
+        TgMessageItem::Text(Lit::Str(s)) => literal_element_count(&s.value()) > 1,
+        TgMessageItem::MessageReference(_) => true,
+        _ => false,
+    };
+    if multiple {
+        return Err(syn::Error::new_spanned(
+            input,
+            "el_one! content expands to more than one element",
+        ));
+    }
+
+    Ok(quote! {
+        match #item {
+            ::msg::Element::Group(mut elements) if elements.len() == 1 => elements.remove(0),
+            other => other,
+        }
+    })
+}
+
+// Mirrors the runtime splitting of string literals into links and newlines.
+fn literal_element_count(text: &str) -> usize {
+    let mut count = 0;
+    let mut rest = text;
+    let mut has_url = false;
+    while let Some((start, end)) = find_url(rest) {
+        has_url = true;
+        if start > 0 {
+            count += 1;
+        }
+        count += 1;
+        rest = &rest[end..];
+    }
+    if has_url {
+        return count + usize::from(!rest.is_empty());
+    }
+
+    let parts: Vec<&str> = text.split('\n').collect();
+    parts.iter().filter(|part| !part.is_empty()).count() + parts.len() - 1
+}
+
+fn find_url(text: &str) -> Option<(usize, usize)> {
+    text.char_indices().find_map(|(start, _)| {
+        let scheme = ["https://", "http://"]
+            .into_iter()
+            .find(|scheme| text[start..].starts_with(scheme))?;
+        let body = start + scheme.len();
+        let end = text[body..]
+            .find(char::is_whitespace)
+            .map_or(text.len(), |i| body + i);
+        (end > body).then_some((start, end))
+    })
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,128 @@
+#[cfg(test)]
+mod parse_tests {
+    use crate::TgMessageItem;
+    use quote::quote;
+    use syn::parse::Parser;
+
+    #[test]
+    fn test_code_rejects_nested_formatting() {
+        let err = syn::parse2::<TgMessageItem>(quote! { code { bold { "x" } } }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "code blocks may only contain a string literal"
+        );
+    }
+
+    #[test]
+    fn test_code_rejects_trailing_tokens() {
+        let err = syn::parse2::<TgMessageItem>(quote! { code { "a" "b" } }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "code blocks may only contain a string literal"
+        );
+    }
+
+    #[test]
+    fn test_pre_rejects_nested_formatting() {
+        let err =
+            syn::parse2::<TgMessageItem>(quote! { pre("rust") { spoiler { "x" } } }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "pre blocks may only contain a string literal"
+        );
+    }
+
+    #[test]
+    fn test_code_accepts_literal() {
+        let item = syn::parse2::<TgMessageItem>(quote! { code { "let x = 1;" } }).unwrap();
+        assert!(matches!(item, TgMessageItem::Code(_)));
+    }
+
+    #[test]
+    fn test_inline_spoiler() {
+        let item = syn::parse2::<TgMessageItem>(quote! { || "secret" || }).unwrap();
+        match item {
+            TgMessageItem::Spoiler(items) => {
+                assert_eq!(items.len(), 1);
+                assert!(matches!(items[0], TgMessageItem::Text(_)));
+            }
+            other => panic!("expected spoiler, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_inline_spoiler_keeps_expression_inside() {
+        let items = crate::parse_message_items
+            .parse2(quote! { || value || "after" })
+            .unwrap();
+        assert_eq!(items.len(), 2);
+        assert!(matches!(&items[0], TgMessageItem::Spoiler(inner) if inner.len() == 1));
+        assert!(matches!(items[1], TgMessageItem::Text(_)));
+    }
+
+    #[test]
+    fn test_inline_spoiler_unclosed() {
+        let err = syn::parse2::<TgMessageItem>(quote! { || "secret" }).unwrap_err();
+        assert_eq!(err.to_string(), "unclosed `||` spoiler");
+    }
+
+    #[test]
+    fn test_inline_spoiler_after_expression() {
+        let items = crate::parse_message_items
+            .parse2(quote! { answer || "hidden" || (total) || "more" || })
+            .unwrap();
+        assert_eq!(items.len(), 4);
+        assert!(matches!(items[0], TgMessageItem::Expression(_)));
+        assert!(matches!(items[1], TgMessageItem::Spoiler(_)));
+        assert!(matches!(items[2], TgMessageItem::Expression(_)));
+        assert!(matches!(items[3], TgMessageItem::Spoiler(_)));
+    }
+
+    #[test]
+    fn test_el_one_accepts_single_element() {
+        assert!(crate::expand_el_one(quote! { "plain text" }).is_ok());
+        assert!(crate::expand_el_one(quote! { "https://example.com" }).is_ok());
+        assert!(crate::expand_el_one(quote! { bold { "a" "b" } }).is_ok());
+    }
+
+    #[test]
+    fn test_el_one_rejects_multiple_elements() {
+        let err = crate::expand_el_one(quote! { "see https://example.com now" }).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "el_one! content expands to more than one element"
+        );
+        assert!(crate::expand_el_one(quote! { "a\nb" }).is_err());
+        assert!(crate::expand_el_one("#messages".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_table_rejects_ragged_rows() {
+        let err = syn::parse2::<TgMessageItem>(quote! {
+            table { headers: ["a", "b"] rows: [[1, 2], [3]] }
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "table row has 1 cell but the table has 2 headers"
+        );
+
+        let err = syn::parse2::<TgMessageItem>(quote! {
+            table { headers: ["a"] rows: [[1, 2, 3]] }
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "table row has 3 cells but the table has 1 header"
+        );
+    }
+
+    #[test]
+    fn test_table_accepts_rectangular_rows() {
+        let item = syn::parse2::<TgMessageItem>(quote! {
+            table { headers: ["a", "b"] rows: [[1, 2], [3, 4]] }
+        })
+        .unwrap();
+        assert!(matches!(item, TgMessageItem::Table { .. }));
+    }
+}
@@ -1,6 +1,14 @@
-#[derive(Debug, Clone, PartialEq)]
+use crate::error::{Error, Result};
+use std::hash::{Hash, Hasher};
+
+#[cfg(feature = "intern")]
+pub type TextStr = std::sync::Arc<str>;
+#[cfg(not(feature = "intern"))]
+pub type TextStr = String;
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum Element {
-    Text(String),
+    Text(TextStr),
     Bold(Vec<Element>),
     Italic(Vec<Element>),
     Code(String),
@@ -9,85 +17,140 @@ pub enum Element {
     Strikethrough(Vec<Element>),
     Spoiler(Vec<Element>),
 
-    Link { text: Vec<Element>, url: String },
-    TextLink { text: String, url: String },
-
-    Mention { username: String },
-    MentionId { user_id: u64, text: String },
+    Link {
+        text: Vec<Element>,
+        url: String,
+        title: Option<String>,
+    },
+    TextLink {
+        text: String,
+        url: String,
+    },
+
+    Mention {
+        username: String,
+    },
+    MentionId {
+        user_id: u64,
+        text: String,
+    },
 
     Hashtag(String),
-    Command { name: String, args: Vec<String> },
+    Command {
+        name: String,
+        args: Vec<String>,
+        bot: Option<String>,
+    },
     Emoji(String),
-    CustomEmoji { emoji: String, id: u64 },
+    CustomEmoji {
+        emoji: String,
+        id: u64,
+    },
 
     List(ListNode),
     Table(TableNode),
-    Quote(Vec<Element>),
-
-    Custom { formatter: String, value: String },
+    Quote {
+        content: Vec<Element>,
+        cite: Option<String>,
+    },
+
+    Custom {
+        formatter: String,
+        value: String,
+    },
+
+    RawHtml(String),
+    Preformatted {
+        markdown_v2: String,
+        html: String,
+    },
+
+    Image {
+        url: String,
+        alt: String,
+    },
+
+    Footnote {
+        id: String,
+        content: Vec<Element>,
+    },
 
     Group(Vec<Element>),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct PreBlock {
     pub code: String,
     pub language: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct ListNode {
     pub style: ListStyle,
     pub items: Vec<ListItem>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum ListStyle {
     Bullet,
     Numbered,
     Custom(String),
+    /// Items on their own lines without any marker.
+    None,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct ListItem {
     pub content: Vec<Element>,
     pub nested: Option<Box<ListNode>>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct TableNode {
     pub headers: Vec<TableCell>,
     pub rows: Vec<TableRow>,
     pub style: TableStyle,
     pub rules: Vec<ConditionalFormat>,
+    pub default_align: CellAlign,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct TableRow {
     pub cells: Vec<TableCell>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub struct TableCell {
     pub content: Vec<Element>,
     pub align: CellAlign,
+    pub style: CellStyle,
     pub colspan: usize,
     pub rowspan: usize,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum CellAlign {
     Left,
     Center,
     Right,
+    /// Uses the table's `default_align`
+    Inherit,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum CellStyle {
+    Normal,
+    Bold,
+    Italic,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
 pub enum TableStyle {
     Ascii,
     Unicode,
     Minimal,
     Compact,
+    Html,
 }
 
 #[derive(Debug, Clone)]
@@ -102,6 +165,13 @@ impl PartialEq for ConditionalFormat {
     }
 }
 
+impl Hash for ConditionalFormat {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.condition.hash(state);
+        self.format.hash(state);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Condition {
     GreaterThan(f64),
@@ -110,24 +180,131 @@ pub enum Condition {
     Contains(String),
     Regex(String),
     Custom(String),
+    /// Empty or whitespace only.
+    Empty,
+    NonEmpty,
+    /// More than this many characters, counted as grapheme clusters.
+    LengthGreaterThan(usize),
+}
+
+impl Hash for Condition {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            // 0.0 and -0.0 compare equal, so they must hash alike
+            Condition::GreaterThan(value) | Condition::LessThan(value) => {
+                (value + 0.0).to_bits().hash(state)
+            }
+            Condition::Equals(text)
+            | Condition::Contains(text)
+            | Condition::Regex(text)
+            | Condition::Custom(text) => text.hash(state),
+            Condition::Empty | Condition::NonEmpty => {}
+            Condition::LengthGreaterThan(len) => len.hash(state),
+        }
+    }
 }
 
 impl Default for TableCell {
     fn default() -> Self {
         Self {
             content: Vec::new(),
-            align: CellAlign::Left,
+            align: CellAlign::Inherit,
+            style: CellStyle::Normal,
             colspan: 1,
             rowspan: 1,
         }
     }
 }
 
+impl TableNode {
+    /// Appends a bold row with `label` in the first cell and the sum of each
+    /// of `columns` below it. Cells that don't parse as numbers are skipped.
+    pub fn with_totals(mut self, columns: &[usize], label: &str) -> Self {
+        let width = self
+            .rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain(std::iter::once(self.headers.len()))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        let mut cells = vec![
+            TableCell {
+                style: CellStyle::Bold,
+                ..TableCell::default()
+            };
+            width
+        ];
+        cells[0].content = vec![Element::text(label)];
+
+        for &column in columns.iter().filter(|&&c| c > 0 && c < width) {
+            let mut sum = 0.0;
+            let mut decimals = 0;
+            for row in &self.rows {
+                let Some(cell) = row.cells.get(column) else {
+                    continue;
+                };
+                let text = text_iter(&cell.content).collect::<String>();
+                let text = text.trim();
+                if let Some(value) = text.parse::<f64>().ok().filter(|v| v.is_finite()) {
+                    sum += value;
+                    decimals = decimals.max(text.split_once('.').map_or(0, |(_, f)| f.len()));
+                }
+            }
+            cells[column].content = vec![Element::text(format!("{:.*}", decimals, sum))];
+        }
+
+        self.rows.push(TableRow { cells });
+        self
+    }
+
+    /// Swaps rows and columns, so the headers become the first column.
+    /// Every cell is aligned left and short rows are padded with empty cells.
+    pub fn transpose(&self) -> Result<TableNode> {
+        let grid: Vec<&[TableCell]> = std::iter::once(self.headers.as_slice())
+            .chain(self.rows.iter().map(|row| row.cells.as_slice()))
+            .collect();
+        if grid
+            .iter()
+            .flat_map(|cells| cells.iter())
+            .any(|cell| cell.colspan > 1 || cell.rowspan > 1)
+        {
+            return Err(Error::InvalidTable(
+                "cannot transpose a table with colspan or rowspan greater than 1".to_string(),
+            ));
+        }
+
+        let columns = grid.iter().map(|cells| cells.len()).max().unwrap_or(0);
+        let mut transposed = (0..columns).map(|column| {
+            grid.iter()
+                .map(|cells| TableCell {
+                    align: CellAlign::Left,
+                    ..cells.get(column).cloned().unwrap_or_default()
+                })
+                .collect::<Vec<_>>()
+        });
+
+        Ok(TableNode {
+            headers: transposed.next().unwrap_or_default(),
+            rows: transposed.map(|cells| TableRow { cells }).collect(),
+            style: self.style.clone(),
+            rules: self.rules.clone(),
+            default_align: CellAlign::Left,
+        })
+    }
+}
+
 impl Element {
-    pub fn text(s: impl Into<String>) -> Self {
+    pub fn text(s: impl Into<TextStr>) -> Self {
         Element::Text(s.into())
     }
 
+    pub fn nbsp() -> Self {
+        Element::text("\u{00A0}")
+    }
+
     pub fn bold(elements: Vec<Element>) -> Self {
         Element::Bold(elements)
     }
@@ -151,6 +328,7 @@ impl Element {
         Element::Link {
             text,
             url: url.into(),
+            title: None,
         }
     }
 
@@ -167,10 +345,39 @@ impl Element {
         }
     }
 
+    pub fn try_mention(username: impl Into<String>) -> Result<Self> {
+        let username = username.into();
+        check_username(&username)?;
+        Ok(Element::Mention { username })
+    }
+
+    pub fn mention_id(user_id: u64, text: impl Into<String>) -> Self {
+        Element::MentionId {
+            user_id,
+            text: text.into(),
+        }
+    }
+
     pub fn hashtag(tag: impl Into<String>) -> Self {
         Element::Hashtag(tag.into())
     }
 
+    pub fn command(name: impl Into<String>) -> Self {
+        Element::Command {
+            name: name.into(),
+            args: Vec::new(),
+            bot: None,
+        }
+    }
+
+    pub fn command_for_bot(name: impl Into<String>, bot: impl Into<String>) -> Self {
+        Element::Command {
+            name: name.into(),
+            args: Vec::new(),
+            bot: Some(bot.into()),
+        }
+    }
+
     pub fn group(elements: Vec<Element>) -> Self {
         Element::Group(elements)
     }
@@ -186,4 +393,454 @@ impl Element {
     pub fn spoiler(elements: Vec<Element>) -> Self {
         Element::Spoiler(elements)
     }
+
+    pub fn quote(elements: Vec<Element>) -> Self {
+        Element::Quote {
+            content: elements,
+            cite: None,
+        }
+    }
+
+    pub fn quote_with_cite(elements: Vec<Element>, cite: impl Into<String>) -> Self {
+        Element::Quote {
+            content: elements,
+            cite: Some(cite.into()),
+        }
+    }
+
+    pub fn raw_html(html: impl Into<String>) -> Self {
+        Element::RawHtml(html.into())
+    }
+
+    pub fn preformatted(markdown_v2: impl Into<String>, html: impl Into<String>) -> Self {
+        Element::Preformatted {
+            markdown_v2: markdown_v2.into(),
+            html: html.into(),
+        }
+    }
+
+    pub fn image(url: impl Into<String>, alt: impl Into<String>) -> Self {
+        Element::Image {
+            url: url.into(),
+            alt: alt.into(),
+        }
+    }
+
+    pub fn footnote(id: impl Into<String>, content: Vec<Element>) -> Self {
+        Element::Footnote {
+            id: id.into(),
+            content,
+        }
+    }
+
+    pub fn from_plain(input: &str) -> Result<Vec<Element>> {
+        crate::parser::parse(input)
+    }
+
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            Element::Text(_) => "Text",
+            Element::Bold(_) => "Bold",
+            Element::Italic(_) => "Italic",
+            Element::Code(_) => "Code",
+            Element::Pre(_) => "Pre",
+            Element::Underline(_) => "Underline",
+            Element::Strikethrough(_) => "Strikethrough",
+            Element::Spoiler(_) => "Spoiler",
+            Element::Link { .. } => "Link",
+            Element::TextLink { .. } => "TextLink",
+            Element::Mention { .. } => "Mention",
+            Element::MentionId { .. } => "MentionId",
+            Element::Hashtag(_) => "Hashtag",
+            Element::Command { .. } => "Command",
+            Element::Emoji(_) => "Emoji",
+            Element::CustomEmoji { .. } => "CustomEmoji",
+            Element::List(_) => "List",
+            Element::Table(_) => "Table",
+            Element::Quote { .. } => "Quote",
+            Element::Custom { .. } => "Custom",
+            Element::RawHtml(_) => "RawHtml",
+            Element::Preformatted { .. } => "Preformatted",
+            Element::Image { .. } => "Image",
+            Element::Footnote { .. } => "Footnote",
+            Element::Group(_) => "Group",
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Element::Text(text) => text.is_empty(),
+            Element::Bold(elements)
+            | Element::Italic(elements)
+            | Element::Underline(elements)
+            | Element::Strikethrough(elements)
+            | Element::Spoiler(elements)
+            | Element::Quote {
+                content: elements,
+                cite: None,
+            }
+            | Element::Group(elements) => elements.iter().all(Element::is_empty),
+            _ => false,
+        }
+    }
+
+    pub fn walk_mut(&mut self, f: &mut impl FnMut(&mut Element)) {
+        f(self);
+        match self {
+            Element::Bold(elements)
+            | Element::Italic(elements)
+            | Element::Underline(elements)
+            | Element::Strikethrough(elements)
+            | Element::Spoiler(elements)
+            | Element::Quote {
+                content: elements, ..
+            }
+            | Element::Group(elements)
+            | Element::Link { text: elements, .. }
+            | Element::Footnote {
+                content: elements, ..
+            } => elements.iter_mut().for_each(|e| e.walk_mut(f)),
+
+            Element::List(list) => list.walk_mut(f),
+
+            Element::Table(table) => table
+                .headers
+                .iter_mut()
+                .chain(table.rows.iter_mut().flat_map(|row| row.cells.iter_mut()))
+                .flat_map(|cell| cell.content.iter_mut())
+                .for_each(|e| e.walk_mut(f)),
+
+            _ => {}
+        }
+    }
+
+    pub fn text_iter(&self) -> impl Iterator<Item = &str> {
+        let mut texts = Vec::new();
+        self.collect_text(&mut texts);
+        texts.into_iter()
+    }
+
+    fn collect_text<'a>(&'a self, texts: &mut Vec<&'a str>) {
+        match self {
+            Element::Text(text) => texts.push(text),
+            Element::Code(text) | Element::Hashtag(text) => texts.push(text),
+            Element::Pre(block) => texts.push(&block.code),
+            Element::TextLink { text, .. } | Element::MentionId { text, .. } => texts.push(text),
+            Element::Mention { username } => texts.push(username),
+            Element::Image { alt, .. } => texts.push(alt),
+
+            Element::Bold(elements)
+            | Element::Italic(elements)
+            | Element::Underline(elements)
+            | Element::Strikethrough(elements)
+            | Element::Spoiler(elements)
+            | Element::Quote {
+                content: elements, ..
+            }
+            | Element::Group(elements)
+            | Element::Link { text: elements, .. }
+            | Element::Footnote {
+                content: elements, ..
+            } => elements.iter().for_each(|e| e.collect_text(texts)),
+
+            Element::List(list) => list.collect_text(texts),
+
+            Element::Table(table) => table
+                .headers
+                .iter()
+                .chain(table.rows.iter().flat_map(|row| row.cells.iter()))
+                .flat_map(|cell| cell.content.iter())
+                .for_each(|e| e.collect_text(texts)),
+
+            Element::Command { .. }
+            | Element::Emoji(_)
+            | Element::CustomEmoji { .. }
+            | Element::Custom { .. }
+            | Element::RawHtml(_)
+            | Element::Preformatted { .. } => {}
+        }
+    }
+}
+
+impl ListNode {
+    fn walk_mut(&mut self, f: &mut impl FnMut(&mut Element)) {
+        for item in &mut self.items {
+            item.content.iter_mut().for_each(|e| e.walk_mut(f));
+            if let Some(nested) = &mut item.nested {
+                nested.walk_mut(f);
+            }
+        }
+    }
+
+    fn collect_text<'a>(&'a self, texts: &mut Vec<&'a str>) {
+        for item in &self.items {
+            item.content.iter().for_each(|e| e.collect_text(texts));
+            if let Some(nested) = &item.nested {
+                nested.collect_text(texts);
+            }
+        }
+    }
+}
+
+pub(crate) fn check_username(username: &str) -> Result<()> {
+    let valid = (5..=32).contains(&username.len())
+        && username.starts_with(|c: char| c.is_ascii_alphabetic())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidUsername(username.to_string()))
+    }
+}
+
+pub fn text_iter(elements: &[Element]) -> impl Iterator<Item = &str> {
+    elements.iter().flat_map(|e| e.text_iter())
+}
+
+/// Renders elements as an indented tree, one node per line, for debugging.
+pub fn debug_tree(elements: &[Element]) -> String {
+    let mut out = String::new();
+    elements.iter().for_each(|e| tree_node(&mut out, e, 0));
+    out
+}
+
+fn tree_node(out: &mut String, element: &Element, depth: usize) {
+    let name = element.variant_name();
+    let label = match element {
+        Element::Text(text) => format!("{} {:?}", name, text),
+        Element::Code(text)
+        | Element::Hashtag(text)
+        | Element::Emoji(text)
+        | Element::RawHtml(text)
+        | Element::Mention { username: text } => format!("{} {:?}", name, text),
+        Element::Pre(PreBlock {
+            code,
+            language: Some(language),
+        }) => format!("{} {:?} language={:?}", name, code, language),
+        Element::Pre(block) => format!("{} {:?}", name, block.code),
+        Element::TextLink { text, url } => format!("{} {:?} url={:?}", name, text, url),
+        Element::Image { url, alt } => format!("{} {:?} url={:?}", name, alt, url),
+        Element::MentionId { user_id, text } => format!("{} {} {:?}", name, user_id, text),
+        Element::CustomEmoji { emoji, id } => format!("{} {} {:?}", name, id, emoji),
+        Element::Custom { formatter, value } => format!("{} {} {:?}", name, formatter, value),
+        Element::Command {
+            name: command,
+            args,
+            bot,
+        } => format!("{} {:?} args={:?} bot={:?}", name, command, args, bot),
+        Element::Link { url, .. } => format!("{} url={:?}", name, url),
+        Element::Quote {
+            cite: Some(cite), ..
+        } => format!("{} cite={:?}", name, cite),
+        Element::Footnote { id, .. } => format!("{} id={:?}", name, id),
+        Element::List(list) => format!("{} {:?}", name, list.style),
+        Element::Table(table) => format!("{} {:?}", name, table.style),
+        _ => name.to_string(),
+    };
+    tree_line(out, depth, &label);
+
+    match element {
+        Element::Bold(elements)
+        | Element::Italic(elements)
+        | Element::Underline(elements)
+        | Element::Strikethrough(elements)
+        | Element::Spoiler(elements)
+        | Element::Group(elements)
+        | Element::Link { text: elements, .. }
+        | Element::Quote {
+            content: elements, ..
+        }
+        | Element::Footnote {
+            content: elements, ..
+        } => elements.iter().for_each(|e| tree_node(out, e, depth + 1)),
+        Element::List(list) => tree_list(out, list, depth + 1),
+        Element::Table(table) => {
+            tree_line(out, depth + 1, "Headers");
+            tree_cells(out, &table.headers, depth + 2);
+            for row in &table.rows {
+                tree_line(out, depth + 1, "Row");
+                tree_cells(out, &row.cells, depth + 2);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn tree_line(out: &mut String, depth: usize, label: &str) {
+    out.push_str(&"  ".repeat(depth));
+    out.push_str(label);
+    out.push('\n');
+}
+
+fn tree_list(out: &mut String, list: &ListNode, depth: usize) {
+    for item in &list.items {
+        tree_line(out, depth, "Item");
+        item.content
+            .iter()
+            .for_each(|e| tree_node(out, e, depth + 1));
+        if let Some(nested) = &item.nested {
+            tree_line(out, depth + 1, &format!("List {:?}", nested.style));
+            tree_list(out, nested, depth + 2);
+        }
+    }
+}
+
+fn tree_cells(out: &mut String, cells: &[TableCell], depth: usize) {
+    for cell in cells {
+        tree_line(out, depth, "Cell");
+        cell.content
+            .iter()
+            .for_each(|e| tree_node(out, e, depth + 1));
+    }
+}
+
+pub fn flatten(elements: Vec<Element>) -> Vec<Element> {
+    let mut flat = Vec::with_capacity(elements.len());
+    for element in elements {
+        match element {
+            Element::Group(inner) => flat.extend(flatten(inner)),
+            mut element => {
+                flatten_children(&mut element);
+                flat.push(element);
+            }
+        }
+    }
+    flat
+}
+
+pub fn prune_empty(mut elements: Vec<Element>) -> Vec<Element> {
+    elements.retain(|e| !e.is_empty());
+    for element in &mut elements {
+        element.walk_mut(&mut |e| match e {
+            Element::Bold(children)
+            | Element::Italic(children)
+            | Element::Underline(children)
+            | Element::Strikethrough(children)
+            | Element::Spoiler(children)
+            | Element::Quote {
+                content: children, ..
+            }
+            | Element::Group(children)
+            | Element::Link { text: children, .. }
+            | Element::Footnote {
+                content: children, ..
+            } => children.retain(|c| !c.is_empty()),
+            Element::List(list) => prune_list(list),
+            Element::Table(table) => table
+                .headers
+                .iter_mut()
+                .chain(table.rows.iter_mut().flat_map(|row| row.cells.iter_mut()))
+                .for_each(|cell| cell.content.retain(|c| !c.is_empty())),
+            _ => {}
+        });
+    }
+    elements
+}
+
+fn prune_list(list: &mut ListNode) {
+    for item in &mut list.items {
+        item.content.retain(|c| !c.is_empty());
+        if let Some(nested) = &mut item.nested {
+            prune_list(nested);
+        }
+    }
+}
+
+pub fn summarize(mut elements: Vec<Element>, max_code_lines: usize) -> Vec<Element> {
+    for element in &mut elements {
+        element.walk_mut(&mut |e| match e {
+            Element::Code(code) => truncate_lines(code, max_code_lines),
+            Element::Pre(block) => truncate_lines(&mut block.code, max_code_lines),
+            _ => {}
+        });
+    }
+    elements
+}
+
+// Numbers footnotes in reading order, repeating the number for a reused id,
+// and moves their content into a definitions block at the end
+pub fn collect_footnotes(mut elements: Vec<Element>) -> Vec<Element> {
+    let mut ids: Vec<String> = Vec::new();
+    let mut definitions: Vec<Vec<Element>> = Vec::new();
+    for element in &mut elements {
+        element.walk_mut(&mut |e| {
+            if let Element::Footnote { id, content } = e {
+                let number = match ids.iter().position(|known| known == id) {
+                    Some(index) => index + 1,
+                    None => {
+                        ids.push(std::mem::take(id));
+                        definitions.push(std::mem::take(content));
+                        ids.len()
+                    }
+                };
+                *e = Element::text(format!("[{}]", number));
+            }
+        });
+    }
+
+    if !definitions.is_empty() {
+        let mut block = vec![Element::text("\n")];
+        for (index, content) in definitions.into_iter().enumerate() {
+            block.push(Element::text(format!("\n[{}] ", index + 1)));
+            block.extend(content);
+        }
+        elements.push(Element::Group(block));
+    }
+    elements
+}
+
+fn truncate_lines(code: &mut String, max_lines: usize) {
+    if code.lines().count() <= max_lines {
+        return;
+    }
+    let mut truncated = code.lines().take(max_lines).collect::<Vec<_>>().join("\n");
+    if !truncated.is_empty() {
+        truncated.push('\n');
+    }
+    truncated.push_str("… (truncated)");
+    *code = truncated;
+}
+
+fn flatten_children(element: &mut Element) {
+    match element {
+        Element::Bold(elements)
+        | Element::Italic(elements)
+        | Element::Underline(elements)
+        | Element::Strikethrough(elements)
+        | Element::Spoiler(elements)
+        | Element::Quote {
+            content: elements, ..
+        }
+        | Element::Link { text: elements, .. }
+        | Element::Footnote {
+            content: elements, ..
+        } => {
+            *elements = flatten(std::mem::take(elements));
+        }
+
+        Element::List(list) => flatten_list(list),
+
+        Element::Table(table) => {
+            for cell in table
+                .headers
+                .iter_mut()
+                .chain(table.rows.iter_mut().flat_map(|row| row.cells.iter_mut()))
+            {
+                cell.content = flatten(std::mem::take(&mut cell.content));
+            }
+        }
+
+        _ => {}
+    }
+}
+
+fn flatten_list(list: &mut ListNode) {
+    for item in &mut list.items {
+        item.content = flatten(std::mem::take(&mut item.content));
+        if let Some(nested) = &mut item.nested {
+            flatten_list(nested);
+        }
+    }
 }
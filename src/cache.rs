@@ -0,0 +1,125 @@
+use crate::ast::Element;
+use crate::generator::ParseMode;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Entries are found by a hash of the mode and element but keep the element
+// itself, so a hash collision, or a table with conditional rules that never
+// compares equal, is a miss rather than a wrong hit. Recency is a doubly
+// linked list threaded through `nodes`, which keeps every operation O(1).
+pub(crate) struct RenderCache {
+    capacity: usize,
+    index: HashMap<u64, usize>,
+    nodes: Vec<Node>,
+    // Most and least recently used nodes
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+struct Node {
+    key: u64,
+    mode: ParseMode,
+    element: Element,
+    output: String,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+impl RenderCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            index: HashMap::new(),
+            nodes: Vec::new(),
+            head: None,
+            tail: None,
+        }
+    }
+
+    pub(crate) fn get(&mut self, mode: ParseMode, element: &Element) -> Option<String> {
+        let index = *self.index.get(&key(mode, element))?;
+        let node = &self.nodes[index];
+        if node.mode != mode || node.element != *element {
+            return None;
+        }
+        let output = node.output.clone();
+        self.unlink(index);
+        self.push_front(index);
+        Some(output)
+    }
+
+    pub(crate) fn insert(&mut self, mode: ParseMode, element: &Element, output: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = key(mode, element);
+        let index = match self.index.get(&key) {
+            Some(&index) => {
+                self.unlink(index);
+                index
+            }
+            None if self.nodes.len() < self.capacity => {
+                self.nodes.push(Node {
+                    key,
+                    mode,
+                    element: element.clone(),
+                    output: String::new(),
+                    prev: None,
+                    next: None,
+                });
+                self.nodes.len() - 1
+            }
+            None => {
+                let oldest = self.tail.expect("a full cache has a tail");
+                self.unlink(oldest);
+                self.index.remove(&self.nodes[oldest].key);
+                oldest
+            }
+        };
+
+        let node = &mut self.nodes[index];
+        node.key = key;
+        node.mode = mode;
+        node.element = element.clone();
+        node.output = output;
+        self.index.insert(key, index);
+        self.push_front(index);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.index.clear();
+        self.nodes.clear();
+        self.head = None;
+        self.tail = None;
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = (self.nodes[index].prev, self.nodes[index].next);
+        match prev {
+            Some(prev) => self.nodes[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn push_front(&mut self, index: usize) {
+        self.nodes[index].prev = None;
+        self.nodes[index].next = self.head;
+        match self.head {
+            Some(head) => self.nodes[head].prev = Some(index),
+            None => self.tail = Some(index),
+        }
+        self.head = Some(index);
+    }
+}
+
+fn key(mode: ParseMode, element: &Element) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mode.hash(&mut hasher);
+    element.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,29 @@
+use chrono::{DateTime, Local, TimeZone};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(DateTime<Local>);
+
+impl FixedClock {
+    pub fn new<Tz: TimeZone>(now: DateTime<Tz>) -> Self {
+        Self(now.with_timezone(&Local))
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Local> {
+        self.0
+    }
+}
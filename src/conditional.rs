@@ -1,19 +1,19 @@
 use crate::ast::{Condition, ConditionalFormat, Element};
+use crate::width::grapheme_count;
 use regex::Regex;
 
 impl Condition {
     pub fn evaluate(&self, value: &str) -> bool {
         match self {
-            Condition::GreaterThan(threshold) => {
-                value.parse::<f64>().map_or(false, |v| v > *threshold)
-            }
-            Condition::LessThan(threshold) => {
-                value.parse::<f64>().map_or(false, |v| v < *threshold)
-            }
+            Condition::GreaterThan(threshold) => value.parse::<f64>().is_ok_and(|v| v > *threshold),
+            Condition::LessThan(threshold) => value.parse::<f64>().is_ok_and(|v| v < *threshold),
             Condition::Equals(expected) => value == expected,
             Condition::Contains(substring) => value.contains(substring),
-            Condition::Regex(pattern) => Regex::new(pattern).map_or(false, |re| re.is_match(value)),
+            Condition::Regex(pattern) => Regex::new(pattern).is_ok_and(|re| re.is_match(value)),
             Condition::Custom(_) => false,
+            Condition::Empty => value.trim().is_empty(),
+            Condition::NonEmpty => !value.trim().is_empty(),
+            Condition::LengthGreaterThan(length) => grapheme_count(value) > *length,
         }
     }
 }
@@ -0,0 +1,454 @@
+use crate::ast::{flatten, Element, ListNode, ListStyle, PreBlock};
+use crate::error::{Error, Result};
+use crate::generator::{link_text, Generator};
+use crate::normalize::merge_adjacent;
+use std::collections::HashMap;
+
+pub const MAX_ENTITIES: usize = 100;
+
+// The Bot API's formatting rules (https://core.telegram.org/bots/api#formatting-options)
+// let a blockquote hold bold, italic, underline, strikethrough and spoiler,
+// which nest in any order around one other entity such as a link. Anything
+// deeper repeats an entity type, e.g. bold inside bold, which adds nothing.
+pub const MAX_ENTITY_DEPTH: usize = 7;
+
+/// Checks the entities [`to_entities`] would produce against Telegram's limits,
+/// so adjacent runs it merges into one entity only count once.
+pub fn check_entity_limits(elements: &[Element]) -> Result<()> {
+    let (_, entities) = to_entities(elements)?;
+
+    if entities.len() > MAX_ENTITIES {
+        return Err(Error::Generation(format!(
+            "message has {} entities, Telegram allows at most {}",
+            entities.len(),
+            MAX_ENTITIES
+        )));
+    }
+    let depth = entity_depth(&entities);
+    if depth > MAX_ENTITY_DEPTH {
+        return Err(Error::Generation(format!(
+            "entities are nested {} deep, at most {} is supported",
+            depth, MAX_ENTITY_DEPTH
+        )));
+    }
+    Ok(())
+}
+
+// Entities from `to_entities` nest properly, so the depth is the most that
+// are open at any offset
+fn entity_depth(entities: &[MessageEntity]) -> usize {
+    let mut entities: Vec<&MessageEntity> = entities.iter().collect();
+    entities.sort_by_key(|e| (e.offset, std::cmp::Reverse(e.length)));
+
+    let mut open: Vec<usize> = Vec::new();
+    let mut depth = 0;
+    for entity in entities {
+        while open.last().is_some_and(|&end| end <= entity.offset) {
+            open.pop();
+        }
+        open.push(entity.offset + entity.length);
+        depth = depth.max(open.len());
+    }
+    depth
+}
+
+/// A Telegram message entity. Offsets and lengths are in UTF-16 code units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageEntity {
+    pub kind: EntityKind,
+    pub offset: usize,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntityKind {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Spoiler,
+    Blockquote,
+    Code,
+    Pre { language: Option<String> },
+    TextLink { url: String },
+    TextMention { user_id: u64 },
+    Mention,
+    Hashtag,
+    BotCommand,
+    CustomEmoji { custom_emoji_id: u64 },
+}
+
+/// Renders elements as plain text plus the entities Telegram needs to format it.
+///
+/// Groups are flattened and adjacent runs of the same formatting merged first,
+/// so `Group([Bold("a"), Bold("b")])` produces a single bold entity.
+pub fn to_entities(elements: &[Element]) -> Result<(String, Vec<MessageEntity>)> {
+    let mut writer = EntityWriter::default();
+    writer.visit_all(&merge_adjacent(flatten(elements.to_vec())))?;
+    writer.entities.retain(|entity| entity.length > 0);
+    Ok((writer.text, writer.entities))
+}
+
+/// Rebuilds elements from text and Bot API entities, the inverse of [`to_entities`].
+///
+/// Entities that partially overlap are split at the boundaries of the one that
+/// starts first, so the result is always a properly nested tree.
+pub fn from_entities(text: &str, entities: &[MessageEntity]) -> Result<Vec<Element>> {
+    // Byte index for every UTF-16 offset that falls on a character boundary
+    let mut bytes = vec![None; text.encode_utf16().count() + 1];
+    let mut utf16 = 0;
+    for (index, ch) in text.char_indices() {
+        bytes[utf16] = Some(index);
+        utf16 += ch.len_utf16();
+    }
+    bytes[utf16] = Some(text.len());
+
+    let mut entities: Vec<&MessageEntity> = entities.iter().filter(|e| e.length > 0).collect();
+    entities.sort_by_key(|e| (e.offset, std::cmp::Reverse(e.length)));
+
+    let mut boundaries = vec![0, utf16];
+    for entity in &entities {
+        let end = entity.offset.saturating_add(entity.length);
+        for offset in [entity.offset, end] {
+            if bytes.get(offset).copied().flatten().is_none() {
+                return Err(Error::Parse(format!(
+                    "entity {:?} at {}..{} does not fall on character boundaries",
+                    entity.kind, entity.offset, end
+                )));
+            }
+        }
+        boundaries.extend([entity.offset, end]);
+    }
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let segments: Vec<Segment> = boundaries
+        .windows(2)
+        .map(|pair| Segment {
+            text: &text[bytes[pair[0]].unwrap()..bytes[pair[1]].unwrap()],
+            active: (0..entities.len())
+                .filter(|&i| {
+                    entities[i].offset <= pair[0]
+                        && pair[1] <= entities[i].offset + entities[i].length
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(build_tree(&segments, &entities, 0))
+}
+
+struct Segment<'a> {
+    text: &'a str,
+    // Indices into the sorted entities, outermost first
+    active: Vec<usize>,
+}
+
+fn build_tree(segments: &[Segment], entities: &[&MessageEntity], depth: usize) -> Vec<Element> {
+    let mut elements = Vec::new();
+    let mut start = 0;
+    while start < segments.len() {
+        let entity = segments[start].active.get(depth).copied();
+        let end = start
+            + segments[start..]
+                .iter()
+                .take_while(|segment| segment.active.get(depth).copied() == entity)
+                .count();
+        let run = &segments[start..end];
+        match entity {
+            Some(index) => elements.push(entity_element(
+                &entities[index].kind,
+                build_tree(run, entities, depth + 1),
+            )),
+            None => elements.push(Element::text(
+                run.iter().map(|segment| segment.text).collect::<String>(),
+            )),
+        }
+        start = end;
+    }
+    elements
+}
+
+fn entity_element(kind: &EntityKind, children: Vec<Element>) -> Element {
+    let text = || {
+        children
+            .iter()
+            .flat_map(|e| e.text_iter())
+            .collect::<String>()
+    };
+    match kind {
+        EntityKind::Bold => Element::Bold(children),
+        EntityKind::Italic => Element::Italic(children),
+        EntityKind::Underline => Element::Underline(children),
+        EntityKind::Strikethrough => Element::Strikethrough(children),
+        EntityKind::Spoiler => Element::Spoiler(children),
+        EntityKind::Blockquote => Element::quote(children),
+        EntityKind::Code => Element::Code(text()),
+        EntityKind::Pre { language } => Element::Pre(PreBlock {
+            code: text(),
+            language: language.clone(),
+        }),
+        EntityKind::TextLink { url } => Element::link(children, url.clone()),
+        EntityKind::TextMention { user_id } => Element::mention_id(*user_id, text()),
+        EntityKind::Mention => Element::mention(text().trim_start_matches('@').to_string()),
+        EntityKind::Hashtag => Element::hashtag(text().trim_start_matches('#').to_string()),
+        EntityKind::BotCommand => {
+            let text = text();
+            let command = text.trim_start_matches('/');
+            match command.split_once('@') {
+                Some((name, bot)) => Element::command_for_bot(name, bot),
+                None => Element::command(command),
+            }
+        }
+        EntityKind::CustomEmoji { custom_emoji_id } => Element::CustomEmoji {
+            emoji: text(),
+            id: *custom_emoji_id,
+        },
+    }
+}
+
+impl Element {
+    /// Counts entities by their Telegram type name, e.g. `"bold"` or `"text_link"`.
+    pub fn entity_counts(&self) -> HashMap<&'static str, usize> {
+        let mut counter = EntityCounter::default();
+        counter.visit(self);
+        counter.counts
+    }
+}
+
+#[derive(Default)]
+struct EntityCounter {
+    counts: HashMap<&'static str, usize>,
+}
+
+impl EntityCounter {
+    fn entity(&mut self, element: &Element) {
+        if let Some(kind) = entity_type(element) {
+            *self.counts.entry(kind).or_default() += 1;
+        }
+    }
+
+    fn visit(&mut self, element: &Element) {
+        match element {
+            Element::Bold(elements)
+            | Element::Italic(elements)
+            | Element::Underline(elements)
+            | Element::Strikethrough(elements)
+            | Element::Spoiler(elements)
+            | Element::Link { text: elements, .. }
+            | Element::Quote {
+                content: elements, ..
+            } => {
+                self.entity(element);
+                elements.iter().for_each(|e| self.visit(e));
+            }
+
+            Element::Code(_)
+            | Element::Pre(_)
+            | Element::TextLink { .. }
+            | Element::Mention { .. }
+            | Element::MentionId { .. }
+            | Element::Hashtag(_)
+            | Element::Command { .. }
+            | Element::CustomEmoji { .. }
+            | Element::Image { .. }
+            | Element::Table(_) => self.entity(element),
+
+            Element::List(list) => self.visit_list(list),
+            Element::Group(elements)
+            | Element::Footnote {
+                content: elements, ..
+            } => elements.iter().for_each(|e| self.visit(e)),
+
+            Element::Text(_)
+            | Element::Emoji(_)
+            | Element::Custom { .. }
+            | Element::RawHtml(_)
+            | Element::Preformatted { .. } => {}
+        }
+    }
+
+    fn visit_list(&mut self, list: &ListNode) {
+        for item in &list.items {
+            item.content.iter().for_each(|e| self.visit(e));
+            if let Some(nested) = &item.nested {
+                self.visit_list(nested);
+            }
+        }
+    }
+}
+
+fn entity_type(element: &Element) -> Option<&'static str> {
+    let kind = match element {
+        Element::Bold(_) => "bold",
+        Element::Italic(_) => "italic",
+        Element::Underline(_) => "underline",
+        Element::Strikethrough(_) => "strikethrough",
+        Element::Spoiler(_) => "spoiler",
+        Element::Quote { .. } => "blockquote",
+        Element::Code(_) => "code",
+        Element::Pre(_) | Element::Table(_) => "pre",
+        Element::Link { .. } | Element::TextLink { .. } | Element::Image { .. } => "text_link",
+        Element::Mention { .. } => "mention",
+        Element::MentionId { .. } => "text_mention",
+        Element::Hashtag(_) => "hashtag",
+        Element::Command { .. } => "bot_command",
+        Element::CustomEmoji { .. } => "custom_emoji",
+        _ => return None,
+    };
+    Some(kind)
+}
+
+#[derive(Default)]
+struct EntityWriter {
+    text: String,
+    offset: usize,
+    entities: Vec<MessageEntity>,
+}
+
+impl EntityWriter {
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.offset += text.encode_utf16().count();
+    }
+
+    fn wrap(
+        &mut self,
+        kind: EntityKind,
+        inner: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        let index = self.entities.len();
+        let offset = self.offset;
+        self.entities.push(MessageEntity {
+            kind,
+            offset,
+            length: 0,
+        });
+        inner(self)?;
+        self.entities[index].length = self.offset - offset;
+        Ok(())
+    }
+
+    fn wrap_text(&mut self, kind: EntityKind, text: &str) -> Result<()> {
+        self.wrap(kind, |w| {
+            w.push(text);
+            Ok(())
+        })
+    }
+
+    fn visit_all(&mut self, elements: &[Element]) -> Result<()> {
+        elements.iter().try_for_each(|e| self.visit(e))
+    }
+
+    fn visit(&mut self, element: &Element) -> Result<()> {
+        match element {
+            Element::Text(text) => self.push(text),
+            Element::Bold(elements) => self.wrap(EntityKind::Bold, |w| w.visit_all(elements))?,
+            Element::Italic(elements) => {
+                self.wrap(EntityKind::Italic, |w| w.visit_all(elements))?
+            }
+            Element::Underline(elements) => {
+                self.wrap(EntityKind::Underline, |w| w.visit_all(elements))?
+            }
+            Element::Strikethrough(elements) => {
+                self.wrap(EntityKind::Strikethrough, |w| w.visit_all(elements))?
+            }
+            Element::Spoiler(elements) => {
+                self.wrap(EntityKind::Spoiler, |w| w.visit_all(elements))?
+            }
+            Element::Code(code) if code.contains('\n') => {
+                self.wrap_text(EntityKind::Pre { language: None }, code)?
+            }
+            Element::Code(code) => self.wrap_text(EntityKind::Code, code)?,
+            Element::Pre(pre) => {
+                let kind = EntityKind::Pre {
+                    language: pre.language.clone(),
+                };
+                self.wrap_text(kind, &pre.code)?
+            }
+            Element::Link { text, url, .. } => {
+                let kind = EntityKind::TextLink { url: url.clone() };
+                self.wrap(kind, |w| w.visit_all(&link_text(text)))?
+            }
+            Element::TextLink { text, url } => {
+                let kind = EntityKind::TextLink { url: url.clone() };
+                self.wrap_text(kind, text)?
+            }
+            Element::Image { url, alt } => {
+                let kind = EntityKind::TextLink { url: url.clone() };
+                self.wrap_text(kind, alt)?
+            }
+            Element::Mention { username } => {
+                self.wrap_text(EntityKind::Mention, &format!("@{}", username))?
+            }
+            Element::MentionId { user_id, text } => {
+                let kind = EntityKind::TextMention { user_id: *user_id };
+                self.wrap_text(kind, text)?
+            }
+            Element::Hashtag(tag) => self.wrap_text(EntityKind::Hashtag, &format!("#{}", tag))?,
+            Element::Command { name, args, bot } => {
+                let command = match bot {
+                    Some(bot) => format!("/{}@{}", name, bot),
+                    None => format!("/{}", name),
+                };
+                self.wrap_text(EntityKind::BotCommand, &command)?;
+                for arg in args {
+                    self.push(" ");
+                    self.push(arg);
+                }
+            }
+            Element::Emoji(emoji) => self.push(emoji),
+            Element::CustomEmoji { emoji, id } => {
+                let kind = EntityKind::CustomEmoji {
+                    custom_emoji_id: *id,
+                };
+                self.wrap_text(kind, emoji)?
+            }
+            Element::Quote { content, cite } => self.wrap(EntityKind::Blockquote, |w| {
+                w.visit_all(content)?;
+                if let Some(cite) = cite {
+                    w.push("\n— ");
+                    w.push(cite);
+                }
+                Ok(())
+            })?,
+            Element::List(list) => self.visit_list(list, 0)?,
+            Element::Table(table) => {
+                let body = Generator::default().plain_table(table)?;
+                self.wrap_text(EntityKind::Pre { language: None }, &body)?
+            }
+            Element::Custom { value, .. } | Element::RawHtml(value) => self.push(value),
+            Element::Preformatted { .. } => {
+                return Err(Error::Generation(
+                    "preformatted content has no entity representation".to_string(),
+                ))
+            }
+            Element::Group(elements)
+            | Element::Footnote {
+                content: elements, ..
+            } => self.visit_all(elements)?,
+        }
+        Ok(())
+    }
+
+    fn visit_list(&mut self, list: &ListNode, depth: usize) -> Result<()> {
+        for (i, item) in list.items.iter().enumerate() {
+            if i > 0 || depth > 0 {
+                self.push("\n");
+            }
+            self.push(&"  ".repeat(depth));
+            match &list.style {
+                ListStyle::Bullet => self.push("• "),
+                ListStyle::Numbered => self.push(&format!("{}. ", i + 1)),
+                ListStyle::Custom(marker) if marker.is_empty() => {}
+                ListStyle::None => {}
+                ListStyle::Custom(marker) => self.push(&format!("{} ", marker)),
+            }
+            self.visit_all(&item.content)?;
+            if let Some(nested) = &item.nested {
+                self.visit_list(nested, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
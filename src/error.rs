@@ -14,8 +14,8 @@ pub enum Error {
     #[error("Formatter not found: {0}")]
     FormatterNotFound(String),
 
-    #[error("Invalid formatter value: {0}")]
-    InvalidFormatterValue(String),
+    #[error("Invalid value for formatter {formatter}: {value:?}")]
+    InvalidFormatterValue { formatter: String, value: String },
 
     #[error("Generation error: {0}")]
     Generation(String),
@@ -23,6 +23,9 @@ pub enum Error {
     #[error("Invalid table structure: {0}")]
     InvalidTable(String),
 
+    #[error("Invalid username {0:?}: expected 5-32 letters, digits or underscores starting with a letter")]
+    InvalidUsername(String),
+
     #[error("Regex error: {0}")]
     Regex(#[from] regex::Error),
 }
@@ -0,0 +1,52 @@
+use crate::generator::{self, ParseMode};
+
+/// Escapes plain text for the given parse mode.
+///
+/// ```
+/// use msg::ParseMode;
+///
+/// assert_eq!(msg::escape::text("1.5 * 2", ParseMode::MarkdownV2), r"1\.5 \* 2");
+/// ```
+pub fn text(text: &str, mode: ParseMode) -> String {
+    generator::escape(text, mode)
+}
+
+/// Escapes the URL part of a MarkdownV2 link.
+///
+/// ```
+/// let url = msg::escape::markdown_url("https://en.wikipedia.org/wiki/Rust_(language)");
+/// assert_eq!(url, r"https://en.wikipedia.org/wiki/Rust_(language\)");
+/// ```
+pub fn markdown_url(url: &str) -> String {
+    generator::escape_url(url)
+}
+
+/// Escapes inline code for MarkdownV2.
+///
+/// ```
+/// assert_eq!(msg::escape::markdown_code(r"let s = `\n`;"), r"let s = \`\\n\`;");
+/// ```
+pub fn markdown_code(code: &str) -> String {
+    generator::escape_code(code)
+}
+
+/// Escapes the body of a MarkdownV2 pre block.
+///
+/// ```
+/// assert_eq!(msg::escape::markdown_pre("```rust"), r"\`\`\`rust");
+/// ```
+pub fn markdown_pre(code: &str) -> String {
+    generator::escape_pre(code)
+}
+
+/// Escapes text or attribute values for HTML.
+///
+/// ```
+/// assert_eq!(
+///     msg::escape::html(r#"<a href="x">&</a>"#),
+///     "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+/// );
+/// ```
+pub fn html(text: &str) -> String {
+    generator::escape_html(text)
+}
@@ -1,11 +1,164 @@
-use crate::error::Result;
-use crate::generator::ParseMode;
-use chrono::NaiveDate;
+use crate::clock::{Clock, FixedClock, SystemClock};
+use crate::error::{Error, Result};
+use crate::generator::{discord_code, escape, escape_slack, ParseMode};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FormatContext {
+    pub locale: Option<String>,
+}
+
+impl FormatContext {
+    pub fn new(locale: impl Into<String>) -> Self {
+        Self {
+            locale: Some(locale.into()),
+        }
+    }
+
+    pub fn decimal_separator(&self) -> char {
+        self.separators().0
+    }
+
+    pub fn grouping_separator(&self) -> Option<char> {
+        self.separators().1
+    }
+
+    pub fn format_number(&self, value: f64, precision: usize) -> String {
+        let (decimal, grouping) = self.separators();
+        let raw = format!("{:.*}", precision, value.abs());
+        let (int_part, frac_part) = match raw.split_once('.') {
+            Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+            None => (raw.as_str(), None),
+        };
+
+        let mut result = String::new();
+        if value < 0.0 {
+            result.push('-');
+        }
+
+        for (i, digit) in int_part.chars().enumerate() {
+            if i > 0 && (int_part.len() - i) % 3 == 0 {
+                if let Some(sep) = grouping {
+                    result.push(sep);
+                }
+            }
+            result.push(digit);
+        }
+
+        if let Some(frac_part) = frac_part {
+            result.push(decimal);
+            result.push_str(frac_part);
+        }
+
+        result
+    }
+
+    fn separators(&self) -> (char, Option<char>) {
+        let Some(locale) = &self.locale else {
+            return ('.', None);
+        };
+
+        let tag = locale.replace('_', "-").to_lowercase();
+        match tag.as_str() {
+            "de-ch" | "it-ch" | "fr-ch" => return ('.', Some('\'')),
+            _ => {}
+        }
+
+        let language = tag.split('-').next().unwrap_or_default();
+        match language {
+            "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el" => (',', Some('.')),
+            "fr" | "ru" | "uk" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" => {
+                (',', Some('\u{a0}'))
+            }
+            _ => ('.', Some(',')),
+        }
+    }
+}
 
 pub trait CustomFormatter: Send + Sync {
     fn name(&self) -> &str;
-    fn format(&self, value: &str, mode: ParseMode) -> Result<String>;
+    fn format(&self, value: &str, mode: ParseMode, ctx: &FormatContext) -> Result<String>;
     fn parse(&self, input: &str) -> Option<(String, usize)>;
+
+    fn validate(&self, _value: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Formats a phone number the way `msg!`'s `+(...)` items do, returning the
+/// display text and its `tel:` URL, or `None` if `raw` has no digits.
+///
+/// Without a `prefix`, 11-digit numbers starting with 7 or 8 and bare 10-digit
+/// numbers are treated as Russian (`+7(999) 123-45-67`), 11-digit numbers
+/// starting with 1 as North American (`+1 (202) 555-0123`), and anything else
+/// is kept as an international number.
+pub fn format_phone(raw: &str, prefix: Option<&str>) -> Option<(String, String)> {
+    let digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.is_empty() {
+        return None;
+    }
+
+    let (final_prefix, phone_digits, tel_digits) = match prefix {
+        Some(prefix) => {
+            // Drop a country code repeated in the number itself
+            let code = prefix.replace('+', "");
+            let local = if digits.len() == code.len() + 10 && digits.starts_with(&code) {
+                digits[code.len()..].to_string()
+            } else {
+                digits.clone()
+            };
+            let tel = format!("{}{}", code, local);
+            (prefix.to_string(), local, tel)
+        }
+        None if digits.len() == 11 && (digits.starts_with('7') || digits.starts_with('8')) => (
+            "+7".to_string(),
+            digits[1..].to_string(),
+            format!("7{}", &digits[1..]),
+        ),
+        None if digits.len() == 11 && digits.starts_with('1') => {
+            ("+1".to_string(), digits[1..].to_string(), digits.clone())
+        }
+        None if digits.len() == 10 => ("+7".to_string(), digits.clone(), format!("7{}", digits)),
+        None => ("+".to_string(), digits.clone(), digits.clone()),
+    };
+
+    let display = if final_prefix == "+" {
+        format!("+{}", phone_digits)
+    } else if final_prefix == "+1" && phone_digits.len() == 10 {
+        format!(
+            "+1 ({}) {}-{}",
+            &phone_digits[0..3],
+            &phone_digits[3..6],
+            &phone_digits[6..10]
+        )
+    } else if phone_digits.len() == 10 {
+        let space = if prefix.is_some() { " " } else { "" };
+        format!(
+            "{}{}({}) {}-{}-{}",
+            final_prefix,
+            space,
+            &phone_digits[0..3],
+            &phone_digits[3..6],
+            &phone_digits[6..8],
+            &phone_digits[8..10]
+        )
+    } else if phone_digits.len() >= 7 {
+        // (XXX) XXX-XX-XX..., pairs after the first group of three
+        let (area, rest) = phone_digits.split_at(3);
+        let (group, remaining) = rest.split_at(3);
+        let mut formatted = group.to_string();
+        for (i, c) in remaining.chars().enumerate() {
+            if i % 2 == 0 {
+                formatted.push('-');
+            }
+            formatted.push(c);
+        }
+        format!("{}({}) {}", final_prefix, area, formatted)
+    } else {
+        format!("{}{}", final_prefix, phone_digits)
+    };
+
+    Some((display, format!("tel:+{}", tel_digits)))
 }
 
 pub struct PhoneFormatter;
@@ -15,10 +168,13 @@ impl CustomFormatter for PhoneFormatter {
         "phone"
     }
 
-    fn format(&self, value: &str, mode: ParseMode) -> Result<String> {
+    fn format(&self, value: &str, mode: ParseMode, _ctx: &FormatContext) -> Result<String> {
+        let phone = self.display(value)?;
         let formatted = match mode {
-            ParseMode::MarkdownV2 => format!("`{}`", escape_markdown(value)),
-            ParseMode::Html => format!("<code>{}</code>", escape_html(value)),
+            ParseMode::MarkdownV2 => format!("`{}`", escape_markdown(&phone)),
+            ParseMode::Html => format!("<code>{}</code>", escape_html(&phone)),
+            ParseMode::DiscordMarkdown => discord_code(&phone),
+            ParseMode::SlackMrkdwn => format!("`{}`", escape_slack(&phone)),
         };
         Ok(formatted)
     }
@@ -28,6 +184,22 @@ impl CustomFormatter for PhoneFormatter {
         let mat = phone_regex.find(input)?;
         Some((mat.as_str().to_string(), mat.len()))
     }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        self.display(value).map(|_| ())
+    }
+}
+
+impl PhoneFormatter {
+    fn display(&self, value: &str) -> Result<String> {
+        let valid_chars = value
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '(' | ')'));
+        match format_phone(value, None) {
+            Some((display, _)) if valid_chars => Ok(display),
+            _ => Err(invalid_value(self.name(), value)),
+        }
+    }
 }
 
 pub struct DateFormatter;
@@ -37,14 +209,16 @@ impl CustomFormatter for DateFormatter {
         "date"
     }
 
-    fn format(&self, value: &str, mode: ParseMode) -> Result<String> {
+    fn format(&self, value: &str, mode: ParseMode, _ctx: &FormatContext) -> Result<String> {
         let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
             .map(|d| d.format("%d.%m.%Y").to_string())
-            .unwrap_or_else(|_| value.to_string());
+            .map_err(|_| invalid_value(self.name(), value))?;
 
         let formatted = match mode {
             ParseMode::MarkdownV2 => format!("`{}`", escape_markdown(&date)),
             ParseMode::Html => format!("<code>{}</code>", escape_html(&date)),
+            ParseMode::DiscordMarkdown => discord_code(&date),
+            ParseMode::SlackMrkdwn => format!("`{}`", escape_slack(&date)),
         };
         Ok(formatted)
     }
@@ -54,6 +228,12 @@ impl CustomFormatter for DateFormatter {
         let mat = date_regex.find(input)?;
         Some((mat.as_str().to_string(), mat.len()))
     }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(|_| ())
+            .map_err(|_| invalid_value(self.name(), value))
+    }
 }
 
 pub struct TimeFormatter;
@@ -63,10 +243,12 @@ impl CustomFormatter for TimeFormatter {
         "time"
     }
 
-    fn format(&self, value: &str, mode: ParseMode) -> Result<String> {
+    fn format(&self, value: &str, mode: ParseMode, _ctx: &FormatContext) -> Result<String> {
         let formatted = match mode {
             ParseMode::MarkdownV2 => format!("`{}`", escape_markdown(value)),
             ParseMode::Html => format!("<code>{}</code>", escape_html(value)),
+            ParseMode::DiscordMarkdown => discord_code(value),
+            ParseMode::SlackMrkdwn => format!("`{}`", escape_slack(value)),
         };
         Ok(formatted)
     }
@@ -76,6 +258,13 @@ impl CustomFormatter for TimeFormatter {
         let mat = time_regex.find(input)?;
         Some((mat.as_str().to_string(), mat.len()))
     }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        NaiveTime::parse_from_str(value, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M"))
+            .map(|_| ())
+            .map_err(|_| invalid_value(self.name(), value))
+    }
 }
 
 pub struct EmailFormatter;
@@ -85,10 +274,12 @@ impl CustomFormatter for EmailFormatter {
         "email"
     }
 
-    fn format(&self, value: &str, mode: ParseMode) -> Result<String> {
+    fn format(&self, value: &str, mode: ParseMode, _ctx: &FormatContext) -> Result<String> {
         let formatted = match mode {
             ParseMode::MarkdownV2 => format!("[✉️ {}](mailto:{})", escape_markdown(value), value),
             ParseMode::Html => format!("<a href=\"mailto:{}\">{}</a>", value, escape_html(value)),
+            ParseMode::DiscordMarkdown => format!("[✉️ {}](mailto:{})", escape(value, mode), value),
+            ParseMode::SlackMrkdwn => format!("<mailto:{}|✉️ {}>", value, escape(value, mode)),
         };
         Ok(formatted)
     }
@@ -99,6 +290,15 @@ impl CustomFormatter for EmailFormatter {
         let mat = email_regex.find(input)?;
         Some((mat.as_str().to_string(), mat.len()))
     }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        let email_regex = regex::Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$")?;
+        if email_regex.is_match(value) {
+            Ok(())
+        } else {
+            Err(invalid_value(self.name(), value))
+        }
+    }
 }
 
 pub struct CurrencyFormatter {
@@ -117,13 +317,15 @@ impl CustomFormatter for CurrencyFormatter {
         &self.code
     }
 
-    fn format(&self, value: &str, mode: ParseMode) -> Result<String> {
-        let amount = value.parse::<f64>().unwrap_or(0.0);
-        let formatted_amount = format!("{:.2} {}", amount, self.symbol);
+    fn format(&self, value: &str, mode: ParseMode, ctx: &FormatContext) -> Result<String> {
+        let amount = parse_finite(self.name(), value)?;
+        let formatted_amount = format!("{} {}", ctx.format_number(amount, 2), self.symbol);
 
         let formatted = match mode {
             ParseMode::MarkdownV2 => format!("`{}`", escape_markdown(&formatted_amount)),
             ParseMode::Html => format!("<code>{}</code>", escape_html(&formatted_amount)),
+            ParseMode::DiscordMarkdown => discord_code(&formatted_amount),
+            ParseMode::SlackMrkdwn => format!("`{}`", escape_slack(&formatted_amount)),
         };
         Ok(formatted)
     }
@@ -133,6 +335,10 @@ impl CustomFormatter for CurrencyFormatter {
         let mat = num_regex.find(input)?;
         Some((mat.as_str().to_string(), mat.len()))
     }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        parse_finite(self.name(), value).map(|_| ())
+    }
 }
 
 pub struct PercentFormatter;
@@ -142,13 +348,15 @@ impl CustomFormatter for PercentFormatter {
         "percent"
     }
 
-    fn format(&self, value: &str, mode: ParseMode) -> Result<String> {
-        let percent = value.parse::<f64>().unwrap_or(0.0);
-        let formatted_percent = format!("{:.1}%", percent);
+    fn format(&self, value: &str, mode: ParseMode, ctx: &FormatContext) -> Result<String> {
+        let percent = parse_finite(self.name(), value)?;
+        let formatted_percent = format!("{}%", ctx.format_number(percent, 1));
 
         let formatted = match mode {
             ParseMode::MarkdownV2 => format!("`{}`", escape_markdown(&formatted_percent)),
             ParseMode::Html => format!("<code>{}</code>", escape_html(&formatted_percent)),
+            ParseMode::DiscordMarkdown => discord_code(&formatted_percent),
+            ParseMode::SlackMrkdwn => format!("`{}`", escape_slack(&formatted_percent)),
         };
         Ok(formatted)
     }
@@ -158,6 +366,10 @@ impl CustomFormatter for PercentFormatter {
         let mat = num_regex.find(input)?;
         Some((mat.as_str().to_string(), mat.len()))
     }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        parse_finite(self.name(), value).map(|_| ())
+    }
 }
 
 pub struct ProgressFormatter;
@@ -167,8 +379,8 @@ impl CustomFormatter for ProgressFormatter {
         "progress"
     }
 
-    fn format(&self, value: &str, mode: ParseMode) -> Result<String> {
-        let progress = value.parse::<u8>().unwrap_or(0).min(100);
+    fn format(&self, value: &str, mode: ParseMode, _ctx: &FormatContext) -> Result<String> {
+        let progress = parse_progress(value)?;
         let filled = (progress as f32 / 10.0).round() as usize;
         let empty = 10 - filled;
         let bar = format!("{}{}", "▓".repeat(filled), "░".repeat(empty));
@@ -177,6 +389,8 @@ impl CustomFormatter for ProgressFormatter {
         let formatted = match mode {
             ParseMode::MarkdownV2 => format!("`{}`", escape_markdown(&formatted_progress)),
             ParseMode::Html => format!("<code>{}</code>", escape_html(&formatted_progress)),
+            ParseMode::DiscordMarkdown => discord_code(&formatted_progress),
+            ParseMode::SlackMrkdwn => format!("`{}`", escape_slack(&formatted_progress)),
         };
         Ok(formatted)
     }
@@ -186,6 +400,175 @@ impl CustomFormatter for ProgressFormatter {
         let mat = num_regex.find(input)?;
         Some((mat.as_str().to_string(), mat.len()))
     }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        parse_progress(value).map(|_| ())
+    }
+}
+
+pub struct RelativeTimeFormatter {
+    clock: Box<dyn Clock>,
+}
+
+impl RelativeTimeFormatter {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Box::new(clock),
+        }
+    }
+
+    pub fn with_now(now: DateTime<FixedOffset>) -> Self {
+        Self::with_clock(FixedClock::new(now))
+    }
+}
+
+impl Default for RelativeTimeFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CustomFormatter for RelativeTimeFormatter {
+    fn name(&self) -> &str {
+        "relative"
+    }
+
+    fn format(&self, value: &str, mode: ParseMode, _ctx: &FormatContext) -> Result<String> {
+        let then =
+            DateTime::parse_from_rfc3339(value).map_err(|_| invalid_value(self.name(), value))?;
+        let relative = format_relative(then.timestamp() - self.clock.now().timestamp());
+
+        let formatted = match mode {
+            ParseMode::MarkdownV2 => escape_markdown(&relative),
+            ParseMode::Html => escape_html(&relative),
+            ParseMode::DiscordMarkdown | ParseMode::SlackMrkdwn => escape(&relative, mode),
+        };
+        Ok(formatted)
+    }
+
+    fn parse(&self, input: &str) -> Option<(String, usize)> {
+        let timestamp_regex =
+            regex::Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})")
+                .ok()?;
+        let mat = timestamp_regex.find(input)?;
+        Some((mat.as_str().to_string(), mat.len()))
+    }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|_| ())
+            .map_err(|_| invalid_value(self.name(), value))
+    }
+}
+
+// ISO 3166-1 alpha-2 codes, sorted for binary search
+const COUNTRY_CODES: [&str; 249] = [
+    "AD", "AE", "AF", "AG", "AI", "AL", "AM", "AO", "AQ", "AR", "AS", "AT", "AU", "AW", "AX", "AZ",
+    "BA", "BB", "BD", "BE", "BF", "BG", "BH", "BI", "BJ", "BL", "BM", "BN", "BO", "BQ", "BR", "BS",
+    "BT", "BV", "BW", "BY", "BZ", "CA", "CC", "CD", "CF", "CG", "CH", "CI", "CK", "CL", "CM", "CN",
+    "CO", "CR", "CU", "CV", "CW", "CX", "CY", "CZ", "DE", "DJ", "DK", "DM", "DO", "DZ", "EC", "EE",
+    "EG", "EH", "ER", "ES", "ET", "FI", "FJ", "FK", "FM", "FO", "FR", "GA", "GB", "GD", "GE", "GF",
+    "GG", "GH", "GI", "GL", "GM", "GN", "GP", "GQ", "GR", "GS", "GT", "GU", "GW", "GY", "HK", "HM",
+    "HN", "HR", "HT", "HU", "ID", "IE", "IL", "IM", "IN", "IO", "IQ", "IR", "IS", "IT", "JE", "JM",
+    "JO", "JP", "KE", "KG", "KH", "KI", "KM", "KN", "KP", "KR", "KW", "KY", "KZ", "LA", "LB", "LC",
+    "LI", "LK", "LR", "LS", "LT", "LU", "LV", "LY", "MA", "MC", "MD", "ME", "MF", "MG", "MH", "MK",
+    "ML", "MM", "MN", "MO", "MP", "MQ", "MR", "MS", "MT", "MU", "MV", "MW", "MX", "MY", "MZ", "NA",
+    "NC", "NE", "NF", "NG", "NI", "NL", "NO", "NP", "NR", "NU", "NZ", "OM", "PA", "PE", "PF", "PG",
+    "PH", "PK", "PL", "PM", "PN", "PR", "PS", "PT", "PW", "PY", "QA", "RE", "RO", "RS", "RU", "RW",
+    "SA", "SB", "SC", "SD", "SE", "SG", "SH", "SI", "SJ", "SK", "SL", "SM", "SN", "SO", "SR", "SS",
+    "ST", "SV", "SX", "SY", "SZ", "TC", "TD", "TF", "TG", "TH", "TJ", "TK", "TL", "TM", "TN", "TO",
+    "TR", "TT", "TV", "TW", "TZ", "UA", "UG", "UM", "US", "UY", "UZ", "VA", "VC", "VE", "VG", "VI",
+    "VN", "VU", "WF", "WS", "YE", "YT", "ZA", "ZM", "ZW",
+];
+
+/// Renders an ISO 3166-1 alpha-2 country code such as `US` as its flag emoji.
+/// Anything else is shown as the plain value.
+pub struct FlagFormatter;
+
+impl CustomFormatter for FlagFormatter {
+    fn name(&self) -> &str {
+        "flag"
+    }
+
+    fn format(&self, value: &str, mode: ParseMode, _ctx: &FormatContext) -> Result<String> {
+        let code = value.to_ascii_uppercase();
+        if COUNTRY_CODES.binary_search(&code.as_str()).is_err() {
+            return Ok(escape(value, mode));
+        }
+        // Regional indicator symbols start at U+1F1E6 for 'A'
+        Ok(code
+            .chars()
+            .filter_map(|c| char::from_u32(0x1F1E6 + (c as u32 - 'A' as u32)))
+            .collect())
+    }
+
+    fn parse(&self, input: &str) -> Option<(String, usize)> {
+        let code_regex = regex::Regex::new(r"^[A-Za-z]{2}\b").ok()?;
+        let mat = code_regex.find(input)?;
+        Some((mat.as_str().to_string(), mat.len()))
+    }
+}
+
+fn format_relative(delta_seconds: i64) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ];
+
+    let seconds = delta_seconds.abs();
+    if seconds < 60 {
+        return "just now".to_string();
+    }
+
+    let (size, unit) = UNITS
+        .iter()
+        .find(|(size, _)| seconds >= *size)
+        .copied()
+        .unwrap_or((1, "second"));
+    let count = seconds / size;
+    let amount = if count == 1 {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", count, unit)
+    };
+
+    if delta_seconds < 0 {
+        format!("{} ago", amount)
+    } else {
+        format!("in {}", amount)
+    }
+}
+
+// `format` and `validate` share these so a value that validates always renders
+fn parse_finite(formatter: &str, value: &str) -> Result<f64> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .ok_or_else(|| invalid_value(formatter, value))
+}
+
+fn parse_progress(value: &str) -> Result<u8> {
+    value
+        .parse::<u8>()
+        .ok()
+        .filter(|&progress| progress <= 100)
+        .ok_or_else(|| invalid_value("progress", value))
+}
+
+fn invalid_value(formatter: &str, value: &str) -> Error {
+    Error::InvalidFormatterValue {
+        formatter: formatter.to_string(),
+        value: value.to_string(),
+    }
 }
 
 fn escape_markdown(text: &str) -> String {
@@ -1,13 +1,25 @@
 use crate::ast::*;
+use crate::cache::RenderCache;
 use crate::error::{Error, Result};
-use crate::formatter::CustomFormatter;
-use std::collections::HashMap;
+use crate::formatter::{CustomFormatter, FormatContext};
+use crate::width::{display_width, wrap_text};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::sync::Mutex;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+lazy_static! {
+    static ref ENTITY_REGEX: Regex = Regex::new(r"[@#]\w+").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ParseMode {
     MarkdownV2,
     Html,
+    DiscordMarkdown,
+    SlackMrkdwn,
 }
 
 pub trait Generate {
@@ -17,6 +29,101 @@ pub trait Generate {
 pub struct Generator {
     mode: ParseMode,
     formatters: HashMap<String, Box<dyn CustomFormatter>>,
+    context: FormatContext,
+    autolink_entities: bool,
+    strip_control_chars: bool,
+    wrap_at: Option<usize>,
+    bullets: Vec<String>,
+    table_fence_lang: Option<String>,
+    strict: bool,
+    max_depth: usize,
+    normalize_code_lang: bool,
+    annotate: bool,
+    unknown_formatter_passthrough: bool,
+    markdown_escape_chars: Option<HashSet<char>>,
+    pre_line_numbers: bool,
+    max_output_len: Option<usize>,
+    cache: Option<Mutex<RenderCache>>,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Self::new(ParseMode::MarkdownV2)
+    }
+}
+
+/// Renders an element straight into a formatter, see [`Generator::display`]
+/// and [`Element::display`].
+///
+/// Only elements that pass [`Generator::validate`] should be displayed:
+/// anything `generate` rejects fails with `fmt::Error`, which `format!` and
+/// `to_string` turn into a panic.
+pub struct Displayable<'a> {
+    generator: DisplayGenerator<'a>,
+    element: &'a Element,
+}
+
+enum DisplayGenerator<'a> {
+    Borrowed(&'a Generator),
+    Owned(Box<Generator>),
+}
+
+impl Element {
+    /// Wraps the element in a `Display` impl rendering it with a default
+    /// generator for `mode`, see [`Generator::display`].
+    pub fn display(&self, mode: ParseMode) -> Displayable<'_> {
+        Displayable {
+            generator: DisplayGenerator::Owned(Box::new(Generator::new(mode))),
+            element: self,
+        }
+    }
+}
+
+impl std::fmt::Display for Displayable<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let generator: &Generator = match &self.generator {
+            DisplayGenerator::Borrowed(generator) => generator,
+            DisplayGenerator::Owned(generator) => generator,
+        };
+        generator
+            .generate(f, self.element)
+            .map_err(|_| std::fmt::Error)
+    }
+}
+
+// Stops writing once `limit` bytes would be exceeded, which aborts generation
+struct LimitedWriter<'a, W> {
+    inner: &'a mut W,
+    written: usize,
+    limit: Option<usize>,
+}
+
+impl<'a, W> LimitedWriter<'a, W> {
+    fn new(inner: &'a mut W, limit: Option<usize>) -> Self {
+        Self {
+            inner,
+            written: 0,
+            limit,
+        }
+    }
+
+    fn remaining(&self) -> Option<usize> {
+        self.limit.map(|limit| limit.saturating_sub(self.written))
+    }
+
+    fn exceeded(&self) -> bool {
+        self.limit.is_some_and(|limit| self.written > limit)
+    }
+}
+
+impl<W: Write> Write for LimitedWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.written += s.len();
+        if self.exceeded() {
+            return Err(std::fmt::Error);
+        }
+        self.inner.write_str(s)
+    }
 }
 
 macro_rules! write_fmt {
@@ -30,140 +137,435 @@ impl Generator {
         Self {
             mode,
             formatters: HashMap::new(),
+            context: FormatContext::default(),
+            autolink_entities: false,
+            strip_control_chars: false,
+            wrap_at: None,
+            bullets: vec!["•".to_string()],
+            table_fence_lang: None,
+            strict: false,
+            max_depth: 100,
+            normalize_code_lang: false,
+            annotate: false,
+            unknown_formatter_passthrough: false,
+            markdown_escape_chars: None,
+            pre_line_numbers: false,
+            max_output_len: None,
+            cache: None,
+        }
+    }
+
+    /// Memoizes the output of `generate` for up to `capacity` elements,
+    /// evicting the least recently used. Formatters are assumed to be
+    /// deterministic, so time-dependent ones such as `relative` go stale.
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(Mutex::new(RenderCache::new(capacity)));
+        self
+    }
+
+    fn reset_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.get_mut().unwrap_or_else(|e| e.into_inner()).clear();
         }
     }
 
-    pub fn register_formatter(&mut self, formatter: Box<dyn CustomFormatter>) {
+    fn escape_text(&self, text: &str, mode: ParseMode) -> String {
+        match (&self.markdown_escape_chars, mode) {
+            (Some(chars), ParseMode::MarkdownV2) => text
+                .chars()
+                .map(|c| {
+                    if chars.contains(&c) {
+                        format!("\\{}", c)
+                    } else {
+                        c.to_string()
+                    }
+                })
+                .collect(),
+            _ => escape_text(text, mode),
+        }
+    }
+
+    pub fn set_locale(&mut self, locale: impl Into<String>) {
+        self.context = FormatContext::new(locale);
+        self.reset_cache();
+    }
+
+    pub fn autolink_entities(&mut self, enabled: bool) {
+        self.autolink_entities = enabled;
+        self.reset_cache();
+    }
+
+    pub fn strip_control_chars(&mut self, enabled: bool) {
+        self.strip_control_chars = enabled;
+        self.reset_cache();
+    }
+
+    pub fn wrap_at(&mut self, width: Option<usize>) {
+        self.wrap_at = width;
+        self.reset_cache();
+    }
+
+    pub fn set_bullets(&mut self, bullets: Vec<String>) {
+        self.bullets = bullets;
+        self.reset_cache();
+    }
+
+    pub fn table_fence_lang(&mut self, lang: Option<String>) {
+        self.table_fence_lang = lang;
+        self.reset_cache();
+    }
+
+    pub fn strict(&mut self, enabled: bool) {
+        self.strict = enabled;
+        self.reset_cache();
+    }
+
+    pub fn max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+        self.reset_cache();
+    }
+
+    pub fn normalize_code_lang(&mut self, enabled: bool) {
+        self.normalize_code_lang = enabled;
+        self.reset_cache();
+    }
+
+    /// Debug aid that brackets every element except plain text with its
+    /// variant name, e.g. `[Bold]*text*[/Bold]`. Ignored in HTML mode.
+    pub fn annotate(&mut self, enabled: bool) {
+        self.annotate = enabled;
+        self.reset_cache();
+    }
+
+    /// Renders `Custom` elements with an unregistered formatter as their
+    /// escaped value instead of failing with `Error::FormatterNotFound`.
+    pub fn unknown_formatter_passthrough(&mut self, enabled: bool) {
+        self.unknown_formatter_passthrough = enabled;
+        self.reset_cache();
+    }
+
+    /// Replaces the characters escaped in MarkdownV2 text, for dialects that
+    /// differ from Telegram's. Code, URLs and formatter output are unaffected.
+    pub fn markdown_escape_chars(&mut self, chars: HashSet<char>) {
+        self.markdown_escape_chars = Some(chars);
+        self.reset_cache();
+    }
+
+    /// Prefixes every line of `Pre` blocks with its right-aligned number.
+    pub fn pre_line_numbers(&mut self, enabled: bool) {
+        self.pre_line_numbers = enabled;
+        self.reset_cache();
+    }
+
+    /// Fails `generate` with `Error::Generation` as soon as its output grows
+    /// past `len` bytes, instead of building the whole string.
+    pub fn max_output_len(&mut self, len: Option<usize>) {
+        self.max_output_len = len;
+        self.reset_cache();
+    }
+
+    /// Formatter names are matched case-insensitively and must be unique:
+    /// registering `"usd"` after `"USD"` replaces it and returns the old one.
+    pub fn register_formatter(
+        &mut self,
+        formatter: Box<dyn CustomFormatter>,
+    ) -> Option<Box<dyn CustomFormatter>> {
+        self.reset_cache();
+        self.formatters
+            .insert(formatter_key(formatter.name()), formatter)
+    }
+
+    pub fn unregister_formatter(&mut self, name: &str) -> Option<Box<dyn CustomFormatter>> {
+        self.reset_cache();
+        self.formatters.remove(&formatter_key(name))
+    }
+
+    pub fn registered_formatters(&self) -> impl Iterator<Item = &str> {
+        self.formatters.values().map(|formatter| formatter.name())
+    }
+
+    pub fn has_formatter(&self, name: &str) -> bool {
+        self.formatters.contains_key(&formatter_key(name))
+    }
+
+    fn formatter(&self, name: &str) -> Option<&dyn CustomFormatter> {
         self.formatters
-            .insert(formatter.name().to_string(), formatter);
+            .get(&formatter_key(name))
+            .map(|f| f.as_ref())
     }
 
     pub fn generate<W: Write>(&self, writer: &mut W, element: &Element) -> Result<()> {
-        self.generate_element(writer, element, self.mode)
+        self.generate_all(writer, std::slice::from_ref(element))
     }
 
-    fn generate_element<W: Write>(
+    /// Renders `elements` one after another, with `max_output_len` capping
+    /// their combined output.
+    pub fn generate_all<W: Write>(&self, writer: &mut W, elements: &[Element]) -> Result<()> {
+        let mut limited = LimitedWriter::new(writer, self.max_output_len);
+        for element in elements {
+            if let Err(err) = self.generate_cached(&mut limited, element) {
+                return Err(self.cap_error(&limited).unwrap_or(err));
+            }
+        }
+        Ok(())
+    }
+
+    fn cap_error<W>(&self, writer: &LimitedWriter<'_, W>) -> Option<Error> {
+        writer.exceeded().then(|| {
+            Error::Generation(format!(
+                "output exceeds the maximum length of {} bytes",
+                self.max_output_len.unwrap_or_default()
+            ))
+        })
+    }
+
+    fn generate_cached<W: Write>(
         &self,
-        writer: &mut W,
+        writer: &mut LimitedWriter<'_, W>,
         element: &Element,
-        mode: ParseMode,
     ) -> Result<()> {
-        match element {
-            Element::Text(text) => write_fmt!(writer, "{}", escape_text(text, mode)),
+        let Some(cache) = &self.cache else {
+            return self.generate_element(writer, element, self.mode, Level::default());
+        };
+
+        let cached = cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(self.mode, element);
+        if let Some(output) = cached {
+            return write_fmt!(writer, "{}", output);
+        }
 
-            Element::Bold(elements) => {
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "*")?,
-                    ParseMode::Html => write_fmt!(writer, "<b>")?,
-                }
-                self.generate_elements(writer, elements, mode)?;
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "*")?,
-                    ParseMode::Html => write_fmt!(writer, "</b>")?,
+        // The buffer gets the writer's remaining budget, so a capped render
+        // stops as early as an uncached one and is never cached
+        let mut output = String::new();
+        let mut buffer = LimitedWriter::new(&mut output, writer.remaining());
+        let result = self.generate_element(&mut buffer, element, self.mode, Level::default());
+        if buffer.exceeded() {
+            writer.written += buffer.written;
+        }
+        result?;
+        write_fmt!(writer, "{}", output)?;
+        cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(self.mode, element, output);
+        Ok(())
+    }
+
+    /// Best-effort rendering for logs: an element that fails to render is
+    /// replaced with `[?]` instead of failing the whole output. Rendering
+    /// stops with a final `[?]` at the first element that would take the
+    /// output past `max_output_len`.
+    pub fn render_lossy(&self, elements: &[Element]) -> String {
+        let mut output = String::new();
+        let mut rendered = String::new();
+        for element in elements {
+            rendered.clear();
+            let budget = self
+                .max_output_len
+                .map(|limit| limit.saturating_sub(output.len()));
+            let mut limited = LimitedWriter::new(&mut rendered, budget);
+            match self.generate_cached(&mut limited, element) {
+                Ok(()) => output.push_str(&rendered),
+                Err(_) if limited.exceeded() => {
+                    output.push_str("[?]");
+                    break;
                 }
-                Ok(())
+                Err(_) => output.push_str("[?]"),
             }
+        }
+        output
+    }
 
-            Element::Italic(elements) => {
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "_")?,
-                    ParseMode::Html => write_fmt!(writer, "<i>")?,
-                }
-                self.generate_elements(writer, elements, mode)?;
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "_")?,
-                    ParseMode::Html => write_fmt!(writer, "</i>")?,
-                }
-                Ok(())
+    /// Wraps `element` in a `Display` impl rendering it with this generator.
+    /// Formatting fails with `fmt::Error` wherever `generate` would fail, e.g.
+    /// for a `Custom` element without a registered formatter, which makes
+    /// `format!` and `to_string` panic, so only display elements that passed
+    /// [`Generator::validate`] and use `generate` for anything else.
+    pub fn display<'a>(&'a self, element: &'a Element) -> Displayable<'a> {
+        Displayable {
+            generator: DisplayGenerator::Borrowed(self),
+            element,
+        }
+    }
+
+    pub fn validate(&self, element: &Element) -> Result<()> {
+        match element {
+            Element::Bold(elements)
+            | Element::Italic(elements)
+            | Element::Underline(elements)
+            | Element::Strikethrough(elements)
+            | Element::Spoiler(elements)
+            | Element::Quote {
+                content: elements, ..
             }
+            | Element::Group(elements)
+            | Element::Link { text: elements, .. }
+            | Element::Footnote {
+                content: elements, ..
+            } => elements.iter().try_for_each(|e| self.validate(e)),
 
-            Element::Code(code) => match mode {
-                ParseMode::MarkdownV2 => write_fmt!(writer, "`{}`", escape_code(code)),
-                ParseMode::Html => write_fmt!(writer, "<code>{}</code>", escape_html(code)),
-            },
+            Element::List(list) => self.validate_list(list),
 
-            Element::Pre(block) => match mode {
-                ParseMode::MarkdownV2 => {
-                    if let Some(lang) = &block.language {
-                        write_fmt!(
-                            writer,
-                            "```{}\n{}\n```",
-                            lang,
-                            escape_pre(block.code.as_str())
-                        )
-                    } else {
-                        write_fmt!(writer, "```\n{}\n```", escape_pre(block.code.as_str()))
-                    }
-                }
-                ParseMode::Html => {
-                    if let Some(lang) = &block.language {
-                        write_fmt!(
-                            writer,
-                            "<pre><code class=\"language-{}\">{}</code></pre>",
-                            escape_html(lang),
-                            escape_html(&block.code)
-                        )
-                    } else {
-                        write_fmt!(writer, "<pre>{}</pre>", escape_html(&block.code))
-                    }
-                }
+            Element::Table(table) => table
+                .headers
+                .iter()
+                .chain(table.rows.iter().flat_map(|row| row.cells.iter()))
+                .flat_map(|cell| cell.content.iter())
+                .try_for_each(|e| self.validate(e)),
+
+            Element::Custom { formatter, value } => match self.formatter(formatter) {
+                Some(fmt) => fmt.validate(value),
+                None if self.unknown_formatter_passthrough => Ok(()),
+                None => Err(Error::FormatterNotFound(formatter.clone())),
             },
 
-            Element::Underline(elements) => {
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "__")?,
-                    ParseMode::Html => write_fmt!(writer, "<u>")?,
-                }
-                self.generate_elements(writer, elements, mode)?;
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "__")?,
-                    ParseMode::Html => write_fmt!(writer, "</u>")?,
-                }
-                Ok(())
+            Element::RawHtml(html) if self.strict => check_raw_html(html),
+            Element::Mention { username } if self.strict => check_username(username),
+            Element::Code(code) if self.strict => check_inline_code(code),
+
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_list(&self, list: &ListNode) -> Result<()> {
+        for item in &list.items {
+            item.content.iter().try_for_each(|e| self.validate(e))?;
+            if let Some(nested) = &item.nested {
+                self.validate_list(nested)?;
             }
+        }
+        Ok(())
+    }
 
-            Element::Strikethrough(elements) => {
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "~~")?,
-                    ParseMode::Html => write_fmt!(writer, "<s>")?,
-                }
-                self.generate_elements(writer, elements, mode)?;
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "~~")?,
-                    ParseMode::Html => write_fmt!(writer, "</s>")?,
+    fn generate_element<W: Write>(
+        &self,
+        writer: &mut W,
+        element: &Element,
+        mode: ParseMode,
+        level: Level,
+    ) -> Result<()> {
+        if level.nesting > self.max_depth {
+            return Err(Error::Generation(format!(
+                "element nesting exceeds the maximum depth of {}",
+                self.max_depth
+            )));
+        }
+
+        // Empty containers would leave bare markers such as `**` behind
+        if element.is_empty() {
+            return Ok(());
+        }
+
+        if self.annotate && mode != ParseMode::Html && !matches!(element, Element::Text(_)) {
+            let name = element.variant_name();
+            write_fmt!(writer, "[{}]", name)?;
+            self.generate_content(writer, element, mode, level)?;
+            write_fmt!(writer, "[/{}]", name)
+        } else {
+            self.generate_content(writer, element, mode, level)
+        }
+    }
+
+    fn generate_content<W: Write>(
+        &self,
+        writer: &mut W,
+        element: &Element,
+        mode: ParseMode,
+        level: Level,
+    ) -> Result<()> {
+        match element {
+            Element::Text(text) => {
+                let text = if self.strip_control_chars {
+                    Cow::Owned(remove_control_chars(text))
+                } else {
+                    Cow::Borrowed(&**text)
+                };
+                let text = match self.wrap_at {
+                    Some(width) => Cow::Owned(wrap_text(&text, width.saturating_sub(level.indent))),
+                    None => text,
+                };
+
+                if self.autolink_entities {
+                    for element in autolink_text(&text) {
+                        match element {
+                            Element::Text(text) => {
+                                write_fmt!(writer, "{}", self.escape_text(&text, mode))?
+                            }
+                            other => self.generate_element(writer, &other, mode, level)?,
+                        }
+                    }
+                    Ok(())
+                } else {
+                    write_fmt!(writer, "{}", self.escape_text(&text, mode))
                 }
-                Ok(())
             }
 
-            Element::Spoiler(elements) => {
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "||")?,
-                    ParseMode::Html => write_fmt!(writer, "<tg-spoiler>")?,
-                }
-                self.generate_elements(writer, elements, mode)?;
-                match mode {
-                    ParseMode::MarkdownV2 => write_fmt!(writer, "||")?,
-                    ParseMode::Html => write_fmt!(writer, "</tg-spoiler>")?,
+            Element::Bold(elements)
+            | Element::Italic(elements)
+            | Element::Underline(elements)
+            | Element::Strikethrough(elements)
+            | Element::Spoiler(elements) => {
+                let (open, close) = markers(element, mode);
+                write_fmt!(writer, "{}", open)?;
+                self.generate_elements(writer, elements, mode, level.nested())?;
+                write_fmt!(writer, "{}", close)
+            }
+
+            // Telegram inline code is single-line, so longer code becomes a block
+            Element::Code(code) if code.contains('\n') => {
+                if self.strict {
+                    check_inline_code(code)?;
                 }
-                Ok(())
+                let block = Element::pre(code.clone(), None);
+                self.generate_content(writer, &block, mode, level)
             }
 
-            Element::Link { text, url } => match mode {
+            Element::Code(code) => match mode {
+                ParseMode::MarkdownV2 => write_fmt!(writer, "`{}`", escape_code(code)),
+                ParseMode::Html => write_fmt!(writer, "<code>{}</code>", escape_html(code)),
+                ParseMode::DiscordMarkdown => write_fmt!(writer, "{}", discord_code(code)),
+                ParseMode::SlackMrkdwn => write_fmt!(writer, "`{}`", escape_slack(code)),
+            },
+
+            Element::Pre(block) => self.generate_pre(writer, block, mode),
+
+            Element::Link { text, url, title } => match mode {
                 ParseMode::MarkdownV2 => {
                     write_fmt!(writer, "[")?;
-                    self.generate_elements(writer, text, mode)?;
+                    self.generate_elements(writer, &link_text(text), mode, level.nested())?;
                     write_fmt!(writer, "]({})", escape_url(url))
                 }
                 ParseMode::Html => {
-                    write_fmt!(writer, "<a href=\"{}\">", escape_html(url))?;
-                    self.generate_elements(writer, text, mode)?;
+                    write_fmt!(writer, "<a href=\"{}\"", escape_html(url))?;
+                    if let Some(title) = title {
+                        write_fmt!(writer, " title=\"{}\"", escape_html(title))?;
+                    }
+                    write_fmt!(writer, ">")?;
+                    self.generate_elements(writer, &link_text(text), mode, level.nested())?;
                     write_fmt!(writer, "</a>")
                 }
+                ParseMode::DiscordMarkdown => {
+                    write_fmt!(writer, "[")?;
+                    self.generate_elements(writer, &link_text(text), mode, level.nested())?;
+                    write_fmt!(writer, "]({})", discord_url(url))
+                }
+                ParseMode::SlackMrkdwn => {
+                    write_fmt!(writer, "<{}|", slack_url(url))?;
+                    self.generate_elements(writer, &link_text(text), mode, level.nested())?;
+                    write_fmt!(writer, ">")
+                }
             },
 
             Element::TextLink { text, url } => match mode {
                 ParseMode::MarkdownV2 => {
-                    write_fmt!(writer, "[{}]({})", escape_text(text, mode), escape_url(url))
+                    write_fmt!(
+                        writer,
+                        "[{}]({})",
+                        self.escape_text(text, mode),
+                        escape_url(url)
+                    )
                 }
                 ParseMode::Html => write_fmt!(
                     writer,
@@ -171,15 +573,34 @@ impl Generator {
                     escape_html(url),
                     escape_html(text)
                 ),
+                ParseMode::DiscordMarkdown => write_fmt!(
+                    writer,
+                    "[{}]({})",
+                    self.escape_text(text, mode),
+                    discord_url(url)
+                ),
+                ParseMode::SlackMrkdwn => {
+                    write_fmt!(
+                        writer,
+                        "<{}|{}>",
+                        slack_url(url),
+                        self.escape_text(text, mode)
+                    )
+                }
             },
 
-            Element::Mention { username } => write_fmt!(writer, "@{}", username),
+            Element::Mention { username } => {
+                if self.strict {
+                    check_username(username)?;
+                }
+                write_fmt!(writer, "@{}", self.escape_text(username, mode))
+            }
 
             Element::MentionId { user_id, text } => match mode {
                 ParseMode::MarkdownV2 => write_fmt!(
                     writer,
                     "[{}](tg://user?id={})",
-                    escape_text(text, mode),
+                    self.escape_text(text, mode),
                     user_id
                 ),
                 ParseMode::Html => write_fmt!(
@@ -188,15 +609,25 @@ impl Generator {
                     user_id,
                     escape_html(text)
                 ),
+                // Discord can't link to Telegram users, so only the name is kept
+                ParseMode::DiscordMarkdown | ParseMode::SlackMrkdwn => {
+                    write_fmt!(writer, "{}", self.escape_text(text, mode))
+                }
             },
 
-            Element::Hashtag(tag) => write_fmt!(writer, "#{}", tag),
+            Element::Hashtag(tag) => {
+                write_fmt!(writer, "{}", self.escape_text(&format!("#{}", tag), mode))
+            }
 
-            Element::Command { name, args } => {
+            Element::Command { name, args, bot } => {
+                write_fmt!(writer, "/{}", self.escape_text(name, mode))?;
+                if let Some(bot) = bot {
+                    write_fmt!(writer, "@{}", self.escape_text(bot, mode))?;
+                }
                 if args.is_empty() {
-                    write_fmt!(writer, "/{}", name)
+                    Ok(())
                 } else {
-                    write_fmt!(writer, "/{} {}", name, args.join(" "))
+                    write_fmt!(writer, " {}", self.escape_text(&args.join(" "), mode))
                 }
             }
 
@@ -207,36 +638,108 @@ impl Generator {
                 ParseMode::Html => {
                     write_fmt!(writer, "<tg-emoji emoji-id=\"{}\">{}</tg-emoji>", id, emoji)
                 }
+                ParseMode::DiscordMarkdown | ParseMode::SlackMrkdwn => {
+                    write_fmt!(writer, "{}", emoji)
+                }
             },
 
-            Element::List(list) => self.generate_list(writer, list, mode),
+            Element::List(list) => self.generate_list(writer, list, mode, 0, level.nested()),
 
-            Element::Table(table) => self.generate_table(writer, table, mode),
+            Element::Table(table) => self.generate_table(writer, table, mode, level.nested()),
 
-            Element::Quote(elements) => match mode {
-                ParseMode::MarkdownV2 => {
-                    let mut temp = String::new();
-                    self.generate_elements(&mut temp, elements, mode)?;
-                    let quoted = temp.lines().collect::<Vec<_>>().join("\n>");
-                    write_fmt!(writer, ">{}", quoted)
+            Element::Quote { content, cite } => {
+                let mut temp = String::new();
+                self.generate_elements(&mut temp, content, mode, level.nested())?;
+                if let Some(cite) = cite {
+                    let attribution = Element::italic(vec![Element::text(format!("— {}", cite))]);
+                    write_fmt!(temp, "\n")?;
+                    self.generate_element(&mut temp, &attribution, mode, level.nested())?;
                 }
-                ParseMode::Html => {
-                    write_fmt!(writer, "<blockquote>")?;
-                    self.generate_elements(writer, elements, mode)?;
-                    write_fmt!(writer, "</blockquote>")
+
+                match mode {
+                    ParseMode::MarkdownV2 => {
+                        let quoted = temp.lines().collect::<Vec<_>>().join("\n>");
+                        write_fmt!(writer, ">{}", quoted)
+                    }
+                    ParseMode::Html => write_fmt!(writer, "<blockquote>{}</blockquote>", temp),
+                    ParseMode::DiscordMarkdown | ParseMode::SlackMrkdwn => {
+                        let quoted = temp.lines().collect::<Vec<_>>().join("\n> ");
+                        write_fmt!(writer, "> {}", quoted)
+                    }
                 }
-            },
+            }
 
             Element::Custom { formatter, value } => {
-                if let Some(fmt) = self.formatters.get(formatter) {
-                    let result = fmt.format(value, mode)?;
+                if let Some(fmt) = self.formatter(formatter) {
+                    let result = fmt.format(value, mode, &self.context)?;
                     write_fmt!(writer, "{}", result)
+                } else if self.unknown_formatter_passthrough {
+                    write_fmt!(writer, "{}", self.escape_text(value, mode))
                 } else {
                     Err(Error::FormatterNotFound(formatter.clone()))
                 }
             }
 
-            Element::Group(elements) => self.generate_elements(writer, elements, mode),
+            Element::RawHtml(html) => match mode {
+                ParseMode::MarkdownV2 | ParseMode::DiscordMarkdown | ParseMode::SlackMrkdwn => {
+                    write_fmt!(writer, "{}", self.escape_text(html, mode))
+                }
+                ParseMode::Html => {
+                    if self.strict {
+                        check_raw_html(html)?;
+                    }
+                    write_fmt!(writer, "{}", html)
+                }
+            },
+
+            // Already rendered by the caller for each mode, so nothing is escaped
+            Element::Preformatted { markdown_v2, html } => match mode {
+                ParseMode::MarkdownV2 => write_fmt!(writer, "{}", markdown_v2),
+                ParseMode::Html => write_fmt!(writer, "{}", html),
+                ParseMode::DiscordMarkdown | ParseMode::SlackMrkdwn => Err(Error::Generation(
+                    "preformatted elements only carry MarkdownV2 and HTML renderings".to_string(),
+                )),
+            },
+
+            // Telegram can't inline images, so MarkdownV2 degrades to a link
+            Element::Image { url, alt } => match mode {
+                ParseMode::MarkdownV2 => {
+                    write_fmt!(
+                        writer,
+                        "[{}]({})",
+                        self.escape_text(alt, mode),
+                        escape_url(url)
+                    )
+                }
+                ParseMode::Html => write_fmt!(
+                    writer,
+                    "<img src=\"{}\" alt=\"{}\">",
+                    escape_html(url),
+                    escape_html(alt)
+                ),
+                ParseMode::DiscordMarkdown => {
+                    write_fmt!(
+                        writer,
+                        "[{}]({})",
+                        self.escape_text(alt, mode),
+                        discord_url(url)
+                    )
+                }
+                ParseMode::SlackMrkdwn => {
+                    write_fmt!(
+                        writer,
+                        "<{}|{}>",
+                        slack_url(url),
+                        self.escape_text(alt, mode)
+                    )
+                }
+            },
+
+            // Footnotes only become markers through `collect_footnotes`
+            Element::Group(elements)
+            | Element::Footnote {
+                content: elements, ..
+            } => self.generate_elements(writer, elements, mode, level.nested()),
         }
     }
 
@@ -245,9 +748,10 @@ impl Generator {
         writer: &mut W,
         elements: &[Element],
         mode: ParseMode,
+        level: Level,
     ) -> Result<()> {
         for element in elements {
-            self.generate_element(writer, element, mode)?;
+            self.generate_element(writer, element, mode, level)?;
         }
         Ok(())
     }
@@ -257,21 +761,37 @@ impl Generator {
         writer: &mut W,
         list: &ListNode,
         mode: ParseMode,
+        depth: usize,
+        level: Level,
     ) -> Result<()> {
         for (i, item) in list.items.iter().enumerate() {
             let prefix = match &list.style {
-                ListStyle::Bullet => "• ".to_string(),
+                ListStyle::Bullet => format!("{} ", self.bullet(depth)),
                 ListStyle::Numbered => format!("{}. ", i + 1),
+                ListStyle::Custom(marker) if marker.is_empty() => String::new(),
+                ListStyle::None => String::new(),
                 ListStyle::Custom(marker) => format!("{} ", marker),
             };
+            let indent = display_width(&prefix);
 
-            write_fmt!(writer, "{}", prefix)?;
-            self.generate_elements(writer, &item.content, mode)?;
+            let mut content = String::new();
+            self.generate_elements(&mut content, &item.content, mode, level.indented(indent))?;
+            if self.wrap_at.is_some() && indent > 0 {
+                // Continuation lines of wrapped items hang under the item text
+                content = content.replace('\n', &format!("\n{}", " ".repeat(indent)));
+            }
+            write_fmt!(writer, "{}{}", prefix, content)?;
 
             if let Some(nested) = &item.nested {
                 write_fmt!(writer, "\n")?;
                 let mut nested_content = String::new();
-                self.generate_list(&mut nested_content, nested, mode)?;
+                self.generate_list(
+                    &mut nested_content,
+                    nested,
+                    mode,
+                    depth + 1,
+                    level.nested().indented(2),
+                )?;
                 for line in nested_content.lines() {
                     write_fmt!(writer, "  {}\n", line)?;
                 }
@@ -287,24 +807,208 @@ impl Generator {
         Ok(())
     }
 
+    fn generate_pre<W: Write>(
+        &self,
+        writer: &mut W,
+        block: &PreBlock,
+        mode: ParseMode,
+    ) -> Result<()> {
+        let code = self.pre_code(&block.code);
+        match mode {
+            ParseMode::MarkdownV2 => {
+                if let Some(lang) = self.code_lang(block) {
+                    write_fmt!(writer, "```{}\n{}\n```", lang, escape_pre(&code))
+                } else {
+                    write_fmt!(writer, "```\n{}\n```", escape_pre(&code))
+                }
+            }
+            ParseMode::Html => {
+                if let Some(lang) = self.code_lang(block) {
+                    write_fmt!(
+                        writer,
+                        "<pre><code class=\"language-{}\">{}</code></pre>",
+                        escape_html(lang),
+                        escape_html(&code)
+                    )
+                } else {
+                    write_fmt!(writer, "<pre>{}</pre>", escape_html(&code))
+                }
+            }
+            ParseMode::DiscordMarkdown => write_fmt!(
+                writer,
+                "```{}\n{}\n```",
+                self.code_lang(block).unwrap_or(""),
+                discord_pre(&code)
+            ),
+            // Slack doesn't highlight code, so the language is dropped
+            ParseMode::SlackMrkdwn => {
+                write_fmt!(writer, "```\n{}\n```", escape_slack(&code))
+            }
+        }
+    }
+
+    // Right-aligns line numbers to the widest one
+    fn pre_code<'a>(&self, code: &'a str) -> Cow<'a, str> {
+        if !self.pre_line_numbers {
+            return Cow::Borrowed(code);
+        }
+        let width = code.lines().count().to_string().len();
+        Cow::Owned(
+            code.lines()
+                .enumerate()
+                .map(|(i, line)| format!("{:>width$} {}", i + 1, line, width = width))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    fn code_lang<'a>(&self, block: &'a PreBlock) -> Option<&'a str> {
+        let lang = block.language.as_deref()?;
+        if self.normalize_code_lang {
+            highlighted_lang(lang)
+        } else {
+            Some(lang)
+        }
+    }
+
+    fn bullet(&self, depth: usize) -> &str {
+        if self.bullets.is_empty() {
+            "•"
+        } else {
+            &self.bullets[depth % self.bullets.len()]
+        }
+    }
+
     fn generate_table<W: Write>(
         &self,
         writer: &mut W,
         table: &TableNode,
         mode: ParseMode,
+        level: Level,
     ) -> Result<()> {
+        let table = resolve_align(table);
+        let table = table.as_ref();
+        if table.style == TableStyle::Html {
+            return self.generate_html_table(writer, table, mode, level);
+        }
+
+        let body = self.table_body(table, Some(mode))?;
+
+        match mode {
+            ParseMode::MarkdownV2 => {
+                let lang = self.table_fence_lang.as_deref().unwrap_or("");
+                write_fmt!(writer, "```{}\n{}```", lang, body)
+            }
+            ParseMode::Html => write_fmt!(writer, "<pre>{}</pre>", body.trim_end_matches('\n')),
+            ParseMode::DiscordMarkdown => {
+                let lang = self.table_fence_lang.as_deref().unwrap_or("");
+                write_fmt!(writer, "```{}\n{}```", lang, body)
+            }
+            ParseMode::SlackMrkdwn => write_fmt!(writer, "```\n{}```", body),
+        }
+    }
+
+    /// Lays `table` out as monospace text with no markup or escaping, the
+    /// way it reads inside a `pre` entity. HTML tables use the Unicode layout.
+    pub(crate) fn plain_table(&self, table: &TableNode) -> Result<String> {
+        let table = resolve_align(table);
+        let body = self.table_body(table.as_ref(), None)?;
+        Ok(body.trim_end_matches('\n').to_string())
+    }
+
+    // `None` renders the layout as plain text
+    fn table_body(&self, table: &TableNode, mode: Option<ParseMode>) -> Result<String> {
         let all_rows: Vec<&[TableCell]> = std::iter::once(table.headers.as_slice())
             .chain(table.rows.iter().map(|r| r.cells.as_slice()))
             .collect();
 
         let col_widths = calculate_column_widths(&all_rows, mode)?;
 
+        let mut body = String::new();
         match table.style {
-            TableStyle::Unicode => self.generate_unicode_table(writer, table, &col_widths, mode),
-            TableStyle::Ascii => self.generate_ascii_table(writer, table, &col_widths, mode),
-            TableStyle::Minimal => self.generate_minimal_table(writer, table, &col_widths, mode),
-            TableStyle::Compact => self.generate_compact_table(writer, table, &col_widths, mode),
+            // `generate_table` renders HTML tables itself, so only plain text gets here
+            TableStyle::Unicode | TableStyle::Html => {
+                self.generate_unicode_table(&mut body, table, &col_widths, mode)?
+            }
+            TableStyle::Ascii => self.generate_ascii_table(&mut body, table, &col_widths, mode)?,
+            TableStyle::Minimal => {
+                self.generate_minimal_table(&mut body, table, &col_widths, mode)?
+            }
+            TableStyle::Compact => {
+                self.generate_compact_table(&mut body, table, &col_widths, mode)?
+            }
+        }
+        Ok(body)
+    }
+
+    fn generate_html_table<W: Write>(
+        &self,
+        writer: &mut W,
+        table: &TableNode,
+        mode: ParseMode,
+        level: Level,
+    ) -> Result<()> {
+        if mode != ParseMode::Html {
+            return Err(Error::InvalidTable(
+                "TableStyle::Html requires ParseMode::Html".to_string(),
+            ));
+        }
+
+        write_fmt!(writer, "<table><thead><tr>")?;
+        for cell in &table.headers {
+            self.generate_html_cell(writer, cell, "th", mode, level)?;
+        }
+        write_fmt!(writer, "</tr></thead><tbody>")?;
+
+        for row in &table.rows {
+            write_fmt!(writer, "<tr>")?;
+            for cell in &row.cells {
+                self.generate_html_cell(writer, cell, "td", mode, level)?;
+            }
+            write_fmt!(writer, "</tr>")?;
         }
+
+        write_fmt!(writer, "</tbody></table>")
+    }
+
+    fn generate_html_cell<W: Write>(
+        &self,
+        writer: &mut W,
+        cell: &TableCell,
+        tag: &str,
+        mode: ParseMode,
+        level: Level,
+    ) -> Result<()> {
+        let align = match cell.align {
+            CellAlign::Left | CellAlign::Inherit => "left",
+            CellAlign::Center => "center",
+            CellAlign::Right => "right",
+        };
+
+        write_fmt!(writer, "<{} style=\"text-align:{}\"", tag, align)?;
+        if cell.colspan > 1 {
+            write_fmt!(writer, " colspan=\"{}\"", cell.colspan)?;
+        }
+        if cell.rowspan > 1 {
+            write_fmt!(writer, " rowspan=\"{}\"", cell.rowspan)?;
+        }
+        write_fmt!(writer, ">")?;
+
+        match cell.style {
+            CellStyle::Normal => self.generate_elements(writer, &cell.content, mode, level)?,
+            CellStyle::Bold => {
+                write_fmt!(writer, "<b>")?;
+                self.generate_elements(writer, &cell.content, mode, level)?;
+                write_fmt!(writer, "</b>")?;
+            }
+            CellStyle::Italic => {
+                write_fmt!(writer, "<i>")?;
+                self.generate_elements(writer, &cell.content, mode, level)?;
+                write_fmt!(writer, "</i>")?;
+            }
+        }
+
+        write_fmt!(writer, "</{}>", tag)
     }
 
     fn generate_unicode_table<W: Write>(
@@ -312,11 +1016,11 @@ impl Generator {
         writer: &mut W,
         table: &TableNode,
         col_widths: &[usize],
-        mode: ParseMode,
+        mode: Option<ParseMode>,
     ) -> Result<()> {
         write_fmt!(
             writer,
-            "```\n┌{}┐\n",
+            "┌{}┐\n",
             col_widths
                 .iter()
                 .map(|&w| "─".repeat(w + 2))
@@ -342,7 +1046,7 @@ impl Generator {
 
         write_fmt!(
             writer,
-            "└{}┘\n```",
+            "└{}┘\n",
             col_widths
                 .iter()
                 .map(|&w| "─".repeat(w + 2))
@@ -358,11 +1062,11 @@ impl Generator {
         writer: &mut W,
         table: &TableNode,
         col_widths: &[usize],
-        mode: ParseMode,
+        mode: Option<ParseMode>,
     ) -> Result<()> {
         write_fmt!(
             writer,
-            "```\n+{}+\n",
+            "+{}+\n",
             col_widths
                 .iter()
                 .map(|&w| "-".repeat(w + 2))
@@ -388,7 +1092,7 @@ impl Generator {
 
         write_fmt!(
             writer,
-            "+{}+\n```",
+            "+{}+\n",
             col_widths
                 .iter()
                 .map(|&w| "-".repeat(w + 2))
@@ -404,10 +1108,8 @@ impl Generator {
         writer: &mut W,
         table: &TableNode,
         col_widths: &[usize],
-        mode: ParseMode,
+        mode: Option<ParseMode>,
     ) -> Result<()> {
-        write_fmt!(writer, "```\n")?;
-
         self.format_table_row(writer, &table.headers, col_widths, mode, " ")?;
         write_fmt!(
             writer,
@@ -424,7 +1126,6 @@ impl Generator {
             write_fmt!(writer, "\n")?;
         }
 
-        write_fmt!(writer, "```")?;
         Ok(())
     }
 
@@ -433,10 +1134,8 @@ impl Generator {
         writer: &mut W,
         table: &TableNode,
         col_widths: &[usize],
-        mode: ParseMode,
+        mode: Option<ParseMode>,
     ) -> Result<()> {
-        write_fmt!(writer, "```\n")?;
-
         self.format_table_row(writer, &table.headers, col_widths, mode, " ")?;
         write_fmt!(writer, "\n")?;
 
@@ -445,7 +1144,6 @@ impl Generator {
             write_fmt!(writer, "\n")?;
         }
 
-        write_fmt!(writer, "```")?;
         Ok(())
     }
 
@@ -454,7 +1152,7 @@ impl Generator {
         writer: &mut W,
         cells: &[TableCell],
         col_widths: &[usize],
-        _mode: ParseMode,
+        mode: Option<ParseMode>,
         separator: &str,
     ) -> Result<()> {
         write_fmt!(writer, "{}", separator)?;
@@ -465,18 +1163,46 @@ impl Generator {
                     .content
                     .iter()
                     .map(|e| match e {
-                        Element::Text(t) => t.clone(),
+                        Element::Text(t) => t.to_string(),
                         _ => String::new(),
                     })
                     .collect::<String>();
 
-                let padded = match cell.align {
-                    CellAlign::Left => format!(" {:<width$} ", content, width = col_widths[i]),
-                    CellAlign::Center => format!(" {:^width$} ", content, width = col_widths[i]),
-                    CellAlign::Right => format!(" {:>width$} ", content, width = col_widths[i]),
+                let escaped = match mode {
+                    Some(ParseMode::MarkdownV2) => escape_pre(&content),
+                    Some(ParseMode::Html) => escape_html(&content),
+                    Some(ParseMode::DiscordMarkdown) => discord_pre(&content),
+                    Some(ParseMode::SlackMrkdwn) => escape_slack(&content),
+                    None => content.clone(),
+                };
+
+                let styled = match (&cell.style, mode) {
+                    (CellStyle::Normal, _) => escaped,
+                    (CellStyle::Bold, Some(ParseMode::MarkdownV2)) => format!("*{}*", escaped),
+                    (CellStyle::Bold, Some(ParseMode::Html)) => format!("<b>{}</b>", escaped),
+                    (CellStyle::Italic, Some(ParseMode::MarkdownV2)) => format!("_{}_", escaped),
+                    (CellStyle::Italic, Some(ParseMode::Html)) => format!("<i>{}</i>", escaped),
+                    // Discord shows markers inside code blocks literally
+                    (_, Some(ParseMode::DiscordMarkdown | ParseMode::SlackMrkdwn) | None) => {
+                        escaped
+                    }
+                };
+
+                let width = display_width(&content) + style_marker_width(&cell.style, mode);
+                let padding = col_widths[i].saturating_sub(width);
+                let (left, right) = match cell.align {
+                    CellAlign::Left | CellAlign::Inherit => (0, padding),
+                    CellAlign::Center => (padding / 2, padding - padding / 2),
+                    CellAlign::Right => (padding, 0),
                 };
 
-                write_fmt!(writer, "{}", padded)?;
+                write_fmt!(
+                    writer,
+                    " {}{}{} ",
+                    " ".repeat(left),
+                    styled,
+                    " ".repeat(right)
+                )?;
                 write_fmt!(writer, "{}", separator)?;
             }
         }
@@ -485,7 +1211,88 @@ impl Generator {
     }
 }
 
-fn calculate_column_widths(rows: &[&[TableCell]], _mode: ParseMode) -> Result<Vec<usize>> {
+pub(crate) fn autolink_text(text: &str) -> Vec<Element> {
+    let mut elements = Vec::new();
+    let mut last_end = 0;
+
+    for mat in ENTITY_REGEX.find_iter(text) {
+        let preceded_by_word = text[..mat.start()]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '@' | '#'));
+        if preceded_by_word {
+            continue;
+        }
+
+        if mat.start() > last_end {
+            elements.push(Element::text(&text[last_end..mat.start()]));
+        }
+
+        let name = &mat.as_str()[1..];
+        if mat.as_str().starts_with('@') {
+            elements.push(Element::mention(name));
+        } else {
+            elements.push(Element::hashtag(name));
+        }
+        last_end = mat.end();
+    }
+
+    if last_end < text.len() || elements.is_empty() {
+        elements.push(Element::text(&text[last_end..]));
+    }
+
+    elements
+}
+
+fn remove_control_chars(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &c)| {
+            if c == '\u{200D}' {
+                let prev = i.checked_sub(1).and_then(|p| chars.get(p));
+                let next = chars.get(i + 1);
+                return prev.is_some_and(|&p| is_emoji_component(p))
+                    && next.is_some_and(|&n| is_emoji_component(n));
+            }
+            c == '\n' || !(c.is_control() || is_format_char(c))
+        })
+        .map(|(_, &c)| c)
+        .collect()
+}
+
+fn is_format_char(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{0600}'..='\u{0605}'
+            | '\u{061C}'
+            | '\u{06DD}'
+            | '\u{070F}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{206F}'
+            | '\u{FEFF}'
+            | '\u{FFF9}'..='\u{FFFB}'
+            | '\u{E0001}'
+    )
+}
+
+fn is_emoji_component(c: char) -> bool {
+    matches!(
+        c,
+        '\u{2300}'..='\u{23FF}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2B00}'..='\u{2BFF}'
+            | '\u{FE0F}'
+            | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
+
+fn calculate_column_widths(rows: &[&[TableCell]], mode: Option<ParseMode>) -> Result<Vec<usize>> {
     if rows.is_empty() {
         return Ok(Vec::new());
     }
@@ -500,10 +1307,11 @@ fn calculate_column_widths(rows: &[&[TableCell]], _mode: ParseMode) -> Result<Ve
                     .content
                     .iter()
                     .map(|e| match e {
-                        Element::Text(t) => t.len(),
+                        Element::Text(t) => display_width(t),
                         _ => 0,
                     })
-                    .sum::<usize>();
+                    .sum::<usize>()
+                    + style_marker_width(&cell.style, mode);
                 widths[i] = widths[i].max(content_len);
             }
         }
@@ -512,6 +1320,289 @@ fn calculate_column_widths(rows: &[&[TableCell]], _mode: ParseMode) -> Result<Ve
     Ok(widths)
 }
 
+const TELEGRAM_HTML_TAGS: &[&str] = &[
+    "b",
+    "strong",
+    "i",
+    "em",
+    "u",
+    "ins",
+    "s",
+    "strike",
+    "del",
+    "span",
+    "tg-spoiler",
+    "a",
+    "tg-emoji",
+    "code",
+    "pre",
+    "blockquote",
+];
+
+fn check_inline_code(code: &str) -> Result<()> {
+    if code.contains('\n') {
+        return Err(Error::Generation(format!(
+            "inline code can't span lines, use a pre block instead: {:?}",
+            code
+        )));
+    }
+    Ok(())
+}
+
+fn check_raw_html(html: &str) -> Result<()> {
+    let mut open_tags: Vec<String> = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        let end = rest[start..]
+            .find('>')
+            .map(|i| start + i)
+            .ok_or_else(|| Error::Generation(format!("unterminated tag in raw HTML: {}", html)))?;
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        let (closing, body) = match tag.strip_prefix('/') {
+            Some(body) => (true, body),
+            None => (false, tag),
+        };
+        let name = body
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+
+        if !TELEGRAM_HTML_TAGS.contains(&name.as_str()) {
+            return Err(Error::Generation(format!(
+                "unsupported tag <{}> in raw HTML",
+                name
+            )));
+        }
+
+        if closing {
+            match open_tags.pop() {
+                Some(open) if open == name => {}
+                Some(open) => {
+                    return Err(Error::Generation(format!(
+                        "mismatched </{}> in raw HTML, expected </{}>",
+                        name, open
+                    )))
+                }
+                None => {
+                    return Err(Error::Generation(format!(
+                        "unexpected </{}> in raw HTML",
+                        name
+                    )))
+                }
+            }
+        } else if !body.trim_end().ends_with('/') {
+            open_tags.push(name);
+        }
+    }
+
+    match open_tags.pop() {
+        Some(open) => Err(Error::Generation(format!(
+            "unclosed <{}> in raw HTML",
+            open
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Telegram can't nest links, so a link inside link text keeps only its text
+pub(crate) fn link_text(text: &[Element]) -> Cow<'_, [Element]> {
+    if !text.iter().any(has_link) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut text = text.to_vec();
+    for element in &mut text {
+        element.walk_mut(&mut |e| {
+            if is_link(e) {
+                *e = Element::text(e.text_iter().collect::<String>());
+            }
+        });
+    }
+    Cow::Owned(text)
+}
+
+fn is_link(element: &Element) -> bool {
+    matches!(
+        element,
+        Element::Link { .. }
+            | Element::TextLink { .. }
+            | Element::MentionId { .. }
+            | Element::Image { .. }
+    )
+}
+
+fn has_link(element: &Element) -> bool {
+    match element {
+        Element::Bold(elements)
+        | Element::Italic(elements)
+        | Element::Underline(elements)
+        | Element::Strikethrough(elements)
+        | Element::Spoiler(elements)
+        | Element::Group(elements)
+        | Element::Footnote {
+            content: elements, ..
+        } => elements.iter().any(has_link),
+        _ => is_link(element),
+    }
+}
+
+// Renderers only see concrete alignments once inheriting cells take the table's
+// default_align
+fn resolve_align(table: &TableNode) -> Cow<'_, TableNode> {
+    let inherits = table
+        .headers
+        .iter()
+        .chain(table.rows.iter().flat_map(|row| row.cells.iter()))
+        .any(|cell| cell.align == CellAlign::Inherit);
+    if !inherits || table.default_align == CellAlign::Inherit {
+        return Cow::Borrowed(table);
+    }
+
+    let mut table = table.clone();
+    let align = table.default_align.clone();
+    for cell in table
+        .headers
+        .iter_mut()
+        .chain(table.rows.iter_mut().flat_map(|row| row.cells.iter_mut()))
+    {
+        if cell.align == CellAlign::Inherit {
+            cell.align = align.clone();
+        }
+    }
+    Cow::Owned(table)
+}
+
+fn markers(element: &Element, mode: ParseMode) -> (&'static str, &'static str) {
+    match (element, mode) {
+        (Element::Bold(_), ParseMode::MarkdownV2) => ("*", "*"),
+        (Element::Bold(_), ParseMode::Html) => ("<b>", "</b>"),
+        (Element::Bold(_), ParseMode::DiscordMarkdown) => ("**", "**"),
+        (Element::Italic(_), ParseMode::MarkdownV2) => ("_", "_"),
+        (Element::Italic(_), ParseMode::Html) => ("<i>", "</i>"),
+        (Element::Italic(_), ParseMode::DiscordMarkdown) => ("*", "*"),
+        (Element::Underline(_), ParseMode::MarkdownV2 | ParseMode::DiscordMarkdown) => ("__", "__"),
+        (Element::Underline(_), ParseMode::Html) => ("<u>", "</u>"),
+        (Element::Strikethrough(_), ParseMode::MarkdownV2) => ("~", "~"),
+        (Element::Strikethrough(_), ParseMode::DiscordMarkdown) => ("~~", "~~"),
+        (Element::Strikethrough(_), ParseMode::Html) => ("<s>", "</s>"),
+        (Element::Spoiler(_), ParseMode::MarkdownV2 | ParseMode::DiscordMarkdown) => ("||", "||"),
+        (Element::Spoiler(_), ParseMode::Html) => ("<tg-spoiler>", "</tg-spoiler>"),
+        (Element::Bold(_), ParseMode::SlackMrkdwn) => ("*", "*"),
+        (Element::Italic(_), ParseMode::SlackMrkdwn) => ("_", "_"),
+        (Element::Strikethrough(_), ParseMode::SlackMrkdwn) => ("~", "~"),
+        // Slack has no underline or spoiler, so their content stays plain
+        _ => ("", ""),
+    }
+}
+
+fn style_marker_width(style: &CellStyle, mode: Option<ParseMode>) -> usize {
+    match (style, mode) {
+        (CellStyle::Bold | CellStyle::Italic, Some(ParseMode::MarkdownV2)) => 2,
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Level {
+    nesting: usize,
+    indent: usize,
+}
+
+impl Level {
+    fn nested(self) -> Self {
+        Self {
+            nesting: self.nesting + 1,
+            ..self
+        }
+    }
+
+    fn indented(self, width: usize) -> Self {
+        Self {
+            indent: self.indent + width,
+            ..self
+        }
+    }
+}
+
+// Languages Telegram clients highlight; anything else renders as plain code
+const CODE_LANGS: &[&str] = &[
+    "bash",
+    "c",
+    "cpp",
+    "csharp",
+    "css",
+    "diff",
+    "dockerfile",
+    "go",
+    "haskell",
+    "html",
+    "java",
+    "javascript",
+    "json",
+    "kotlin",
+    "lua",
+    "markdown",
+    "perl",
+    "php",
+    "python",
+    "ruby",
+    "rust",
+    "scala",
+    "sql",
+    "swift",
+    "toml",
+    "typescript",
+    "xml",
+    "yaml",
+];
+
+const CODE_LANG_ALIASES: &[(&str, &str)] = &[
+    ("c#", "csharp"),
+    ("c++", "cpp"),
+    ("cs", "csharp"),
+    ("docker", "dockerfile"),
+    ("golang", "go"),
+    ("js", "javascript"),
+    ("kt", "kotlin"),
+    ("md", "markdown"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("sh", "bash"),
+    ("shell", "bash"),
+    ("ts", "typescript"),
+    ("yml", "yaml"),
+    ("zsh", "bash"),
+];
+
+fn highlighted_lang(lang: &str) -> Option<&'static str> {
+    let lang = lang.trim().to_lowercase();
+    CODE_LANGS
+        .iter()
+        .find(|known| **known == lang)
+        .copied()
+        .or_else(|| {
+            CODE_LANG_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == lang)
+                .map(|(_, known)| *known)
+        })
+}
+
+fn formatter_key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// Escapes plain text for insertion into already rendered output.
+/// Not suitable for URLs or code, which use different escaping rules.
+pub fn escape(text: &str, mode: ParseMode) -> String {
+    escape_text(text, mode)
+}
+
 fn escape_text(text: &str, mode: ParseMode) -> String {
     match mode {
         ParseMode::MarkdownV2 => text
@@ -525,22 +1616,61 @@ fn escape_text(text: &str, mode: ParseMode) -> String {
             })
             .collect(),
         ParseMode::Html => escape_html(text),
+        // Unlike MarkdownV2, punctuation such as `.` and `!` is left alone
+        ParseMode::DiscordMarkdown => text
+            .chars()
+            .map(|c| match c {
+                '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '[' | ']' | '#' => format!("\\{}", c),
+                _ => c.to_string(),
+            })
+            .collect(),
+        ParseMode::SlackMrkdwn => escape_slack(text),
     }
 }
 
-fn escape_code(code: &str) -> String {
+pub(crate) fn escape_code(code: &str) -> String {
     code.replace('\\', "\\\\").replace('`', "\\`")
 }
 
-fn escape_pre(code: &str) -> String {
+pub(crate) fn escape_pre(code: &str) -> String {
     code.replace('\\', "\\\\").replace('`', "\\`")
 }
 
-fn escape_url(url: &str) -> String {
+// Discord code spans have no escapes, so a longer fence is used instead
+pub(crate) fn discord_code(code: &str) -> String {
+    if code.contains('`') {
+        format!("`` {} ``", code)
+    } else {
+        format!("`{}`", code)
+    }
+}
+
+pub(crate) fn discord_pre(code: &str) -> String {
+    code.replace("```", "`\u{200b}``")
+}
+
+fn discord_url(url: &str) -> String {
+    url.replace(')', "%29")
+}
+
+// Slack only treats `&`, `<` and `>` as control characters
+pub(crate) fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn slack_url(url: &str) -> String {
+    escape_slack(url).replace('|', "%7C")
+}
+
+pub(crate) fn escape_url(url: &str) -> String {
     url.replace(')', "\\)")
 }
 
-fn escape_html(text: &str) -> String {
+// Also used for double-quoted attributes: any URL scheme, `tg://` included,
+// passes through, and `&` in query strings becomes `&amp;`
+pub(crate) fn escape_html(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -0,0 +1,45 @@
+use crate::ast::Element;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub struct Interner {
+    strings: HashSet<Arc<str>>,
+    limit: usize,
+}
+
+impl Interner {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            strings: HashSet::new(),
+            limit,
+        }
+    }
+
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(existing) = self.strings.get(s) {
+            return Arc::clone(existing);
+        }
+
+        let interned: Arc<str> = Arc::from(s);
+        if self.strings.len() < self.limit {
+            self.strings.insert(Arc::clone(&interned));
+        }
+        interned
+    }
+
+    pub fn text(&mut self, s: &str) -> Element {
+        Element::Text(self.intern(s))
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.strings.clear();
+    }
+}
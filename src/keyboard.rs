@@ -0,0 +1,59 @@
+use crate::ast::{text_iter, Element};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Keyboard {
+    inline_keyboard: Vec<Vec<Button>>,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn row(mut self, buttons: impl IntoIterator<Item = Button>) -> Self {
+        self.inline_keyboard.push(buttons.into_iter().collect());
+        self
+    }
+
+    pub fn rows(&self) -> &[Vec<Button>] {
+        &self.inline_keyboard
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Button {
+    pub text: String,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub action: ButtonAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ButtonAction {
+    Url(String),
+    CallbackData(String),
+}
+
+impl Button {
+    pub fn url(text: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            action: ButtonAction::Url(url.into()),
+        }
+    }
+
+    pub fn callback(text: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            action: ButtonAction::CallbackData(data.into()),
+        }
+    }
+}
+
+// Telegram shows button labels verbatim, so formatting is dropped
+pub fn label(elements: &[Element]) -> String {
+    text_iter(elements).collect()
+}
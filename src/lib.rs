@@ -1,19 +1,45 @@
 pub mod ast;
+mod cache;
+pub mod clock;
 pub mod conditional;
+pub mod entities;
 pub mod error;
+pub mod escape;
 pub mod formatter;
 pub mod generator;
+#[cfg(feature = "intern")]
+pub mod intern;
+pub mod keyboard;
+pub mod message;
+pub mod normalize;
 pub mod parser;
+pub mod split;
+pub mod stats;
+pub mod telegraph;
+pub mod template;
 pub mod token;
+mod width;
 
 pub use ast::*;
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use entities::{check_entity_limits, from_entities, to_entities, EntityKind, MessageEntity};
 pub use error::{Error, Result};
-pub use formatter::CustomFormatter;
-pub use generator::{Generate, Generator, ParseMode};
-pub use parser::{parse, Parse, ParseStream};
+pub use formatter::{CustomFormatter, FormatContext};
+pub use generator::{escape, Displayable, Generate, Generator, ParseMode};
+pub use keyboard::{Button, ButtonAction, Keyboard};
+pub use message::{Message, MessageBuilder};
+pub use normalize::{collapse_nested, merge_adjacent};
+pub use parser::{
+    parse, parse_collect, parse_with_dialect, parse_with_max_depth, Dialect, Parse, ParseStream,
+};
+pub use split::{message_count, message_count_with, split_message, split_message_with};
+pub use stats::{stats, MessageStats};
+pub use telegraph::render_telegraph;
+pub use template::template;
 pub use token::Token;
+pub use width::display_width;
 
-pub use msg_macro::{el, msg};
+pub use msg_macro::{el, el_one, msg};
 
 #[cfg(test)]
 mod tests;
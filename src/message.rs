@@ -0,0 +1,172 @@
+use crate::ast::*;
+use crate::error::Result;
+use crate::generator::{Generate, Generator, ParseMode};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Message {
+    elements: Vec<Element>,
+}
+
+impl Message {
+    pub fn new(elements: Vec<Element>) -> Self {
+        Self { elements }
+    }
+
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::default()
+    }
+
+    pub fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Element> {
+        self.elements.iter()
+    }
+
+    pub fn into_elements(self) -> Vec<Element> {
+        self.elements
+    }
+
+    /// Renders the whole message with a configured generator, so settings
+    /// like `max_output_len` apply to the message as a whole.
+    pub fn generate_with(&self, generator: &Generator) -> Result<String> {
+        let mut output = String::new();
+        generator.generate_all(&mut output, &self.elements)?;
+        Ok(output)
+    }
+}
+
+impl From<Vec<Element>> for Message {
+    fn from(elements: Vec<Element>) -> Self {
+        Self::new(elements)
+    }
+}
+
+impl From<Message> for Vec<Element> {
+    fn from(message: Message) -> Self {
+        message.elements
+    }
+}
+
+impl IntoIterator for Message {
+    type Item = Element;
+    type IntoIter = std::vec::IntoIter<Element>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Message {
+    type Item = &'a Element;
+    type IntoIter = std::slice::Iter<'a, Element>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.iter()
+    }
+}
+
+impl FromIterator<Element> for Message {
+    fn from_iter<I: IntoIterator<Item = Element>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl Extend<Element> for Message {
+    fn extend<I: IntoIterator<Item = Element>>(&mut self, iter: I) {
+        self.elements.extend(iter);
+    }
+}
+
+impl Generate for Message {
+    fn generate(&self, mode: ParseMode) -> Result<String> {
+        self.generate_with(&Generator::new(mode))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MessageBuilder {
+    elements: Vec<Element>,
+}
+
+impl MessageBuilder {
+    pub fn element(mut self, element: Element) -> Self {
+        self.elements.push(element);
+        self
+    }
+
+    pub fn text(self, text: impl Into<String>) -> Self {
+        self.element(Element::text(text.into()))
+    }
+
+    pub fn bold(self, text: impl Into<String>) -> Self {
+        self.element(Element::bold(vec![Element::text(text.into())]))
+    }
+
+    pub fn italic(self, text: impl Into<String>) -> Self {
+        self.element(Element::italic(vec![Element::text(text.into())]))
+    }
+
+    pub fn code(self, code: impl Into<String>) -> Self {
+        self.element(Element::code(code))
+    }
+
+    pub fn link(self, text: impl Into<String>, url: impl Into<String>) -> Self {
+        self.element(Element::link(vec![Element::text(text.into())], url))
+    }
+
+    pub fn newline(self) -> Self {
+        self.text("\n")
+    }
+
+    pub fn list<I, S>(self, items: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let items = items
+            .into_iter()
+            .map(|item| ListItem {
+                content: vec![Element::text(item.into())],
+                nested: None,
+            })
+            .collect();
+        self.element(Element::List(ListNode {
+            style: ListStyle::Bullet,
+            items,
+        }))
+    }
+
+    pub fn table<H, R, C>(self, headers: H, rows: R) -> Self
+    where
+        H: IntoIterator,
+        H::Item: ToString,
+        R: IntoIterator<Item = C>,
+        C: IntoIterator,
+        C::Item: ToString,
+    {
+        let cell = |value: &dyn ToString| TableCell {
+            content: vec![Element::text(value.to_string())],
+            ..TableCell::default()
+        };
+        let headers = headers.into_iter().map(|h| cell(&h)).collect();
+        let rows = rows
+            .into_iter()
+            .map(|row| TableRow {
+                cells: row.into_iter().map(|c| cell(&c)).collect(),
+            })
+            .collect();
+        self.element(Element::Table(TableNode {
+            headers,
+            rows,
+            style: TableStyle::Unicode,
+            rules: Vec::new(),
+            default_align: CellAlign::Left,
+        }))
+    }
+
+    pub fn build(self) -> Message {
+        Message::new(self.elements)
+    }
+}
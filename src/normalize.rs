@@ -0,0 +1,103 @@
+use crate::ast::{Element, ListNode};
+use std::mem::{discriminant, take, Discriminant};
+
+// Telegram renders bold inside bold as a single bold run, so the inner
+// markers only cost entities
+pub fn collapse_nested(mut elements: Vec<Element>) -> Vec<Element> {
+    for element in &mut elements {
+        element.walk_mut(&mut |e| {
+            let kind = discriminant(&*e);
+            if let Some(children) = formatting_children(e) {
+                *children = splice(kind, take(children));
+            }
+        });
+    }
+    elements
+}
+
+// Joins neighbouring runs such as `Bold("a"), Bold("b")` into one entity.
+// Only formatting of the same type is merged; links, quotes and text are left alone.
+pub fn merge_adjacent(elements: Vec<Element>) -> Vec<Element> {
+    let mut elements = merge_runs(elements);
+    for element in &mut elements {
+        element.walk_mut(&mut |e| match e {
+            Element::Bold(children)
+            | Element::Italic(children)
+            | Element::Underline(children)
+            | Element::Strikethrough(children)
+            | Element::Spoiler(children)
+            | Element::Group(children)
+            | Element::Link { text: children, .. }
+            | Element::Quote {
+                content: children, ..
+            }
+            | Element::Footnote {
+                content: children, ..
+            } => *children = merge_runs(take(children)),
+            Element::List(list) => merge_list(list),
+            Element::Table(table) => {
+                for cell in table
+                    .headers
+                    .iter_mut()
+                    .chain(table.rows.iter_mut().flat_map(|row| row.cells.iter_mut()))
+                {
+                    cell.content = merge_runs(take(&mut cell.content));
+                }
+            }
+            _ => {}
+        });
+    }
+    elements
+}
+
+fn merge_runs(elements: Vec<Element>) -> Vec<Element> {
+    let mut merged: Vec<Element> = Vec::with_capacity(elements.len());
+    for mut element in elements {
+        if let Some(last) = merged.last_mut() {
+            if discriminant(&*last) == discriminant(&element) {
+                if let (Some(into), Some(from)) =
+                    (formatting_children(last), formatting_children(&mut element))
+                {
+                    into.append(from);
+                    continue;
+                }
+            }
+        }
+        merged.push(element);
+    }
+    merged
+}
+
+fn merge_list(list: &mut ListNode) {
+    for item in &mut list.items {
+        item.content = merge_runs(take(&mut item.content));
+        if let Some(nested) = &mut item.nested {
+            merge_list(nested);
+        }
+    }
+}
+
+fn splice(kind: Discriminant<Element>, children: Vec<Element>) -> Vec<Element> {
+    let mut spliced = Vec::with_capacity(children.len());
+    for mut child in children {
+        if discriminant(&child) == kind {
+            if let Some(inner) = formatting_children(&mut child) {
+                spliced.extend(splice(kind, take(inner)));
+                continue;
+            }
+        }
+        spliced.push(child);
+    }
+    spliced
+}
+
+fn formatting_children(element: &mut Element) -> Option<&mut Vec<Element>> {
+    match element {
+        Element::Bold(children)
+        | Element::Italic(children)
+        | Element::Underline(children)
+        | Element::Strikethrough(children)
+        | Element::Spoiler(children) => Some(children),
+        _ => None,
+    }
+}
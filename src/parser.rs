@@ -6,14 +6,73 @@ pub trait Parse: Sized {
     fn parse(input: ParseStream) -> Result<Self>;
 }
 
+const DEFAULT_MAX_DEPTH: usize = 100;
+
+/// Selects the strikethrough syntax: Telegram MarkdownV2 uses `~text~`,
+/// CommonMark uses `~~text~~` and leaves a single `~` as text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Telegram,
+    CommonMark,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Closer {
+    Star,
+    DoubleStar,
+    Underscore,
+    DoubleUnderscore,
+    Tilde,
+    DoubleTilde,
+    DoublePipe,
+}
+
+impl Closer {
+    fn token(self) -> Token {
+        match self {
+            Closer::Star | Closer::DoubleStar => Token::Star,
+            Closer::Underscore | Closer::DoubleUnderscore => Token::Underscore,
+            Closer::Tilde | Closer::DoubleTilde => Token::Tilde,
+            Closer::DoublePipe => Token::Pipe,
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Closer::Star | Closer::Underscore | Closer::Tilde => 1,
+            Closer::DoubleStar
+            | Closer::DoubleUnderscore
+            | Closer::DoubleTilde
+            | Closer::DoublePipe => 2,
+        }
+    }
+
+    fn at(self, stream: &ParseStream) -> bool {
+        let token = self.token();
+        (0..self.len()).all(|n| stream.peek_ahead(n) == Some(&token))
+    }
+}
+
 pub struct ParseStream<'a> {
     tokens: &'a [Token],
     cursor: usize,
+    depth: usize,
+    max_depth: usize,
+    dialect: Dialect,
+    open: Vec<Closer>,
 }
 
 impl<'a> ParseStream<'a> {
     pub fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens, cursor: 0 }
+        Self {
+            tokens,
+            cursor: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            dialect: Dialect::default(),
+            open: Vec::new(),
+        }
     }
 
     pub fn peek(&self) -> Option<&Token> {
@@ -51,15 +110,57 @@ impl<'a> ParseStream<'a> {
         T::parse(ParseStream {
             tokens: self.tokens,
             cursor: self.cursor,
+            depth: self.depth,
+            max_depth: self.max_depth,
+            dialect: self.dialect,
+            open: self.open.clone(),
         })
     }
 }
 
 pub fn parse(input: &str) -> Result<Vec<Element>> {
+    parse_with_max_depth(input, DEFAULT_MAX_DEPTH)
+}
+
+pub fn parse_with_max_depth(input: &str, max_depth: usize) -> Result<Vec<Element>> {
+    parse_tokens(input, max_depth, Dialect::default())
+}
+
+pub fn parse_with_dialect(input: &str, dialect: Dialect) -> Result<Vec<Element>> {
+    parse_tokens(input, DEFAULT_MAX_DEPTH, dialect)
+}
+
+/// Parses as much as possible, skipping the token an element failed at and
+/// carrying on, so every error in the input is reported.
+pub fn parse_collect(input: &str) -> (Vec<Element>, Vec<Error>) {
     let mut lexer = Lexer::new(input);
     let tokens = lexer.tokenize();
     let mut stream = ParseStream::new(&tokens);
 
+    let mut elements = Vec::new();
+    let mut errors = Vec::new();
+
+    while !stream.is_at_end() {
+        let start = stream.cursor;
+        match parse_element(&mut stream) {
+            Ok(element) => elements.push(element),
+            Err(error) => {
+                errors.push(error);
+                stream.cursor = start + 1;
+            }
+        }
+    }
+
+    (elements, errors)
+}
+
+fn parse_tokens(input: &str, max_depth: usize, dialect: Dialect) -> Result<Vec<Element>> {
+    let mut lexer = Lexer::new(input);
+    let tokens = lexer.tokenize();
+    let mut stream = ParseStream::new(&tokens);
+    stream.max_depth = max_depth;
+    stream.dialect = dialect;
+
     let mut elements = Vec::new();
 
     while !stream.is_at_end() {
@@ -70,12 +171,27 @@ pub fn parse(input: &str) -> Result<Vec<Element>> {
 }
 
 fn parse_element(stream: &mut ParseStream) -> Result<Element> {
+    if stream.depth >= stream.max_depth {
+        return Err(Error::Parse(format!(
+            "nesting exceeds the maximum depth of {}",
+            stream.max_depth
+        )));
+    }
+
+    stream.depth += 1;
+    let element = parse_single_element(stream);
+    stream.depth -= 1;
+    element
+}
+
+fn parse_single_element(stream: &mut ParseStream) -> Result<Element> {
     let token = stream.peek().cloned();
     match token {
-        Some(Token::Star) => parse_bold_or_italic(stream),
-        Some(Token::Underscore) => parse_italic_or_underline(stream),
+        Some(Token::Star) => parse_delimited(stream, parse_bold_or_italic),
+        Some(Token::Underscore) => parse_delimited(stream, parse_italic_or_underline),
         Some(Token::Backtick) => parse_code_or_pre(stream),
-        Some(Token::Tilde) => parse_strikethrough_or_spoiler(stream),
+        Some(Token::Tilde) => parse_delimited(stream, parse_strikethrough),
+        Some(Token::Pipe) => parse_delimited(stream, parse_spoiler),
         Some(Token::LeftBracket) => parse_link(stream),
         Some(Token::Mention(username)) => {
             stream.advance();
@@ -87,53 +203,111 @@ fn parse_element(stream: &mut ParseStream) -> Result<Element> {
         }
         Some(Token::Command(cmd)) => {
             stream.advance();
+            // `/start@MyBot` lexes as a command immediately followed by a mention
+            let bot = match stream.peek() {
+                Some(Token::Mention(bot)) => {
+                    let bot = bot.clone();
+                    stream.advance();
+                    Some(bot)
+                }
+                _ => None,
+            };
             Ok(Element::Command {
                 name: cmd,
                 args: Vec::new(),
+                bot,
+            })
+        }
+        Some(Token::Link(url)) => {
+            stream.advance();
+            Ok(Element::TextLink {
+                text: url.clone(),
+                url,
             })
         }
+        Some(Token::CustomEmoji(emoji, id)) => {
+            stream.advance();
+            Ok(Element::CustomEmoji { emoji, id })
+        }
         Some(Token::Text(text)) => {
             stream.advance();
-            Ok(Element::Text(text))
+            Ok(Element::text(text))
         }
         Some(Token::Escape(ch)) => {
             stream.advance();
-            Ok(Element::Text(ch.to_string()))
+            Ok(Element::text(ch.to_string()))
         }
         Some(Token::LineBreak) => {
             stream.advance();
-            Ok(Element::Text("\n".to_string()))
+            Ok(Element::text("\n"))
         }
-        _ => {
+        Some(Token::Eof) | None => Err(Error::UnexpectedEof),
+        Some(token) => {
             stream.advance();
-            Ok(Element::Text("".to_string()))
+            Ok(Element::text(token_text(&token)))
         }
     }
 }
 
-fn parse_bold_or_italic(stream: &mut ParseStream) -> Result<Element> {
+fn token_text(token: &Token) -> &'static str {
+    match token {
+        Token::Star => "*",
+        Token::Underscore => "_",
+        Token::Backtick => "`",
+        Token::Tilde => "~",
+        Token::Pipe => "|",
+        Token::At => "@",
+        Token::Hash => "#",
+        Token::Slash => "/",
+        Token::LeftParen => "(",
+        Token::RightParen => ")",
+        Token::LeftBracket => "[",
+        Token::RightBracket => "]",
+        Token::LeftBrace => "{",
+        Token::RightBrace => "}",
+        _ => "",
+    }
+}
+
+// Like `peek`, but treats the trailing `Eof` token as the end of input.
+fn peek_content<'a>(stream: &'a ParseStream) -> Option<&'a Token> {
+    stream.peek().filter(|token| !matches!(token, Token::Eof))
+}
+
+// A delimiter that is never closed is kept as literal text
+fn parse_delimited(
+    stream: &mut ParseStream,
+    parse_span: fn(&mut ParseStream) -> Result<Option<Element>>,
+) -> Result<Element> {
+    let start = stream.cursor;
+    if let Some(element) = parse_span(stream)? {
+        return Ok(element);
+    }
+
+    stream.cursor = start;
+    let token = stream.advance().ok_or(Error::UnexpectedEof)?;
+    Ok(Element::text(token_text(&token)))
+}
+
+fn parse_bold_or_italic(stream: &mut ParseStream) -> Result<Option<Element>> {
     stream.consume(&Token::Star)?;
 
     if matches!(stream.peek(), Some(Token::Star)) {
         stream.advance();
-        let content = parse_until_double_star(stream)?;
-        Ok(Element::Bold(content))
+        Ok(parse_until(stream, Closer::DoubleStar)?.map(Element::Bold))
     } else {
-        let content = parse_until_single_star(stream)?;
-        Ok(Element::Italic(content))
+        Ok(parse_until(stream, Closer::Star)?.map(Element::Italic))
     }
 }
 
-fn parse_italic_or_underline(stream: &mut ParseStream) -> Result<Element> {
+fn parse_italic_or_underline(stream: &mut ParseStream) -> Result<Option<Element>> {
     stream.consume(&Token::Underscore)?;
 
     if matches!(stream.peek(), Some(Token::Underscore)) {
         stream.advance();
-        let content = parse_until_double_underscore(stream)?;
-        Ok(Element::Underline(content))
+        Ok(parse_until(stream, Closer::DoubleUnderscore)?.map(Element::Underline))
     } else {
-        let content = parse_until_single_underscore(stream)?;
-        Ok(Element::Italic(content))
+        Ok(parse_until(stream, Closer::Underscore)?.map(Element::Italic))
     }
 }
 
@@ -150,7 +324,7 @@ fn parse_code_or_pre(stream: &mut ParseStream) -> Result<Element> {
         }
     } else {
         let mut code = String::new();
-        while let Some(token) = stream.peek() {
+        while let Some(token) = peek_content(stream) {
             match token {
                 Token::Backtick => {
                     stream.advance();
@@ -186,7 +360,7 @@ fn parse_pre_block(stream: &mut ParseStream) -> Result<Element> {
     let mut code = String::new();
     let mut backtick_count = 0;
 
-    while let Some(token) = stream.peek() {
+    while let Some(token) = peek_content(stream) {
         match token {
             Token::Backtick => {
                 backtick_count += 1;
@@ -217,19 +391,32 @@ fn parse_pre_block(stream: &mut ParseStream) -> Result<Element> {
     Err(Error::Parse("Unclosed pre block".to_string()))
 }
 
-fn parse_strikethrough_or_spoiler(stream: &mut ParseStream) -> Result<Element> {
+fn parse_strikethrough(stream: &mut ParseStream) -> Result<Option<Element>> {
     stream.consume(&Token::Tilde)?;
 
-    if matches!(stream.peek(), Some(Token::Tilde)) {
-        stream.advance();
-        let content = parse_until_double_tilde(stream)?;
-        Ok(Element::Strikethrough(content))
-    } else {
-        let content = parse_until_single_tilde(stream)?;
-        Ok(Element::Spoiler(content))
+    match stream.dialect {
+        Dialect::Telegram => Ok(parse_until(stream, Closer::Tilde)?.map(Element::Strikethrough)),
+        Dialect::CommonMark => {
+            if !matches!(stream.peek(), Some(Token::Tilde)) {
+                return Ok(None);
+            }
+            stream.advance();
+            Ok(parse_until(stream, Closer::DoubleTilde)?.map(Element::Strikethrough))
+        }
     }
 }
 
+// Both dialects share `||text||`; a single `|` stays text
+fn parse_spoiler(stream: &mut ParseStream) -> Result<Option<Element>> {
+    stream.consume(&Token::Pipe)?;
+
+    if !matches!(stream.peek(), Some(Token::Pipe)) {
+        return Ok(None);
+    }
+    stream.advance();
+    Ok(parse_until(stream, Closer::DoublePipe)?.map(Element::Spoiler))
+}
+
 fn parse_link(stream: &mut ParseStream) -> Result<Element> {
     stream.consume(&Token::LeftBracket)?;
 
@@ -238,11 +425,12 @@ fn parse_link(stream: &mut ParseStream) -> Result<Element> {
     stream.consume(&Token::LeftParen)?;
 
     let mut url = String::new();
-    while let Some(token) = stream.peek() {
+    while let Some(token) = peek_content(stream) {
         match token {
             Token::RightParen => {
                 stream.advance();
-                return Ok(Element::Link { text, url });
+                let (url, title) = split_link_title(url);
+                return Ok(tg_link(text, url, title));
             }
             Token::Text(text) => {
                 url.push_str(text);
@@ -314,103 +502,72 @@ fn parse_link(stream: &mut ParseStream) -> Result<Element> {
     Err(Error::Parse("Unclosed link".to_string()))
 }
 
-fn parse_until_double_star(stream: &mut ParseStream) -> Result<Vec<Element>> {
-    let mut elements = Vec::new();
-
-    while let Some(token) = stream.peek() {
-        if matches!(token, Token::Star) {
-            if matches!(stream.peek_ahead(1), Some(Token::Star)) {
-                stream.advance();
-                stream.advance();
-                return Ok(elements);
-            }
-        }
-        elements.push(parse_element(stream)?);
-    }
-
-    Err(Error::Parse("Unclosed bold".to_string()))
-}
-
-fn parse_until_single_star(stream: &mut ParseStream) -> Result<Vec<Element>> {
-    let mut elements = Vec::new();
+// Undo the generator's `tg://` encodings of MentionId and CustomEmoji
+fn tg_link(text: Vec<Element>, url: String, title: Option<String>) -> Element {
+    let id = |prefix: &str| url.strip_prefix(prefix)?.parse::<u64>().ok();
+    let plain = || text.iter().flat_map(|e| e.text_iter()).collect::<String>();
 
-    while let Some(token) = stream.peek() {
-        if matches!(token, Token::Star) {
-            stream.advance();
-            return Ok(elements);
+    if let Some(user_id) = id("tg://user?id=") {
+        Element::MentionId {
+            user_id,
+            text: plain(),
         }
-        elements.push(parse_element(stream)?);
+    } else if let Some(id) = id("tg://emoji?id=") {
+        Element::CustomEmoji { emoji: plain(), id }
+    } else {
+        Element::Link { text, url, title }
     }
-
-    Err(Error::Parse("Unclosed italic".to_string()))
 }
 
-fn parse_until_double_underscore(stream: &mut ParseStream) -> Result<Vec<Element>> {
-    let mut elements = Vec::new();
-
-    while let Some(token) = stream.peek() {
-        if matches!(token, Token::Underscore) {
-            if matches!(stream.peek_ahead(1), Some(Token::Underscore)) {
-                stream.advance();
-                stream.advance();
-                return Ok(elements);
-            }
+fn split_link_title(raw: String) -> (String, Option<String>) {
+    let trimmed = raw.trim_end();
+    if let Some(inner) = trimmed.strip_suffix('"') {
+        if let Some(start) = inner.rfind(" \"") {
+            let url = inner[..start].trim_end().to_string();
+            let title = inner[start + 2..].to_string();
+            return (url, Some(title));
         }
-        elements.push(parse_element(stream)?);
     }
-
-    Err(Error::Parse("Unclosed underline".to_string()))
+    (raw, None)
 }
 
-fn parse_until_single_underscore(stream: &mut ParseStream) -> Result<Vec<Element>> {
-    let mut elements = Vec::new();
-
-    while let Some(token) = stream.peek() {
-        if matches!(token, Token::Underscore) {
-            stream.advance();
-            return Ok(elements);
-        }
-        elements.push(parse_element(stream)?);
-    }
-
-    Err(Error::Parse("Unclosed italic".to_string()))
+// Emphasis closes at the nearest matching delimiter. A span still open when
+// an enclosing span's delimiter arrives was improperly nested, so its opener
+// is kept as text: `*a _b* c_` is italic "a _b" followed by " c_".
+fn parse_until(stream: &mut ParseStream, closer: Closer) -> Result<Option<Vec<Element>>> {
+    stream.open.push(closer);
+    let result = parse_span(stream, closer);
+    stream.open.pop();
+    result
 }
 
-fn parse_until_double_tilde(stream: &mut ParseStream) -> Result<Vec<Element>> {
+fn parse_span(stream: &mut ParseStream, closer: Closer) -> Result<Option<Vec<Element>>> {
     let mut elements = Vec::new();
+    let enclosing = stream.open.len() - 1;
 
-    while let Some(token) = stream.peek() {
-        if matches!(token, Token::Tilde) {
-            if matches!(stream.peek_ahead(1), Some(Token::Tilde)) {
-                stream.advance();
+    while peek_content(stream).is_some() {
+        if closer.at(stream) {
+            for _ in 0..closer.len() {
                 stream.advance();
-                return Ok(elements);
             }
+            return Ok(Some(elements));
         }
-        elements.push(parse_element(stream)?);
-    }
-
-    Err(Error::Parse("Unclosed strikethrough".to_string()))
-}
-
-fn parse_until_single_tilde(stream: &mut ParseStream) -> Result<Vec<Element>> {
-    let mut elements = Vec::new();
-
-    while let Some(token) = stream.peek() {
-        if matches!(token, Token::Tilde) {
-            stream.advance();
-            return Ok(elements);
+        if stream.open[..enclosing]
+            .iter()
+            .any(|outer| outer.at(stream))
+        {
+            return Ok(None);
         }
         elements.push(parse_element(stream)?);
     }
 
-    Err(Error::Parse("Unclosed spoiler".to_string()))
+    Ok(None)
 }
 
 fn parse_until_right_bracket(stream: &mut ParseStream) -> Result<Vec<Element>> {
     let mut elements = Vec::new();
 
-    while let Some(token) = stream.peek() {
+    while let Some(token) = peek_content(stream) {
         if matches!(token, Token::RightBracket) {
             return Ok(elements);
         }
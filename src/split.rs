@@ -0,0 +1,167 @@
+use crate::ast::{flatten, Element};
+use crate::error::{Error, Result};
+use crate::generator::{Generator, ParseMode};
+
+/// Renders `elements` in `mode` and splits the output into chunks of at most
+/// `limit` UTF-16 code units, the unit Telegram's message limit is counted in.
+pub fn split_message(elements: &[Element], mode: ParseMode, limit: usize) -> Result<Vec<String>> {
+    split_message_with(elements, &Generator::new(mode), limit)
+}
+
+/// Like [`split_message`], rendering with `generator` and its settings.
+pub fn split_message_with(
+    elements: &[Element],
+    generator: &Generator,
+    limit: usize,
+) -> Result<Vec<String>> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for_each_piece(elements, generator, limit, |piece| {
+        let len = utf16_len(piece);
+        if current_len + len > limit && current_len > 0 {
+            chunks.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        current.push_str(piece);
+        current_len += len;
+    })?;
+
+    if current_len > 0 {
+        chunks.push(current);
+    }
+
+    Ok(chunks)
+}
+
+/// The number of chunks [`split_message`] would produce, without building them.
+pub fn message_count(elements: &[Element], mode: ParseMode, limit: usize) -> Result<usize> {
+    message_count_with(elements, &Generator::new(mode), limit)
+}
+
+/// Like [`message_count`], rendering with `generator` and its settings.
+pub fn message_count_with(
+    elements: &[Element],
+    generator: &Generator,
+    limit: usize,
+) -> Result<usize> {
+    let mut count = 0;
+    let mut current_len = 0;
+
+    for_each_piece(elements, generator, limit, |piece| {
+        let len = utf16_len(piece);
+        if current_len + len > limit && current_len > 0 {
+            count += 1;
+            current_len = 0;
+        }
+        current_len += len;
+    })?;
+
+    if current_len > 0 {
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn for_each_piece(
+    elements: &[Element],
+    generator: &Generator,
+    limit: usize,
+    mut f: impl FnMut(&str),
+) -> Result<()> {
+    if limit == 0 {
+        return Err(Error::Generation(
+            "message limit must be positive".to_string(),
+        ));
+    }
+
+    let mut buffer = String::new();
+
+    for element in flatten(elements.to_vec()) {
+        buffer.clear();
+        generator.generate(&mut buffer, &element)?;
+        if utf16_len(&buffer) <= limit {
+            f(&buffer);
+            continue;
+        }
+
+        match &element {
+            Element::Text(text) => split_text(generator, text, limit, &mut f)?,
+            _ => {
+                return Err(Error::Generation(format!(
+                    "element exceeds message limit of {} UTF-16 code units",
+                    limit
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Words and, for overlong words, chars are rendered once each and packed
+// into chunks by their rendered length. Text escaping works char by char, so
+// the concatenated pieces match rendering the whole chunk at once.
+fn split_text(
+    generator: &Generator,
+    text: &str,
+    limit: usize,
+    f: &mut impl FnMut(&str),
+) -> Result<()> {
+    let mut chunk = Chunk::default();
+    let mut piece = String::new();
+
+    for word in text.split_inclusive(char::is_whitespace) {
+        render(generator, word, &mut piece)?;
+        let len = utf16_len(&piece);
+        if len <= limit {
+            chunk.push(&piece, len, limit, f);
+            continue;
+        }
+
+        let mut buf = [0; 4];
+        for c in word.chars() {
+            render(generator, c.encode_utf8(&mut buf), &mut piece)?;
+            let len = utf16_len(&piece);
+            chunk.push(&piece, len, limit, f);
+        }
+    }
+
+    chunk.flush(f);
+    Ok(())
+}
+
+#[derive(Default)]
+struct Chunk {
+    rendered: String,
+    len: usize,
+}
+
+impl Chunk {
+    fn push(&mut self, piece: &str, len: usize, limit: usize, f: &mut impl FnMut(&str)) {
+        if self.len + len > limit {
+            self.flush(f);
+        }
+        self.rendered.push_str(piece);
+        self.len += len;
+    }
+
+    fn flush(&mut self, f: &mut impl FnMut(&str)) {
+        if self.len > 0 {
+            f(&self.rendered);
+            self.rendered.clear();
+            self.len = 0;
+        }
+    }
+}
+
+fn render(generator: &Generator, text: &str, rendered: &mut String) -> Result<()> {
+    rendered.clear();
+    generator.generate(rendered, &Element::text(text))
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
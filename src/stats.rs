@@ -0,0 +1,127 @@
+use crate::ast::{Element, ListNode};
+use crate::width::grapheme_count;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageStats {
+    /// Visible characters, counted as grapheme clusters
+    pub chars: usize,
+    pub words: usize,
+    pub links: usize,
+    pub mentions: usize,
+    pub hashtags: usize,
+}
+
+pub fn stats(elements: &[Element]) -> MessageStats {
+    let mut counter = Counter::default();
+    elements.iter().for_each(|e| counter.visit(e));
+    counter.stats.words = counter.text.split_whitespace().count();
+    counter.stats
+}
+
+#[derive(Default)]
+struct Counter {
+    stats: MessageStats,
+    text: String,
+}
+
+impl Counter {
+    fn push(&mut self, text: &str) {
+        self.stats.chars += grapheme_count(text);
+        self.text.push_str(text);
+    }
+
+    // Separates list items and table cells so their words don't run together
+    fn boundary(&mut self) {
+        self.text.push(' ');
+    }
+
+    fn visit(&mut self, element: &Element) {
+        match element {
+            Element::Text(text) => self.push(text),
+            Element::Code(code) => self.push(code),
+            Element::Pre(block) => {
+                self.boundary();
+                self.push(&block.code);
+                self.boundary();
+            }
+
+            Element::Bold(elements)
+            | Element::Italic(elements)
+            | Element::Underline(elements)
+            | Element::Strikethrough(elements)
+            | Element::Spoiler(elements)
+            | Element::Group(elements)
+            | Element::Footnote {
+                content: elements, ..
+            } => elements.iter().for_each(|e| self.visit(e)),
+
+            Element::Link { text, .. } => {
+                self.stats.links += 1;
+                text.iter().for_each(|e| self.visit(e));
+            }
+            Element::TextLink { text, .. } => {
+                self.stats.links += 1;
+                self.push(text);
+            }
+
+            Element::Mention { username } => {
+                self.stats.mentions += 1;
+                self.push(&format!("@{}", username));
+            }
+            Element::MentionId { text, .. } => {
+                self.stats.mentions += 1;
+                self.push(text);
+            }
+
+            Element::Hashtag(tag) => {
+                self.stats.hashtags += 1;
+                self.push(&format!("#{}", tag));
+            }
+            Element::Command { name, args, bot } => {
+                self.push(&format!("/{}", name));
+                if let Some(bot) = bot {
+                    self.push(&format!("@{}", bot));
+                }
+                for arg in args {
+                    self.push(&format!(" {}", arg));
+                }
+            }
+
+            Element::Emoji(emoji) | Element::CustomEmoji { emoji, .. } => self.push(emoji),
+            Element::Image { alt, .. } => self.push(alt),
+            Element::Custom { value, .. } => self.push(value),
+            Element::RawHtml(_) | Element::Preformatted { .. } => {}
+
+            Element::List(list) => self.visit_list(list),
+            Element::Table(table) => {
+                for cell in table
+                    .headers
+                    .iter()
+                    .chain(table.rows.iter().flat_map(|row| row.cells.iter()))
+                {
+                    self.boundary();
+                    cell.content.iter().for_each(|e| self.visit(e));
+                }
+                self.boundary();
+            }
+            Element::Quote { content, cite } => {
+                content.iter().for_each(|e| self.visit(e));
+                if let Some(cite) = cite {
+                    self.boundary();
+                    self.push(cite);
+                }
+            }
+        }
+    }
+
+    fn visit_list(&mut self, list: &ListNode) {
+        for item in &list.items {
+            self.boundary();
+            item.content.iter().for_each(|e| self.visit(e));
+            if let Some(nested) = &item.nested {
+                self.visit_list(nested);
+            }
+        }
+        self.boundary();
+    }
+}
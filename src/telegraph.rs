@@ -0,0 +1,178 @@
+use crate::ast::{flatten, Element, ListNode, ListStyle, TableNode, TableStyle};
+use crate::error::{Error, Result};
+use crate::generator::{escape_html, Generator, ParseMode};
+use std::fmt::Write;
+
+macro_rules! write_fmt {
+    ($writer:expr, $($args:tt)*) => {
+        write!($writer, $($args)*).map_err(|e| Error::Generation(e.to_string()))
+    };
+}
+
+pub fn render_telegraph(elements: &[Element]) -> Result<String> {
+    let mut output = String::new();
+    let mut paragraph = String::new();
+
+    for element in flatten(elements.to_vec()) {
+        if is_block(&element) {
+            flush_paragraph(&mut output, &mut paragraph)?;
+            render_block(&mut output, &element)?;
+        } else {
+            render_inline(&mut paragraph, &element)?;
+        }
+    }
+    flush_paragraph(&mut output, &mut paragraph)?;
+
+    Ok(output)
+}
+
+fn is_block(element: &Element) -> bool {
+    matches!(
+        element,
+        Element::Pre(_) | Element::List(_) | Element::Table(_) | Element::Quote { .. }
+    )
+}
+
+fn flush_paragraph(output: &mut String, paragraph: &mut String) -> Result<()> {
+    let content = trim_breaks(paragraph);
+    if !content.trim().is_empty() {
+        write_fmt!(output, "<p>{}</p>", content)?;
+    }
+    paragraph.clear();
+    Ok(())
+}
+
+fn trim_breaks(mut content: &str) -> &str {
+    loop {
+        let trimmed = content
+            .trim()
+            .trim_start_matches("<br>")
+            .trim_end_matches("<br>");
+        if trimmed.len() == content.len() {
+            return content;
+        }
+        content = trimmed;
+    }
+}
+
+fn render_block(output: &mut String, element: &Element) -> Result<()> {
+    match element {
+        Element::Pre(block) => write_fmt!(output, "<pre>{}</pre>", escape_html(&block.code)),
+        Element::List(list) => render_list(output, list),
+        Element::Table(table) => render_table(output, table),
+        Element::Quote { content, cite } => {
+            let mut quote = String::new();
+            render_inlines(&mut quote, content)?;
+            if let Some(cite) = cite {
+                write_fmt!(quote, "<br><i>— {}</i>", escape_html(cite))?;
+            }
+            write_fmt!(output, "<blockquote>{}</blockquote>", trim_breaks(&quote))
+        }
+        other => render_inline(output, other),
+    }
+}
+
+fn render_list(output: &mut String, list: &ListNode) -> Result<()> {
+    let tag = match list.style {
+        ListStyle::Numbered => "ol",
+        ListStyle::Bullet | ListStyle::Custom(_) | ListStyle::None => "ul",
+    };
+
+    write_fmt!(output, "<{}>", tag)?;
+    for item in &list.items {
+        let mut content = String::new();
+        render_inlines(&mut content, &item.content)?;
+        write_fmt!(output, "<li>{}", trim_breaks(&content))?;
+        if let Some(nested) = &item.nested {
+            render_list(output, nested)?;
+        }
+        write_fmt!(output, "</li>")?;
+    }
+    write_fmt!(output, "</{}>", tag)
+}
+
+// Telegra.ph has no table nodes, so tables keep their monospace text layout
+fn render_table(output: &mut String, table: &TableNode) -> Result<()> {
+    let mut table = table.clone();
+    if table.style == TableStyle::Html {
+        table.style = TableStyle::Unicode;
+    }
+    Generator::new(ParseMode::Html).generate(output, &Element::Table(table))
+}
+
+fn render_inlines(output: &mut String, elements: &[Element]) -> Result<()> {
+    for element in elements {
+        if is_block(element) {
+            render_block(output, element)?;
+        } else {
+            render_inline(output, element)?;
+        }
+    }
+    Ok(())
+}
+
+fn render_inline(output: &mut String, element: &Element) -> Result<()> {
+    match element {
+        Element::Text(text) => {
+            let escaped = escape_html(text);
+            write_fmt!(output, "{}", escaped.replace('\n', "<br>"))
+        }
+        Element::Bold(content) => render_tag(output, "b", content),
+        Element::Italic(content) => render_tag(output, "i", content),
+        Element::Underline(content) => render_tag(output, "u", content),
+        Element::Strikethrough(content) => render_tag(output, "s", content),
+        Element::Spoiler(content) | Element::Group(content) | Element::Footnote { content, .. } => {
+            render_inlines(output, content)
+        }
+        Element::Code(code) => write_fmt!(output, "<code>{}</code>", escape_html(code)),
+        Element::Link { text, url, .. } => {
+            write_fmt!(output, "<a href=\"{}\">", escape_html(url))?;
+            render_inlines(output, text)?;
+            write_fmt!(output, "</a>")
+        }
+        Element::TextLink { text, url } => write_fmt!(
+            output,
+            "<a href=\"{}\">{}</a>",
+            escape_html(url),
+            escape_html(text)
+        ),
+        Element::Mention { username } => write_fmt!(
+            output,
+            "<a href=\"https://t.me/{}\">@{}</a>",
+            escape_html(username),
+            escape_html(username)
+        ),
+        Element::MentionId { text, .. } => write_fmt!(output, "{}", escape_html(text)),
+        Element::Hashtag(tag) => write_fmt!(output, "#{}", escape_html(tag)),
+        Element::Command { name, args, bot } => {
+            write_fmt!(output, "/{}", escape_html(name))?;
+            if let Some(bot) = bot {
+                write_fmt!(output, "@{}", escape_html(bot))?;
+            }
+            for arg in args {
+                write_fmt!(output, " {}", escape_html(arg))?;
+            }
+            Ok(())
+        }
+        Element::Emoji(emoji) | Element::CustomEmoji { emoji, .. } => {
+            write_fmt!(output, "{}", escape_html(emoji))
+        }
+        Element::Custom { value, .. } => write_fmt!(output, "{}", escape_html(value)),
+        Element::RawHtml(html) | Element::Preformatted { html, .. } => {
+            write_fmt!(output, "{}", html)
+        }
+        Element::Image { url, alt } => write_fmt!(
+            output,
+            "<img src=\"{}\" alt=\"{}\">",
+            escape_html(url),
+            escape_html(alt)
+        ),
+        block => render_block(output, block),
+    }
+}
+
+fn render_tag(output: &mut String, tag: &str, content: &[Element]) -> Result<()> {
+    write_fmt!(output, "<{}>", tag)?;
+    render_inlines(output, content)?;
+    write_fmt!(output, "</{}>", tag)
+}
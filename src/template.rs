@@ -0,0 +1,57 @@
+use crate::ast::Element;
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+
+pub fn template(tmpl: &str, args: &HashMap<&str, Vec<Element>>) -> Result<Vec<Element>> {
+    let mut elements = Vec::new();
+    let mut literal = String::new();
+    let mut chars = tmpl.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut key = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => key.push(c),
+                        None => {
+                            return Err(Error::Parse(format!(
+                                "Unclosed template placeholder {{{}",
+                                key
+                            )))
+                        }
+                    }
+                }
+
+                let value = args.get(key.as_str()).ok_or_else(|| {
+                    Error::Parse(format!("Missing template argument {{{}}}", key))
+                })?;
+                if !literal.is_empty() {
+                    elements.push(Element::text(std::mem::take(&mut literal)));
+                }
+                elements.extend(value.iter().cloned());
+            }
+            '}' => {
+                return Err(Error::Parse(
+                    "Unmatched `}` in template, use `}}` for a literal brace".to_string(),
+                ))
+            }
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        elements.push(Element::text(literal));
+    }
+
+    Ok(elements)
+}
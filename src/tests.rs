@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod token_tests {
-    use crate::token::{Lexer, Token};
+    use crate::token::{Lexer, LexerOptions, Token};
 
     #[test]
     fn test_tokenize_simple_text() {
@@ -94,6 +94,108 @@ mod token_tests {
             ]
         );
     }
+
+    #[test]
+    fn test_tokenize_crlf_line_break() {
+        let mut lexer = Lexer::new("a\r\nb");
+        let tokens = lexer.tokenize();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Text("a".to_string()),
+                Token::LineBreak,
+                Token::Text("b".to_string()),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_lone_carriage_return() {
+        let mut lexer = Lexer::new("a\rb\r\n\nc");
+        let tokens = lexer.tokenize();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Text("a".to_string()),
+                Token::LineBreak,
+                Token::Text("b".to_string()),
+                Token::LineBreak,
+                Token::LineBreak,
+                Token::Text("c".to_string()),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_mid_word_sigils_as_text() {
+        let mut lexer = Lexer::new("C# and a/b or me@host");
+        assert_eq!(
+            lexer.tokenize(),
+            vec![Token::Text("C# and a/b or me@host".to_string()), Token::Eof]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_hashtag_after_whitespace() {
+        let mut lexer = Lexer::new("C# #tag");
+        assert_eq!(
+            lexer.tokenize(),
+            vec![
+                Token::Text("C# ".to_string()),
+                Token::Hashtag("tag".to_string()),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_command_with_bot_suffix() {
+        let mut lexer = Lexer::new("/start@my_bot");
+        assert_eq!(
+            lexer.tokenize(),
+            vec![
+                Token::Command("start".to_string()),
+                Token::Mention("my_bot".to_string()),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_hashtags_disabled() {
+        let options = LexerOptions {
+            hashtags: false,
+            ..LexerOptions::default()
+        };
+        let mut lexer = Lexer::new_with("#rust and @me", options);
+        assert_eq!(
+            lexer.tokenize(),
+            vec![
+                Token::Text("#rust and ".to_string()),
+                Token::Mention("me".to_string()),
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_commands_disabled() {
+        let options = LexerOptions {
+            commands: false,
+            ..LexerOptions::default()
+        };
+        let mut lexer = Lexer::new_with("/start #go", options);
+        assert_eq!(
+            lexer.tokenize(),
+            vec![
+                Token::Text("/start ".to_string()),
+                Token::Hashtag("go".to_string()),
+                Token::Eof,
+            ]
+        );
+    }
 }
 
 #[cfg(test)]
@@ -103,7 +205,7 @@ mod ast_tests {
     #[test]
     fn test_create_text_element() {
         let element = Element::text("Hello, world!");
-        assert_eq!(element, Element::Text("Hello, world!".to_string()));
+        assert_eq!(element, Element::Text("Hello, world!".into()));
     }
 
     #[test]
@@ -111,7 +213,7 @@ mod ast_tests {
         let element = Element::bold(vec![Element::text("Bold text")]);
         assert_eq!(
             element,
-            Element::Bold(vec![Element::Text("Bold text".to_string())])
+            Element::Bold(vec![Element::Text("Bold text".into())])
         );
     }
 
@@ -121,8 +223,9 @@ mod ast_tests {
         assert_eq!(
             element,
             Element::Link {
-                text: vec![Element::Text("Click here".to_string())],
+                text: vec![Element::Text("Click here".into())],
                 url: "https://example.com".to_string(),
+                title: None,
             }
         );
     }
@@ -149,11 +252,326 @@ mod ast_tests {
     #[test]
     fn test_table_cell_default() {
         let cell = TableCell::default();
-        assert_eq!(cell.align, CellAlign::Left);
+        assert_eq!(cell.align, CellAlign::Inherit);
+        assert_eq!(cell.style, CellStyle::Normal);
         assert_eq!(cell.colspan, 1);
         assert_eq!(cell.rowspan, 1);
         assert!(cell.content.is_empty());
     }
+
+    #[test]
+    fn test_text_iter() {
+        let message = vec![
+            Element::bold(vec![
+                Element::text("Read "),
+                Element::link(vec![Element::text("the docs")], "https://docs.rs"),
+            ]),
+            Element::text(" by "),
+            Element::mention("rustlang"),
+            Element::text(": "),
+            Element::code("cargo doc"),
+        ];
+
+        let text: String = text_iter(&message).collect();
+        assert_eq!(text, "Read the docs by rustlang: cargo doc");
+
+        let element = Element::group(message);
+        assert!(element.text_iter().all(|t| !t.contains("https")));
+    }
+
+    #[test]
+    fn test_walk_mut_lowercases_urls() {
+        let mut element = Element::group(vec![
+            Element::link(vec![Element::text("Docs")], "HTTPS://Docs.RS/Msg"),
+            Element::bold(vec![Element::text_link("Home", "https://EXAMPLE.com")]),
+            Element::List(ListNode {
+                style: ListStyle::Bullet,
+                items: vec![ListItem {
+                    content: vec![Element::text_link("Item", "HTTPS://A.B")],
+                    nested: None,
+                }],
+            }),
+        ]);
+
+        element.walk_mut(&mut |e| {
+            if let Element::Link { url, .. } | Element::TextLink { url, .. } = e {
+                *url = url.to_lowercase();
+            }
+        });
+
+        assert_eq!(
+            element,
+            Element::group(vec![
+                Element::link(vec![Element::text("Docs")], "https://docs.rs/msg"),
+                Element::bold(vec![Element::text_link("Home", "https://example.com")]),
+                Element::List(ListNode {
+                    style: ListStyle::Bullet,
+                    items: vec![ListItem {
+                        content: vec![Element::text_link("Item", "https://a.b")],
+                        nested: None,
+                    }],
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_flatten_nested_groups() {
+        let elements = vec![
+            Element::text("a"),
+            Element::group(vec![
+                Element::text("b"),
+                Element::group(vec![Element::text("c"), Element::group(vec![])]),
+            ]),
+            Element::bold(vec![Element::group(vec![Element::text("d")])]),
+        ];
+
+        assert_eq!(
+            flatten(elements),
+            vec![
+                Element::text("a"),
+                Element::text("b"),
+                Element::text("c"),
+                Element::bold(vec![Element::text("d")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summarize_truncates_code_blocks() {
+        let code = (1..=100)
+            .map(|i| format!("line {}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let elements = vec![
+            Element::text("Log:"),
+            Element::quote(vec![Element::pre(code, Some("text".to_string()))]),
+            Element::code("short"),
+        ];
+
+        let summarized = summarize(elements, 5);
+
+        assert_eq!(summarized[0], Element::text("Log:"));
+        assert_eq!(
+            summarized[1],
+            Element::quote(vec![Element::pre(
+                "line 1\nline 2\nline 3\nline 4\nline 5\n… (truncated)",
+                Some("text".to_string())
+            )])
+        );
+        assert_eq!(summarized[2], Element::code("short"));
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(Element::text("").is_empty());
+        assert!(!Element::text(" ").is_empty());
+        assert!(Element::Group(vec![]).is_empty());
+        assert!(Element::bold(vec![Element::italic(vec![Element::text("")])]).is_empty());
+        assert!(!Element::bold(vec![Element::text(""), Element::text("x")]).is_empty());
+        assert!(!Element::quote_with_cite(vec![], "Author").is_empty());
+        assert!(!Element::code("").is_empty());
+    }
+
+    #[test]
+    fn test_prune_empty() {
+        let elements = vec![
+            Element::text(""),
+            Element::bold(vec![Element::text("")]),
+            Element::italic(vec![
+                Element::text(""),
+                Element::text("kept"),
+                Element::Group(vec![]),
+            ]),
+            Element::List(ListNode {
+                style: ListStyle::Bullet,
+                items: vec![ListItem {
+                    content: vec![Element::text("item"), Element::text("")],
+                    nested: None,
+                }],
+            }),
+        ];
+        assert_eq!(
+            prune_empty(elements),
+            vec![
+                Element::italic(vec![Element::text("kept")]),
+                Element::List(ListNode {
+                    style: ListStyle::Bullet,
+                    items: vec![ListItem {
+                        content: vec![Element::text("item")],
+                        nested: None
+                    }],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_footnotes() {
+        let elements = vec![
+            Element::text("Rust"),
+            Element::footnote("rust", vec![Element::text("A language")]),
+            Element::text(" and "),
+            Element::bold(vec![
+                Element::text("Go"),
+                Element::footnote("go", vec![Element::italic(vec![Element::text("Another")])]),
+            ]),
+            Element::footnote("rust", vec![Element::text("ignored")]),
+        ];
+
+        let collected = collect_footnotes(elements);
+        assert_eq!(
+            collected,
+            vec![
+                Element::text("Rust"),
+                Element::text("[1]"),
+                Element::text(" and "),
+                Element::bold(vec![Element::text("Go"), Element::text("[2]")]),
+                Element::text("[1]"),
+                Element::Group(vec![
+                    Element::text("\n"),
+                    Element::text("\n[1] "),
+                    Element::text("A language"),
+                    Element::text("\n[2] "),
+                    Element::italic(vec![Element::text("Another")]),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_footnotes_without_footnotes() {
+        let elements = vec![Element::text("plain")];
+        assert_eq!(collect_footnotes(elements.clone()), elements);
+    }
+
+    #[test]
+    fn test_try_mention() {
+        use crate::error::Error;
+
+        assert_eq!(
+            Element::try_mention("rust_lang").unwrap(),
+            Element::mention("rust_lang")
+        );
+        assert!(matches!(
+            Element::try_mention("abcd"),
+            Err(Error::InvalidUsername(name)) if name == "abcd"
+        ));
+        assert!(matches!(
+            Element::try_mention("1password"),
+            Err(Error::InvalidUsername(_))
+        ));
+    }
+
+    #[test]
+    fn test_debug_tree() {
+        let message = vec![
+            Element::bold(vec![
+                Element::text("x"),
+                Element::italic(vec![Element::text("y")]),
+            ]),
+            Element::link(vec![Element::code("z")], "https://example.com"),
+        ];
+        assert_eq!(
+        debug_tree(&message),
+        "Bold\n  Text \"x\"\n  Italic\n    Text \"y\"\nLink url=\"https://example.com\"\n  Code \"z\"\n"
+    );
+    }
+
+    fn cells(texts: &[&str]) -> Vec<TableCell> {
+        texts
+            .iter()
+            .map(|text| TableCell {
+                content: vec![Element::text(*text)],
+                align: CellAlign::Right,
+                ..TableCell::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_table_transpose() {
+        let table = TableNode {
+            headers: cells(&["Name", "Qty", "Price"]),
+            rows: vec![TableRow {
+                cells: cells(&["Apple", "3", "1.50"]),
+            }],
+            style: TableStyle::Unicode,
+            rules: Vec::new(),
+            default_align: CellAlign::Right,
+        };
+
+        let transposed = table.transpose().unwrap();
+        assert_eq!(transposed.headers, {
+            let mut headers = cells(&["Name", "Apple"]);
+            headers
+                .iter_mut()
+                .for_each(|cell| cell.align = CellAlign::Left);
+            headers
+        });
+        assert_eq!(transposed.rows.len(), 2);
+        let texts: Vec<Vec<String>> = transposed
+            .rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| text_iter(&cell.content).collect())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(texts, vec![vec!["Qty", "3"], vec!["Price", "1.50"]]);
+        assert_eq!(transposed.default_align, CellAlign::Left);
+        assert_eq!(transposed.transpose().unwrap().rows.len(), 1);
+    }
+
+    #[test]
+    fn test_table_transpose_rejects_spans() {
+        let mut headers = cells(&["a", "b"]);
+        headers[0].colspan = 2;
+        let table = TableNode {
+            headers,
+            rows: Vec::new(),
+            style: TableStyle::Unicode,
+            rules: Vec::new(),
+            default_align: CellAlign::Left,
+        };
+        assert!(matches!(
+            table.transpose(),
+            Err(crate::error::Error::InvalidTable(_))
+        ));
+    }
+
+    #[test]
+    fn test_table_with_totals() {
+        let table = TableNode {
+            headers: cells(&["Item", "Qty", "Price"]),
+            rows: vec![
+                TableRow {
+                    cells: cells(&["Coffee", "2", "3.50"]),
+                },
+                TableRow {
+                    cells: cells(&["Cake", "1", "4.25"]),
+                },
+                TableRow {
+                    cells: cells(&["Tip", "-", "n/a"]),
+                },
+            ],
+            style: TableStyle::Unicode,
+            rules: Vec::new(),
+            default_align: CellAlign::Left,
+        }
+        .with_totals(&[1, 2], "Total");
+
+        let total = table.rows.last().unwrap();
+        let texts: Vec<String> = total
+            .cells
+            .iter()
+            .map(|cell| text_iter(&cell.content).collect())
+            .collect();
+        assert_eq!(texts, vec!["Total", "3", "7.75"]);
+        assert!(total.cells.iter().all(|cell| cell.style == CellStyle::Bold));
+    }
 }
 
 #[cfg(test)]
@@ -164,7 +582,7 @@ mod parser_tests {
     #[test]
     fn test_parse_simple_text() {
         let result = parse("Hello, world!").unwrap();
-        assert_eq!(result, vec![Element::Text("Hello, world!".to_string())]);
+        assert_eq!(result, vec![Element::Text("Hello, world!".into())]);
     }
 
     #[test]
@@ -172,7 +590,7 @@ mod parser_tests {
         let result = parse("**bold text**").unwrap();
         assert_eq!(
             result,
-            vec![Element::Bold(vec![Element::Text("bold text".to_string())])]
+            vec![Element::Bold(vec![Element::Text("bold text".into())])]
         );
     }
 
@@ -182,7 +600,7 @@ mod parser_tests {
         assert_eq!(
             result,
             vec![Element::Italic(vec![Element::Text(
-                "italic text".to_string()
+                "italic text".into()
             )])]
         );
     }
@@ -199,11 +617,11 @@ mod parser_tests {
         assert_eq!(
             result,
             vec![
-                Element::Text("Hello ".to_string()),
+                Element::Text("Hello ".into()),
                 Element::Mention {
                     username: "username".to_string()
                 },
-                Element::Text("!".to_string()),
+                Element::Text("!".into()),
             ]
         );
     }
@@ -214,8 +632,9 @@ mod parser_tests {
         assert_eq!(
             result,
             vec![Element::Link {
-                text: vec![Element::Text("Google".to_string())],
+                text: vec![Element::Text("Google".into())],
                 url: "https://google.com".to_string(),
+                title: None,
             }]
         );
     }
@@ -226,9 +645,9 @@ mod parser_tests {
         assert_eq!(
             result,
             vec![Element::Bold(vec![
-                Element::Text("bold ".to_string()),
-                Element::Italic(vec![Element::Text("and italic".to_string())]),
-                Element::Text(" text".to_string()),
+                Element::Text("bold ".into()),
+                Element::Italic(vec![Element::Text("and italic".into())]),
+                Element::Text(" text".into()),
             ])]
         );
     }
@@ -239,315 +658,3107 @@ mod parser_tests {
         assert_eq!(
             result,
             vec![
-                Element::Text("*".to_string()),
-                Element::Text("not bold".to_string()),
-                Element::Text("*".to_string()),
+                Element::Text("*".into()),
+                Element::Text("not bold".into()),
+                Element::Text("*".into()),
             ]
         );
     }
-}
-
-#[cfg(test)]
-mod generator_tests {
-    use crate::ast::*;
-    use crate::generator::{Generator, ParseMode};
 
     #[test]
-    fn test_generate_text_markdown() {
-        let generator = Generator::new(ParseMode::MarkdownV2);
-        let element = Element::Text("Hello, world!".to_string());
-        let mut result = String::new();
-        generator.generate(&mut result, &element).unwrap();
-        assert_eq!(result, "Hello, world\\!");
+    fn test_parse_link_with_title() {
+        let result = parse("[Rust](https://rust-lang.org \"The Rust site\")").unwrap();
+        assert_eq!(
+            result,
+            vec![Element::Link {
+                text: vec![Element::Text("Rust".into())],
+                url: "https://rust-lang.org".to_string(),
+                title: Some("The Rust site".to_string()),
+            }]
+        );
     }
 
     #[test]
-    fn test_generate_text_html() {
-        let generator = Generator::new(ParseMode::Html);
-        let element = Element::Text("Hello <world>".to_string());
-        let mut result = String::new();
-        generator.generate(&mut result, &element).unwrap();
-        assert_eq!(result, "Hello &lt;world&gt;");
+    fn test_element_from_plain() {
+        let result = Element::from_plain("**bold *and italic* text**").unwrap();
+        assert_eq!(
+            result,
+            vec![Element::Bold(vec![
+                Element::Text("bold ".into()),
+                Element::Italic(vec![Element::Text("and italic".into())]),
+                Element::Text(" text".into()),
+            ])]
+        );
+        assert!(Element::from_plain("`unclosed").is_err());
     }
 
     #[test]
-    fn test_generate_bold_markdown() {
-        let generator = Generator::new(ParseMode::MarkdownV2);
-        let element = Element::Bold(vec![Element::Text("bold text".to_string())]);
-        let mut result = String::new();
-        generator.generate(&mut result, &element).unwrap();
-        assert_eq!(result, "*bold text*");
+    fn test_parse_keeps_unmatched_punctuation() {
+        let result = parse("a (b) | c]").unwrap();
+        let text: String = text_iter(&result).collect();
+        assert_eq!(text, "a (b) | c]");
     }
 
-    #[test]
-    fn test_generate_bold_html() {
-        let generator = Generator::new(ParseMode::Html);
-        let element = Element::Bold(vec![Element::Text("bold text".to_string())]);
-        let mut result = String::new();
-        generator.generate(&mut result, &element).unwrap();
-        assert_eq!(result, "<b>bold text</b>");
-    }
+    proptest::proptest! {
+        #[test]
+        fn test_parse_never_panics(input in "\\PC{0,64}") {
+            let _ = parse(&input);
+        }
 
-    #[test]
-    fn test_generate_link_markdown() {
-        let generator = Generator::new(ParseMode::MarkdownV2);
-        let element = Element::Link {
-            text: vec![Element::Text("Google".to_string())],
-            url: "https://google.com".to_string(),
-        };
-        let mut result = String::new();
-        generator.generate(&mut result, &element).unwrap();
-        assert_eq!(result, "[Google](https://google.com)");
+        #[test]
+        fn test_parse_markup_never_panics(input in "[*_`~|@#/()\\[\\]{}\\\\\n a-z]{0,64}") {
+            let _ = parse(&input);
+        }
     }
 
     #[test]
-    fn test_generate_link_html() {
-        let generator = Generator::new(ParseMode::Html);
-        let element = Element::Link {
-            text: vec![Element::Text("Google".to_string())],
-            url: "https://google.com".to_string(),
-        };
-        let mut result = String::new();
-        generator.generate(&mut result, &element).unwrap();
-        assert_eq!(result, "<a href=\"https://google.com\">Google</a>");
+    fn test_parse_command_with_bot() {
+        let result = parse("/start@MyBot /help @user").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Element::command_for_bot("start", "MyBot"),
+                Element::text(" "),
+                Element::command("help"),
+                Element::text(" "),
+                Element::mention("user"),
+            ]
+        );
     }
 
     #[test]
-    fn test_generate_pre_markdown() {
-        let generator = Generator::new(ParseMode::MarkdownV2);
-        let element = Element::Pre(PreBlock {
-            code: "fn main() {\n    println!(\"Hello\");\n}".to_string(),
-            language: Some("rust".to_string()),
-        });
-        let mut result = String::new();
-        generator.generate(&mut result, &element).unwrap();
+    fn test_parse_custom_emoji() {
+        let result = parse("Nice ![👍](tg://emoji?id=5368324170671202286)").unwrap();
         assert_eq!(
             result,
-            "```rust\nfn main() {\n    println!(\"Hello\");\n}\n```"
+            vec![
+                Element::Text("Nice ".into()),
+                Element::CustomEmoji {
+                    emoji: "👍".to_string(),
+                    id: 5368324170671202286
+                },
+            ]
         );
     }
 
     #[test]
-    fn test_generate_list() {
-        let generator = Generator::new(ParseMode::MarkdownV2);
-        let element = Element::List(ListNode {
-            style: ListStyle::Bullet,
-            items: vec![
-                ListItem {
-                    content: vec![Element::Text("Item 1".to_string())],
-                    nested: None,
-                },
-                ListItem {
-                    content: vec![Element::Text("Item 2".to_string())],
-                    nested: None,
+    fn test_parse_image_link_without_tg_url() {
+        let result = parse("![logo](https://example.com/a.png)").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Element::Text("!".into()),
+                Element::Link {
+                    text: vec![Element::Text("logo".into())],
+                    url: "https://example.com/a.png".to_string(),
+                    title: None,
                 },
-            ],
-        });
-        let mut result = String::new();
-        generator.generate(&mut result, &element).unwrap();
-        assert_eq!(result, "• Item 1\n• Item 2");
+            ]
+        );
     }
 
     #[test]
-    fn test_roundtrip() {
-        use crate::parser::parse;
+    fn test_custom_emoji_round_trip() {
+        use crate::generator::{Generator, ParseMode};
 
-        let original = "**bold** *italic* `code`";
-        let parsed = parse(original).unwrap();
-        let generator = Generator::new(ParseMode::MarkdownV2);
+        let emoji = Element::CustomEmoji {
+            emoji: "🔥".to_string(),
+            id: 42,
+        };
+        let mut markdown = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut markdown, &emoji)
+            .unwrap();
+        assert_eq!(parse(&markdown).unwrap(), vec![emoji]);
+    }
 
-        let mut generated = String::new();
-        for element in &parsed {
-            generator.generate(&mut generated, element).unwrap();
+    #[test]
+    fn test_parse_rejects_deep_nesting() {
+        let input = format!("{}x{}", "[".repeat(10_000), "]".repeat(10_000));
+        match parse(&input) {
+            Err(crate::error::Error::Parse(message)) => {
+                assert_eq!(message, "nesting exceeds the maximum depth of 100")
+            }
+            other => panic!("expected a depth error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_with_max_depth() {
+        use crate::parser::parse_with_max_depth;
+
+        assert!(parse_with_max_depth("*_bold italic_*", 1).is_err());
+        assert!(parse_with_max_depth("*_bold italic_*", 3).is_ok());
+    }
+
+    #[test]
+    fn test_parse_unmatched_delimiters_as_text() {
+        assert_eq!(parse("****").unwrap(), vec![Element::Bold(vec![])]);
+        assert_eq!(
+            parse("***").unwrap(),
+            vec![Element::text("*"), Element::text("*"), Element::text("*")]
+        );
+        assert_eq!(
+            parse("**a*").unwrap(),
+            vec![
+                Element::text("*"),
+                Element::Italic(vec![Element::text("a")])
+            ]
+        );
+        assert_eq!(
+            parse("**unclosed").unwrap(),
+            vec![
+                Element::text("*"),
+                Element::text("*"),
+                Element::text("unclosed")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strikethrough_round_trip_telegram() {
+        use crate::generator::{Generator, ParseMode};
+        use crate::parser::{parse_with_dialect, Dialect};
+
+        let element = Element::strikethrough(vec![Element::text("gone")]);
+        let mut output = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut output, &element)
+            .unwrap();
+        assert_eq!(output, "~gone~");
+        assert_eq!(
+            parse_with_dialect(&output, Dialect::Telegram).unwrap(),
+            vec![element]
+        );
+    }
+
+    #[test]
+    fn test_strikethrough_round_trip_commonmark() {
+        use crate::generator::{Generator, ParseMode};
+        use crate::parser::{parse_with_dialect, Dialect};
+
+        let element = Element::strikethrough(vec![Element::text("gone")]);
+        let mut output = String::new();
+        Generator::new(ParseMode::DiscordMarkdown)
+            .generate(&mut output, &element)
+            .unwrap();
+        assert_eq!(output, "~~gone~~");
+        assert_eq!(
+            parse_with_dialect(&output, Dialect::CommonMark).unwrap(),
+            vec![element]
+        );
+
+        let result = parse_with_dialect("~not struck~", Dialect::CommonMark).unwrap();
+        assert_eq!(
+            result
+                .iter()
+                .flat_map(|e| e.text_iter())
+                .collect::<String>(),
+            "~not struck~"
+        );
+        assert!(!result
+            .iter()
+            .any(|e| matches!(e, Element::Strikethrough(_))));
+    }
+
+    #[test]
+    fn test_spoiler_round_trip() {
+        use crate::generator::{Generator, ParseMode};
+        use crate::parser::{parse_with_dialect, Dialect};
+
+        let element = Element::spoiler(vec![Element::text("hidden")]);
+        for (mode, dialect) in [
+            (ParseMode::MarkdownV2, Dialect::Telegram),
+            (ParseMode::DiscordMarkdown, Dialect::CommonMark),
+        ] {
+            let mut output = String::new();
+            Generator::new(mode).generate(&mut output, &element).unwrap();
+            assert_eq!(output, "||hidden||");
+            assert_eq!(
+                parse_with_dialect(&output, dialect).unwrap(),
+                vec![element.clone()]
+            );
+        }
+
+        let result = parse_with_dialect("a | b", Dialect::Telegram).unwrap();
+        assert_eq!(
+            result
+                .iter()
+                .flat_map(|e| e.text_iter())
+                .collect::<String>(),
+            "a | b"
+        );
+        assert!(!result.iter().any(|e| matches!(e, Element::Spoiler(_))));
+    }
+
+    #[test]
+    fn test_mention_id_round_trip() {
+        use crate::generator::{Generator, ParseMode};
+
+        let mention = Element::MentionId {
+            user_id: 123,
+            text: "John Doe".to_string(),
+        };
+        let mut markdown = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut markdown, &mention)
+            .unwrap();
+        assert_eq!(markdown, "[John Doe](tg://user?id=123)");
+        assert_eq!(parse(&markdown).unwrap(), vec![mention]);
+    }
+
+    #[test]
+    fn test_parse_tg_emoji_link() {
+        assert_eq!(
+            parse("[🔥](tg://emoji?id=42)").unwrap(),
+            vec![Element::CustomEmoji {
+                emoji: "🔥".to_string(),
+                id: 42,
+            }]
+        );
+        assert!(matches!(
+            parse("[x](tg://user?id=abc)").unwrap()[0],
+            Element::Link { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_collect_reports_every_error() {
+        use crate::parser::parse_collect;
+
+        let input = "[x](y `z";
+        assert!(parse(input).is_err());
+
+        let (elements, errors) = parse_collect(input);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], crate::error::Error::Parse(m) if m == "Unclosed link"));
+        assert!(matches!(&errors[1], crate::error::Error::Parse(m) if m == "Unclosed code block"));
+        assert_eq!(
+            elements
+                .iter()
+                .flat_map(|e| e.text_iter())
+                .collect::<String>(),
+            "x](y z"
+        );
+    }
+
+    #[test]
+    fn test_parse_collect_without_errors() {
+        use crate::parser::parse_collect;
+
+        let (elements, errors) = parse_collect("*bold* text");
+        assert!(errors.is_empty());
+        assert_eq!(elements, parse("*bold* text").unwrap());
+    }
+
+    #[test]
+    fn test_parse_bare_url() {
+        assert_eq!(
+            parse("see http://a.com now").unwrap(),
+            vec![
+                Element::text("see "),
+                Element::text_link("http://a.com", "http://a.com"),
+                Element::text(" now"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_bare_url_edges() {
+        assert_eq!(
+            parse("*https://a\\.com/x\\_y*").unwrap(),
+            vec![Element::italic(vec![Element::text_link(
+                "https://a.com/x_y",
+                "https://a.com/x_y"
+            )])]
+        );
+        assert_eq!(
+            parse("[docs](https://a.com)").unwrap(),
+            vec![Element::link(vec![Element::text("docs")], "https://a.com")]
+        );
+        assert_eq!(
+            text_iter(&parse("nohttp://a.com").unwrap()).collect::<String>(),
+            "nohttp://a.com"
+        );
+        assert!(parse("nohttp://a.com")
+            .unwrap()
+            .iter()
+            .all(|e| matches!(e, Element::Text(_))));
+    }
+
+    #[test]
+    fn test_parse_improperly_nested_emphasis() {
+        assert_eq!(
+            parse("*a _b* c_").unwrap(),
+            vec![
+                Element::italic(vec![
+                    Element::text("a "),
+                    Element::text("_"),
+                    Element::text("b"),
+                ]),
+                Element::text(" c"),
+                Element::text("_"),
+            ]
+        );
+        assert_eq!(
+            parse("_a *b_ c*").unwrap(),
+            vec![
+                Element::italic(vec![
+                    Element::text("a "),
+                    Element::text("*"),
+                    Element::text("b"),
+                ]),
+                Element::text(" c"),
+                Element::text("*"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_properly_nested_emphasis() {
+        assert_eq!(
+            parse("*a _b_ c*").unwrap(),
+            vec![Element::italic(vec![
+                Element::text("a "),
+                Element::italic(vec![Element::text("b")]),
+                Element::text(" c"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_parse_bare_url_boundaries() {
+        assert_eq!(
+            parse("see https://a.com/x_y_z now").unwrap(),
+            vec![
+                Element::text("see "),
+                Element::text_link("https://a.com/x_y_z", "https://a.com/x_y_z"),
+                Element::text(" now"),
+            ]
+        );
+        assert_eq!(
+            parse("_see https://a.com/x_y_").unwrap(),
+            vec![Element::italic(vec![
+                Element::text("see "),
+                Element::text_link("https://a.com/x_y", "https://a.com/x_y"),
+            ])]
+        );
+        assert_eq!(
+            parse("__https://a.com/__").unwrap(),
+            vec![Element::underline(vec![Element::text_link(
+                "https://a.com/",
+                "https://a.com/"
+            )])]
+        );
+
+        let wiki = "https://en.wikipedia.org/wiki/Rust_(programming_language)";
+        assert_eq!(parse(wiki).unwrap(), vec![Element::text_link(wiki, wiki)]);
+        assert_eq!(
+            parse("(see https://a.com/x)").unwrap(),
+            vec![
+                Element::text("("),
+                Element::text("see "),
+                Element::text_link("https://a.com/x", "https://a.com/x"),
+                Element::text(")"),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod generator_tests {
+    use crate::ast::*;
+    use crate::error::Error;
+    use crate::generator::{Generator, ParseMode};
+
+    #[test]
+    fn test_generate_text_markdown() {
+        let generator = Generator::new(ParseMode::MarkdownV2);
+        let element = Element::Text("Hello, world!".into());
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "Hello, world\\!");
+    }
+
+    #[test]
+    fn test_generate_text_html() {
+        let generator = Generator::new(ParseMode::Html);
+        let element = Element::Text("Hello <world>".into());
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "Hello &lt;world&gt;");
+    }
+
+    #[test]
+    fn test_generate_bold_markdown() {
+        let generator = Generator::new(ParseMode::MarkdownV2);
+        let element = Element::Bold(vec![Element::Text("bold text".into())]);
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "*bold text*");
+    }
+
+    #[test]
+    fn test_generate_bold_html() {
+        let generator = Generator::new(ParseMode::Html);
+        let element = Element::Bold(vec![Element::Text("bold text".into())]);
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "<b>bold text</b>");
+    }
+
+    #[test]
+    fn test_generate_link_markdown() {
+        let generator = Generator::new(ParseMode::MarkdownV2);
+        let element = Element::Link {
+            text: vec![Element::Text("Google".into())],
+            url: "https://google.com".to_string(),
+            title: None,
+        };
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "[Google](https://google.com)");
+    }
+
+    #[test]
+    fn test_generate_link_html() {
+        let generator = Generator::new(ParseMode::Html);
+        let element = Element::Link {
+            text: vec![Element::Text("Google".into())],
+            url: "https://google.com".to_string(),
+            title: None,
+        };
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "<a href=\"https://google.com\">Google</a>");
+    }
+
+    #[test]
+    fn test_generate_pre_markdown() {
+        let generator = Generator::new(ParseMode::MarkdownV2);
+        let element = Element::Pre(PreBlock {
+            code: "fn main() {\n    println!(\"Hello\");\n}".to_string(),
+            language: Some("rust".to_string()),
+        });
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(
+            result,
+            "```rust\nfn main() {\n    println!(\"Hello\");\n}\n```"
+        );
+    }
+
+    #[test]
+    fn test_generate_list() {
+        let generator = Generator::new(ParseMode::MarkdownV2);
+        let element = Element::List(ListNode {
+            style: ListStyle::Bullet,
+            items: vec![
+                ListItem {
+                    content: vec![Element::Text("Item 1".into())],
+                    nested: None,
+                },
+                ListItem {
+                    content: vec![Element::Text("Item 2".into())],
+                    nested: None,
+                },
+            ],
+        });
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "• Item 1\n• Item 2");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        use crate::parser::parse;
+
+        let original = "**bold** *italic* `code`";
+        let parsed = parse(original).unwrap();
+        let generator = Generator::new(ParseMode::MarkdownV2);
+
+        let mut generated = String::new();
+        for element in &parsed {
+            generator.generate(&mut generated, element).unwrap();
         }
 
         assert!(generated.contains("*bold*"));
         assert!(generated.contains("_italic_"));
         assert!(generated.contains("`code`"));
     }
+
+    fn sample_table(style: TableStyle) -> Element {
+        let cell = |text: &str| TableCell {
+            content: vec![Element::text(text)],
+            ..TableCell::default()
+        };
+        Element::Table(TableNode {
+            headers: vec![cell("Name"), cell("Qty")],
+            rows: vec![TableRow {
+                cells: vec![cell("A<B"), cell("10")],
+            }],
+            style,
+            rules: vec![],
+            default_align: CellAlign::Left,
+        })
+    }
+
+    #[test]
+    fn test_generate_table_markdown() {
+        let generator = Generator::new(ParseMode::MarkdownV2);
+        let mut result = String::new();
+        generator
+            .generate(&mut result, &sample_table(TableStyle::Ascii))
+            .unwrap();
+        assert_eq!(
+            result,
+            "```\n+------+-----+\n| Name | Qty |\n+------+-----+\n| A<B  | 10  |\n+------+-----+\n```"
+        );
+    }
+
+    #[test]
+    fn test_generate_table_html() {
+        let generator = Generator::new(ParseMode::Html);
+        let mut result = String::new();
+        generator
+            .generate(&mut result, &sample_table(TableStyle::Ascii))
+            .unwrap();
+        assert_eq!(
+            result,
+            "<pre>+------+-----+\n| Name | Qty |\n+------+-----+\n| A&lt;B  | 10  |\n+------+-----+</pre>"
+        );
+        assert!(!result.contains("```"));
+    }
+
+    fn styled_table(style: CellStyle) -> Element {
+        Element::Table(TableNode {
+            headers: vec![TableCell {
+                content: vec![Element::text("Item")],
+                ..TableCell::default()
+            }],
+            rows: vec![
+                TableRow {
+                    cells: vec![TableCell {
+                        content: vec![Element::text("Apple")],
+                        ..TableCell::default()
+                    }],
+                },
+                TableRow {
+                    cells: vec![TableCell {
+                        content: vec![Element::text("Total")],
+                        style,
+                        ..TableCell::default()
+                    }],
+                },
+            ],
+            style: TableStyle::Compact,
+            rules: vec![],
+            default_align: CellAlign::Left,
+        })
+    }
+
+    #[test]
+    fn test_generate_table_bold_cell_markdown() {
+        let generator = Generator::new(ParseMode::MarkdownV2);
+        let mut result = String::new();
+        generator
+            .generate(&mut result, &styled_table(CellStyle::Bold))
+            .unwrap();
+        assert_eq!(result, "```\n  Item     \n  Apple    \n  *Total*  \n```");
+    }
+
+    #[test]
+    fn test_generate_table_bold_cell_html() {
+        let generator = Generator::new(ParseMode::Html);
+        let mut result = String::new();
+        generator
+            .generate(&mut result, &styled_table(CellStyle::Bold))
+            .unwrap();
+        assert_eq!(result, "<pre>  Item   \n  Apple  \n  <b>Total</b>  </pre>");
+    }
+
+    #[test]
+    fn test_generator_default() {
+        let generator = Generator::default();
+        let element = Element::Bold(vec![Element::Text("bold.".into())]);
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "*bold\\.*");
+    }
+
+    #[test]
+    fn test_generate_link_with_title() {
+        let element = Element::Link {
+            text: vec![Element::Text("Rust".into())],
+            url: "https://rust-lang.org".to_string(),
+            title: Some("The \"Rust\" site".to_string()),
+        };
+
+        let mut result = String::new();
+        Generator::new(ParseMode::Html)
+            .generate(&mut result, &element)
+            .unwrap();
+        assert_eq!(
+            result,
+            "<a href=\"https://rust-lang.org\" title=\"The &quot;Rust&quot; site\">Rust</a>"
+        );
+
+        let mut result = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut result, &element)
+            .unwrap();
+        assert_eq!(result, "[Rust](https://rust-lang.org)");
+    }
+
+    #[test]
+    fn test_generate_html_table_style() {
+        let table = Element::Table(TableNode {
+            headers: vec![
+                TableCell {
+                    content: vec![Element::text("Item")],
+                    ..TableCell::default()
+                },
+                TableCell {
+                    content: vec![Element::text("Price")],
+                    align: CellAlign::Right,
+                    ..TableCell::default()
+                },
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell {
+                            content: vec![Element::text("Fish & Chips")],
+                            ..TableCell::default()
+                        },
+                        TableCell {
+                            content: vec![Element::text("9.50")],
+                            align: CellAlign::Right,
+                            ..TableCell::default()
+                        },
+                    ],
+                },
+                TableRow {
+                    cells: vec![TableCell {
+                        content: vec![Element::text("Sold out")],
+                        align: CellAlign::Center,
+                        colspan: 2,
+                        ..TableCell::default()
+                    }],
+                },
+            ],
+            style: TableStyle::Html,
+            rules: vec![],
+            default_align: CellAlign::Left,
+        });
+
+        let mut result = String::new();
+        Generator::new(ParseMode::Html)
+            .generate(&mut result, &table)
+            .unwrap();
+        assert_eq!(
+            result,
+            "<table><thead><tr>\
+             <th style=\"text-align:left\">Item</th>\
+             <th style=\"text-align:right\">Price</th>\
+             </tr></thead><tbody>\
+             <tr><td style=\"text-align:left\">Fish &amp; Chips</td>\
+             <td style=\"text-align:right\">9.50</td></tr>\
+             <tr><td style=\"text-align:center\" colspan=\"2\">Sold out</td></tr>\
+             </tbody></table>"
+        );
+
+        let mut result = String::new();
+        assert!(matches!(
+            Generator::new(ParseMode::MarkdownV2).generate(&mut result, &table),
+            Err(Error::InvalidTable(_))
+        ));
+    }
+
+    #[test]
+    fn test_autolink_entities() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        let element = Element::text("follow @rustlang and #rust!");
+
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "follow @rustlang and \\#rust\\!");
+
+        generator.autolink_entities(true);
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "follow @rustlang and \\#rust\\!");
+
+        let element = Element::text("ping @rust_lang");
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "ping @rust\\_lang");
+    }
+
+    #[test]
+    fn test_autolink_entities_elements() {
+        use crate::generator::autolink_text;
+
+        assert_eq!(
+            autolink_text("follow @rustlang and #rust!"),
+            vec![
+                Element::text("follow "),
+                Element::mention("rustlang"),
+                Element::text(" and "),
+                Element::hashtag("rust"),
+                Element::text("!"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_autolink_entities_skips_emails() {
+        use crate::generator::autolink_text;
+
+        assert_eq!(
+            autolink_text("mail me at admin@example.com or ping @admin"),
+            vec![
+                Element::text("mail me at admin@example.com or ping "),
+                Element::mention("admin"),
+            ]
+        );
+        assert_eq!(
+            autolink_text("issue#42 is fixed"),
+            vec![Element::text("issue#42 is fixed")]
+        );
+    }
+
+    #[test]
+    fn test_strip_control_chars() {
+        let mut generator = Generator::new(ParseMode::Html);
+        let element = Element::text("invoice\u{202E}fdp.exe\nok\u{200B}");
+
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "invoice\u{202E}fdp.exe\nok\u{200B}");
+
+        generator.strip_control_chars(true);
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "invoicefdp.exe\nok");
+    }
+
+    #[test]
+    fn test_strip_control_chars_keeps_emoji_zwj() {
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.strip_control_chars(true);
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let element = Element::text(format!("a\u{200D}b {}", family));
+
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, format!("ab {}", family));
+    }
+
+    #[test]
+    fn test_wrap_at_column_width() {
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.wrap_at(Some(20));
+        let element = Element::Group(vec![
+            Element::text("The quick brown fox jumps over the lazy dog and keeps running"),
+            Element::Code("not wrapped at all by the generator ever".to_string()),
+        ]);
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(
+            result,
+            "The quick brown fox\njumps over the lazy\ndog and keeps\nrunning<code>not wrapped at all by the generator ever</code>"
+        );
+    }
+
+    #[test]
+    fn test_wrap_at_respects_newlines_and_cjk_width() {
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.wrap_at(Some(10));
+        let element = Element::text("short\n你好世界你好世界你好世界");
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "short\n你好世界你\n好世界你好\n世界");
+    }
+
+    #[test]
+    fn test_generate_nested_list_with_level_bullets() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.set_bullets(vec!["•".to_string(), "◦".to_string(), "▪".to_string()]);
+        let element = Element::List(ListNode {
+            style: ListStyle::Bullet,
+            items: vec![ListItem {
+                content: vec![Element::Text("Fruit".into())],
+                nested: Some(Box::new(ListNode {
+                    style: ListStyle::Bullet,
+                    items: vec![
+                        ListItem {
+                            content: vec![Element::Text("Apple".into())],
+                            nested: None,
+                        },
+                        ListItem {
+                            content: vec![Element::Text("Pear".into())],
+                            nested: None,
+                        },
+                    ],
+                })),
+            }],
+        });
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "• Fruit\n  ◦ Apple\n  ◦ Pear\n");
+    }
+
+    #[test]
+    fn test_generate_raw_html() {
+        let element = Element::raw_html("<u>x</u> & <b>y</b>");
+
+        let mut html = String::new();
+        Generator::new(ParseMode::Html)
+            .generate(&mut html, &element)
+            .unwrap();
+        assert_eq!(html, "<u>x</u> & <b>y</b>");
+
+        let mut markdown = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut markdown, &element)
+            .unwrap();
+        assert_eq!(markdown, r"<u\>x</u\> & <b\>y</b\>");
+    }
+
+    #[test]
+    fn test_escape_matches_generated_text() {
+        let text = "Price: 5.00 (50% off!) <b>&</b> [x]_*";
+        for mode in [ParseMode::MarkdownV2, ParseMode::Html] {
+            let mut generated = String::new();
+            Generator::new(mode)
+                .generate(&mut generated, &Element::text(text))
+                .unwrap();
+            assert_eq!(crate::escape(text, mode), generated);
+        }
+
+        assert_eq!(crate::escape("a.b-c!", ParseMode::MarkdownV2), r"a\.b\-c\!");
+        assert_eq!(
+            crate::escape("a < b & c", ParseMode::Html),
+            "a &lt; b &amp; c"
+        );
+    }
+
+    #[test]
+    fn test_generate_quote_with_cite() {
+        let element = Element::quote_with_cite(
+            vec![Element::text("Talk is cheap.\nShow me the code.")],
+            "Linus",
+        );
+
+        let mut markdown = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut markdown, &element)
+            .unwrap();
+        assert_eq!(
+            markdown,
+            ">Talk is cheap\\.\n>Show me the code\\.\n>_— Linus_"
+        );
+
+        let mut html = String::new();
+        Generator::new(ParseMode::Html)
+            .generate(&mut html, &element)
+            .unwrap();
+        assert_eq!(
+            html,
+            "<blockquote>Talk is cheap.\nShow me the code.\n<i>— Linus</i></blockquote>"
+        );
+    }
+
+    #[test]
+    fn test_generate_table_fence_lang() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.table_fence_lang(Some("text".to_string()));
+        let mut result = String::new();
+        generator
+            .generate(&mut result, &sample_table(TableStyle::Ascii))
+            .unwrap();
+        assert!(result.starts_with("```text\n+------+-----+\n"));
+        assert!(result.ends_with("+------+-----+\n```"));
+    }
+
+    #[test]
+    fn test_generate_table_pads_by_display_width() {
+        let cell = |text: &str| TableCell {
+            content: vec![Element::text(text)],
+            ..TableCell::default()
+        };
+        let element = Element::Table(TableNode {
+            headers: vec![cell("Cafe\u{301}")],
+            rows: vec![
+                TableRow {
+                    cells: vec![cell("👨\u{200D}👩\u{200D}👧")],
+                },
+                TableRow {
+                    cells: vec![cell("Tea")],
+                },
+            ],
+            style: TableStyle::Ascii,
+            rules: vec![],
+            default_align: CellAlign::Left,
+        });
+        let mut result = String::new();
+        Generator::new(ParseMode::Html)
+            .generate(&mut result, &element)
+            .unwrap();
+        assert_eq!(
+            result,
+            "<pre>+------+\n| Cafe\u{301} |\n+------+\n| 👨\u{200D}👩\u{200D}👧   |\n| Tea  |\n+------+</pre>"
+        );
+    }
+
+    #[test]
+    fn test_strict_raw_html() {
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.strict(true);
+
+        let mut output = String::new();
+        generator
+            .generate(
+                &mut output,
+                &Element::raw_html("<b>x</b> <a href=\"u\">y</a>"),
+            )
+            .unwrap();
+        assert_eq!(output, "<b>x</b> <a href=\"u\">y</a>");
+
+        for bad in ["<b>x", "<b>x</i>", "x</b>", "<div>x</div>", "<b x"] {
+            let mut output = String::new();
+            let result = generator.generate(&mut output, &Element::raw_html(bad));
+            assert!(matches!(result, Err(Error::Generation(_))), "{}", bad);
+            assert!(generator.validate(&Element::raw_html(bad)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_raw_html_unchecked_by_default() {
+        let generator = Generator::new(ParseMode::Html);
+        let mut output = String::new();
+        generator
+            .generate(&mut output, &Element::raw_html("<b>x"))
+            .unwrap();
+        assert_eq!(output, "<b>x");
+    }
+
+    #[test]
+    fn test_generate_command_with_bot() {
+        let generator = Generator::new(ParseMode::MarkdownV2);
+
+        let mut result = String::new();
+        generator
+            .generate(&mut result, &Element::command("start"))
+            .unwrap();
+        assert_eq!(result, "/start");
+
+        let mut result = String::new();
+        generator
+            .generate(&mut result, &Element::command_for_bot("start", "MyBot"))
+            .unwrap();
+        assert_eq!(result, "/start@MyBot");
+
+        let mut result = String::new();
+        generator
+            .generate(&mut result, &Element::command_for_bot("start", "my_bot"))
+            .unwrap();
+        assert_eq!(result, "/start@my\\_bot");
+    }
+
+    #[test]
+    fn test_empty_containers_emit_no_markers() {
+        let elements = vec![
+            Element::text("a"),
+            Element::bold(vec![]),
+            Element::Spoiler(vec![Element::italic(vec![Element::text("")])]),
+            Element::text("b"),
+        ];
+        for mode in [ParseMode::MarkdownV2, ParseMode::Html] {
+            let generator = Generator::new(mode);
+            let mut output = String::new();
+            for element in &elements {
+                generator.generate(&mut output, element).unwrap();
+            }
+            assert_eq!(output, "ab");
+        }
+    }
+
+    #[test]
+    fn test_generator_max_depth() {
+        let mut element = Element::text("deep");
+        for _ in 0..150 {
+            element = Element::bold(vec![element]);
+        }
+
+        let mut generator = Generator::new(ParseMode::Html);
+        let mut output = String::new();
+        assert!(matches!(
+            generator.generate(&mut output, &element),
+            Err(crate::error::Error::Generation(_))
+        ));
+
+        generator.max_depth(200);
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert!(output.contains("deep"));
+    }
+
+    #[test]
+    fn test_custom_marker_hanging_indent() {
+        let list = Element::List(ListNode {
+            style: ListStyle::Custom("→".to_string()),
+            items: vec![
+                ListItem {
+                    content: vec![Element::text("one two three four")],
+                    nested: None,
+                },
+                ListItem {
+                    content: vec![Element::text("five")],
+                    nested: None,
+                },
+            ],
+        });
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.wrap_at(Some(12));
+        let mut output = String::new();
+        generator.generate(&mut output, &list).unwrap();
+        assert_eq!(output, "→ one two\n  three four\n→ five");
+    }
+
+    #[test]
+    fn test_empty_custom_marker() {
+        let list = Element::List(ListNode {
+            style: ListStyle::Custom(String::new()),
+            items: vec![
+                ListItem {
+                    content: vec![Element::text("first")],
+                    nested: None,
+                },
+                ListItem {
+                    content: vec![Element::text("second")],
+                    nested: None,
+                },
+            ],
+        });
+        let generator = Generator::new(ParseMode::Html);
+        let mut output = String::new();
+        generator.generate(&mut output, &list).unwrap();
+        assert_eq!(output, "first\nsecond");
+    }
+
+    #[test]
+    fn test_render_lossy_replaces_failed_elements() {
+        let elements = vec![
+            Element::text("Total: "),
+            Element::Custom {
+                formatter: "missing".to_string(),
+                value: "42".to_string(),
+            },
+            Element::bold(vec![Element::text("done")]),
+        ];
+        let generator = Generator::new(ParseMode::Html);
+        assert_eq!(generator.render_lossy(&elements), "Total: [?]<b>done</b>");
+    }
+
+    #[test]
+    fn test_image_rendering() {
+        let image = Element::image("https://example.com/cat.png", "A cat");
+
+        let mut html = String::new();
+        Generator::new(ParseMode::Html)
+            .generate(&mut html, &image)
+            .unwrap();
+        assert_eq!(
+            html,
+            "<img src=\"https://example.com/cat.png\" alt=\"A cat\">"
+        );
+
+        let mut markdown = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut markdown, &image)
+            .unwrap();
+        assert_eq!(markdown, "[A cat](https://example.com/cat.png)");
+    }
+
+    #[test]
+    fn test_preformatted_uses_fragment_for_mode() {
+        let elements = vec![
+            Element::text("Price: "),
+            Element::preformatted("*1\\.5 $*", "<b>1.5 $</b>"),
+        ];
+
+        let html = Generator::new(ParseMode::Html).render_lossy(&elements);
+        assert_eq!(html, "Price: <b>1.5 $</b>");
+
+        let markdown = Generator::new(ParseMode::MarkdownV2).render_lossy(&elements);
+        assert_eq!(markdown, "Price: *1\\.5 $*");
+    }
+
+    #[test]
+    fn test_normalize_code_lang() {
+        let pre = |lang: &str| {
+            Element::Pre(PreBlock {
+                code: "x".to_string(),
+                language: Some(lang.to_string()),
+            })
+        };
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.normalize_code_lang(true);
+
+        let mut output = String::new();
+        generator.generate(&mut output, &pre("JS")).unwrap();
+        assert_eq!(
+            output,
+            "<pre><code class=\"language-javascript\">x</code></pre>"
+        );
+
+        let mut output = String::new();
+        generator.generate(&mut output, &pre("brainfuck")).unwrap();
+        assert_eq!(output, "<pre>x</pre>");
+
+        generator.normalize_code_lang(false);
+        let mut output = String::new();
+        generator.generate(&mut output, &pre("brainfuck")).unwrap();
+        assert_eq!(
+            output,
+            "<pre><code class=\"language-brainfuck\">x</code></pre>"
+        );
+    }
+
+    #[test]
+    fn test_normalize_code_lang_markdown() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.normalize_code_lang(true);
+        let element = Element::Pre(PreBlock {
+            code: "fn main() {}".to_string(),
+            language: Some("rs".to_string()),
+        });
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert_eq!(output, "```rust\nfn main() {}\n```");
+    }
+
+    #[test]
+    fn test_discord_escaping_differs_from_markdown_v2() {
+        let element = Element::text("v1.5 is out! (really) *not* bold");
+
+        let mut markdown = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut markdown, &element)
+            .unwrap();
+        assert_eq!(markdown, r"v1\.5 is out\! \(really\) \*not\* bold");
+
+        let mut discord = String::new();
+        Generator::new(ParseMode::DiscordMarkdown)
+            .generate(&mut discord, &element)
+            .unwrap();
+        assert_eq!(discord, r"v1.5 is out! (really) \*not\* bold");
+    }
+
+    #[test]
+    fn test_discord_formatting() {
+        let element = Element::Group(vec![
+            Element::bold(vec![Element::text("b")]),
+            Element::italic(vec![Element::text("i")]),
+            Element::underline(vec![Element::text("u")]),
+            Element::strikethrough(vec![Element::text("s")]),
+            Element::spoiler(vec![Element::text("x")]),
+            Element::Code("a`b".to_string()),
+            Element::Link {
+                text: vec![Element::text("docs!")],
+                url: "https://example.com/a_(b)".to_string(),
+                title: None,
+            },
+            Element::Mention {
+                username: "user".to_string(),
+            },
+        ]);
+        let mut output = String::new();
+        Generator::new(ParseMode::DiscordMarkdown)
+            .generate(&mut output, &element)
+            .unwrap();
+        assert_eq!(
+            output,
+            "**b***i*__u__~~s~~||x||`` a`b ``[docs!](https://example.com/a_(b%29)@user"
+        );
+    }
+
+    #[test]
+    fn test_discord_pre_and_table() {
+        let pre = Element::Pre(PreBlock {
+            code: "let s = \"```\";".to_string(),
+            language: Some("rust".to_string()),
+        });
+        let mut output = String::new();
+        Generator::new(ParseMode::DiscordMarkdown)
+            .generate(&mut output, &pre)
+            .unwrap();
+        assert_eq!(output, "```rust\nlet s = \"`\u{200b}``\";\n```");
+
+        let table = Element::Table(TableNode {
+            headers: vec![TableCell {
+                content: vec![Element::text("a.b")],
+                align: CellAlign::Left,
+                style: CellStyle::Bold,
+                colspan: 1,
+                rowspan: 1,
+            }],
+            rows: vec![],
+            style: TableStyle::Minimal,
+            rules: vec![],
+            default_align: CellAlign::Left,
+        });
+        let mut output = String::new();
+        Generator::new(ParseMode::DiscordMarkdown)
+            .generate(&mut output, &table)
+            .unwrap();
+        assert!(output.starts_with("```\n"));
+        assert!(output.contains("a.b"));
+        assert!(!output.contains('*'));
+    }
+
+    #[test]
+    fn test_slack_bold() {
+        let element = Element::Group(vec![
+            Element::bold(vec![Element::text("bold")]),
+            Element::underline(vec![Element::text(" plain")]),
+        ]);
+        let mut output = String::new();
+        Generator::new(ParseMode::SlackMrkdwn)
+            .generate(&mut output, &element)
+            .unwrap();
+        assert_eq!(output, "*bold* plain");
+    }
+
+    #[test]
+    fn test_slack_link() {
+        let element = Element::Link {
+            text: vec![Element::text("the docs")],
+            url: "https://example.com/?a=1|2".to_string(),
+            title: None,
+        };
+        let mut output = String::new();
+        Generator::new(ParseMode::SlackMrkdwn)
+            .generate(&mut output, &element)
+            .unwrap();
+        assert_eq!(output, "<https://example.com/?a=1%7C2|the docs>");
+    }
+
+    #[test]
+    fn test_slack_escapes_angle_brackets() {
+        let element = Element::text("a < b & c!");
+        let mut output = String::new();
+        Generator::new(ParseMode::SlackMrkdwn)
+            .generate(&mut output, &element)
+            .unwrap();
+        assert_eq!(output, "a &lt; b &amp; c!");
+    }
+
+    #[test]
+    fn test_annotate_bold_italic() {
+        let element = Element::bold(vec![
+            Element::text("a "),
+            Element::italic(vec![Element::text("b")]),
+        ]);
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.annotate(true);
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert_eq!(output, "[Bold]*a [Italic]_b_[/Italic]*[/Bold]");
+
+        generator.annotate(false);
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert_eq!(output, "*a _b_*");
+    }
+
+    #[test]
+    fn test_table_default_align_with_override() {
+        let cell = |text: &str| TableCell {
+            content: vec![Element::text(text)],
+            ..TableCell::default()
+        };
+        let table = Element::Table(TableNode {
+            headers: vec![cell("Amount")],
+            rows: vec![
+                TableRow {
+                    cells: vec![cell("5")],
+                },
+                TableRow {
+                    cells: vec![TableCell {
+                        align: CellAlign::Left,
+                        ..cell("7")
+                    }],
+                },
+            ],
+            style: TableStyle::Compact,
+            rules: vec![],
+            default_align: CellAlign::Right,
+        });
+
+        let mut result = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut result, &table)
+            .unwrap();
+        assert_eq!(
+            result,
+            "```\n  Amount  \n       5  \n  7       \n```"
+        );
+    }
+
+    #[test]
+    fn test_strict_mentions() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        let short = Element::mention("abc");
+
+        let mut output = String::new();
+        generator.generate(&mut output, &short).unwrap();
+        assert_eq!(output, "@abc");
+
+        generator.strict(true);
+        let mut output = String::new();
+        assert!(matches!(
+            generator.generate(&mut output, &short),
+            Err(Error::InvalidUsername(_))
+        ));
+        assert!(generator.validate(&short).is_err());
+        assert!(generator.validate(&Element::mention("valid_name")).is_ok());
+    }
+
+    #[test]
+    fn test_render_cache_serves_repeated_elements() {
+        use crate::formatter::{CustomFormatter, FormatContext};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        struct Counting(Arc<AtomicUsize>);
+
+        impl CustomFormatter for Counting {
+            fn name(&self) -> &str {
+                "counting"
+            }
+
+            fn format(
+                &self,
+                value: &str,
+                _: ParseMode,
+                _: &FormatContext,
+            ) -> crate::Result<String> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(value.to_string())
+            }
+
+            fn parse(&self, _: &str) -> Option<(String, usize)> {
+                None
+            }
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut generator = Generator::new(ParseMode::MarkdownV2).with_cache(4);
+        generator.register_formatter(Box::new(Counting(calls.clone())));
+
+        let element = Element::Custom {
+            formatter: "counting".to_string(),
+            value: "42".to_string(),
+        };
+        for _ in 0..2 {
+            let mut output = String::new();
+            generator.generate(&mut output, &element).unwrap();
+            assert_eq!(output, "42");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        generator.strict(true);
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_unknown_formatter_passthrough() {
+        let element = Element::Custom {
+            formatter: "missing".to_string(),
+            value: "1.5".to_string(),
+        };
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+
+        let mut output = String::new();
+        assert!(matches!(
+            generator.generate(&mut output, &element),
+            Err(Error::FormatterNotFound(name)) if name == "missing"
+        ));
+
+        generator.unknown_formatter_passthrough(true);
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert_eq!(output, "1\\.5");
+        assert!(generator.validate(&element).is_ok());
+    }
+
+    #[test]
+    fn test_multiline_code_promoted_to_pre() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+
+        let mut output = String::new();
+        generator
+            .generate(&mut output, &Element::code("x = 1"))
+            .unwrap();
+        assert_eq!(output, "`x = 1`");
+
+        let multiline = Element::code("a\nb");
+        let mut output = String::new();
+        generator.generate(&mut output, &multiline).unwrap();
+        assert_eq!(output, "```\na\nb\n```");
+
+        generator.strict(true);
+        let mut output = String::new();
+        assert!(matches!(
+            generator.generate(&mut output, &multiline),
+            Err(Error::Generation(_))
+        ));
+        assert!(generator.validate(&multiline).is_err());
+        assert!(generator.validate(&Element::code("x = 1")).is_ok());
+    }
+
+    #[test]
+    fn test_generate_list_without_markers() {
+        let element = Element::List(ListNode {
+            style: ListStyle::None,
+            items: vec![
+                ListItem {
+                    content: vec![Element::text("First")],
+                    nested: None,
+                },
+                ListItem {
+                    content: vec![Element::text("Second")],
+                    nested: None,
+                },
+            ],
+        });
+        for mode in [ParseMode::MarkdownV2, ParseMode::Html] {
+            let mut result = String::new();
+            Generator::new(mode)
+                .generate(&mut result, &element)
+                .unwrap();
+            assert_eq!(result, "First\nSecond");
+        }
+    }
+
+    #[test]
+    fn test_link_keeps_formatted_text() {
+        let link = Element::link(
+            vec![
+                Element::bold(vec![Element::text("bold")]),
+                Element::text(" text"),
+            ],
+            "https://example.com",
+        );
+        let mut markdown = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut markdown, &link)
+            .unwrap();
+        assert_eq!(markdown, "[*bold* text](https://example.com)");
+
+        let mut html = String::new();
+        Generator::new(ParseMode::Html)
+            .generate(&mut html, &link)
+            .unwrap();
+        assert_eq!(html, "<a href=\"https://example.com\"><b>bold</b> text</a>");
+    }
+
+    #[test]
+    fn test_link_inside_link_is_flattened() {
+        let link = Element::link(
+            vec![
+                Element::text("see "),
+                Element::italic(vec![Element::link(
+                    vec![Element::text("inner")],
+                    "https://inner.example",
+                )]),
+            ],
+            "https://example.com",
+        );
+        let mut markdown = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut markdown, &link)
+            .unwrap();
+        assert_eq!(markdown, "[see _inner_](https://example.com)");
+
+        let mut html = String::new();
+        Generator::new(ParseMode::Html)
+            .generate(&mut html, &link)
+            .unwrap();
+        assert_eq!(html, "<a href=\"https://example.com\">see <i>inner</i></a>");
+    }
+
+    #[test]
+    fn test_display_in_format() {
+        let bold = Element::bold(vec![Element::text("hi.")]);
+        let markdown = Generator::new(ParseMode::MarkdownV2);
+        let html = Generator::new(ParseMode::Html);
+        assert_eq!(
+            format!("Say {}!", markdown.display(&bold)),
+            "Say *hi\\.*!"
+        );
+        assert_eq!(format!("{}", html.display(&bold)), "<b>hi.</b>");
+        assert_eq!(
+            format!("Say {}!", bold.display(ParseMode::Html)),
+            "Say <b>hi.</b>!"
+        );
+    }
+
+    #[test]
+    fn test_display_uses_generator_settings() {
+        use crate::formatter::PercentFormatter;
+        use std::fmt::Write;
+
+        let element = Element::Custom {
+            formatter: "percent".to_string(),
+            value: "42".to_string(),
+        };
+        let mut generator = Generator::new(ParseMode::Html);
+        let mut output = String::new();
+        assert!(write!(output, "{}", generator.display(&element)).is_err());
+
+        generator.register_formatter(Box::new(PercentFormatter));
+        assert_eq!(
+            generator.display(&element).to_string(),
+            "<code>42.0%</code>"
+        );
+
+        let pre = Element::Preformatted {
+            markdown_v2: "*a*".to_string(),
+            html: "<b>a</b>".to_string(),
+        };
+        for mode in [ParseMode::DiscordMarkdown, ParseMode::SlackMrkdwn] {
+            let generator = Generator::new(mode);
+            let mut output = String::new();
+            assert!(write!(output, "{}", generator.display(&pre)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_markdown_escape_chars() {
+        let element = Element::text("v1.0 (beta)!");
+
+        let mut output = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut output, &element)
+            .unwrap();
+        assert_eq!(output, "v1\\.0 \\(beta\\)\\!");
+
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.markdown_escape_chars(['(', ')', '!'].into_iter().collect());
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert_eq!(output, "v1.0 \\(beta\\)\\!");
+    }
+
+    #[test]
+    fn test_pre_line_numbers() {
+        let element = Element::pre("let a = 1;\nlet b = 2;\na + b", None);
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.pre_line_numbers(true);
+
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert_eq!(output, "```\n1 let a = 1;\n2 let b = 2;\n3 a + b\n```");
+
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.pre_line_numbers(true);
+        let mut output = String::new();
+        generator.generate(&mut output, &element).unwrap();
+        assert_eq!(output, "<pre>1 let a = 1;\n2 let b = 2;\n3 a + b</pre>");
+    }
+
+    #[test]
+    fn test_pre_line_numbers_align() {
+        let code = (1..=10)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.pre_line_numbers(true);
+
+        let mut output = String::new();
+        generator
+            .generate(&mut output, &Element::pre(code, None))
+            .unwrap();
+        assert!(output.starts_with("```\n 1 1\n 2 2\n"));
+        assert!(output.ends_with("\n10 10\n```"));
+    }
+
+    #[test]
+    fn test_max_output_len() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.max_output_len(Some(10));
+
+        let mut output = String::new();
+        generator
+            .generate(&mut output, &Element::bold(vec![Element::text("short")]))
+            .unwrap();
+        assert_eq!(output, "*short*");
+
+        let long = Element::List(ListNode {
+            style: ListStyle::Bullet,
+            items: (0..1000)
+                .map(|i| ListItem {
+                    content: vec![Element::text(format!("item {}", i))],
+                    nested: None,
+                })
+                .collect(),
+        });
+        let mut output = String::new();
+        match generator.generate(&mut output, &long) {
+            Err(Error::Generation(message)) => {
+                assert_eq!(message, "output exceeds the maximum length of 10 bytes")
+            }
+            other => panic!("expected a length error, got {:?}", other),
+        }
+        assert!(output.len() <= 10);
+    }
+
+    #[test]
+    fn test_html_tg_link_href_escaping() {
+        let url = "tg://resolve?domain=x&start=y";
+        let generator = Generator::new(ParseMode::Html);
+
+        let mut output = String::new();
+        generator
+            .generate(
+                &mut output,
+                &Element::link(vec![Element::text("open")], url),
+            )
+            .unwrap();
+        assert_eq!(
+            output,
+            "<a href=\"tg://resolve?domain=x&amp;start=y\">open</a>"
+        );
+
+        let mut output = String::new();
+        generator
+            .generate(
+                &mut output,
+                &Element::text_link("open", "tg://x?a=\"1\"&b=2"),
+            )
+            .unwrap();
+        assert_eq!(
+            output,
+            "<a href=\"tg://x?a=&quot;1&quot;&amp;b=2\">open</a>"
+        );
+    }
+
+    #[test]
+    fn test_max_output_len_across_elements() {
+        let elements: Vec<Element> = (0..100).map(|_| Element::text("abc")).collect();
+
+        for generator in [
+            Generator::new(ParseMode::MarkdownV2),
+            Generator::new(ParseMode::MarkdownV2).with_cache(4),
+        ] {
+            let mut generator = generator;
+            generator.max_output_len(Some(10));
+
+            let mut output = String::new();
+            assert!(matches!(
+                generator.generate_all(&mut output, &elements),
+                Err(Error::Generation(_))
+            ));
+            assert_eq!(output, "abcabcabc");
+
+            assert_eq!(generator.render_lossy(&elements), "abcabcabc[?]");
+
+            let mut output = String::new();
+            generator.generate_all(&mut output, &elements[..3]).unwrap();
+            assert_eq!(output, "abcabcabc");
+        }
+    }
+
+    #[test]
+    fn test_max_output_len_with_cache() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2).with_cache(4);
+        generator.max_output_len(Some(10));
+        let long = Element::text("a".repeat(100));
+
+        let mut output = String::new();
+        assert!(generator.generate(&mut output, &long).is_err());
+        assert!(output.is_empty());
+
+        generator.max_output_len(None);
+        let mut output = String::new();
+        generator.generate(&mut output, &long).unwrap();
+        assert_eq!(output.len(), 100);
+    }
+}
+
+#[cfg(test)]
+mod formatter_tests {
+    use crate::ast::Element;
+    use crate::error::Error;
+    use crate::formatter::{
+        CurrencyFormatter, CustomFormatter, DateFormatter, FlagFormatter, FormatContext,
+        PercentFormatter, PhoneFormatter, ProgressFormatter, RelativeTimeFormatter,
+    };
+    use crate::generator::{Generator, ParseMode};
+
+    #[test]
+    fn test_phone_formatter_name() {
+        let formatter = PhoneFormatter;
+        assert_eq!(formatter.name(), "phone");
+    }
+
+    #[test]
+    fn test_phone_formatter_parse_international() {
+        let formatter = PhoneFormatter;
+        
+        let test_cases = vec![
+            ("+1234567890", "+1234567890", 11),
+            ("+1 234 567 890", "+1 234 567 890", 14),
+            ("+1-234-567-890", "+1-234-567-890", 14),
+            ("+1(234)567-890", "+1(234)567-890", 14),
+            ("+7 (495) 123-45-67", "+7 (495) 123-45-67", 18),
+            ("+380 44 123 4567", "+380 44 123 4567", 16),
+        ];
+
+        for (input, expected, expected_len) in test_cases {
+            let result = formatter.parse(input);
+            assert!(result.is_some(), "Failed to parse: {}", input);
+            let (parsed, len) = result.unwrap();
+            assert_eq!(parsed, expected, "Failed for input: {}", input);
+            assert_eq!(len, expected_len, "Wrong length for: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_phone_formatter_parse_local() {
+        let formatter = PhoneFormatter;
+        
+        let test_cases = vec![
+            ("1234567890", "1234567890", 10),
+            ("123-456-7890", "123-456-7890", 12),
+            ("(123) 456-7890", "(123) 456-7890", 14),
+            ("123 456 7890", "123 456 7890", 12),
+            ("8 800 555 35 35", "8 800 555 35 35", 15),
+        ];
+
+        for (input, expected, expected_len) in test_cases {
+            let result = formatter.parse(input);
+            assert!(result.is_some(), "Failed to parse: {}", input);
+            let (parsed, len) = result.unwrap();
+            assert_eq!(parsed, expected, "Failed for input: {}", input);
+            assert_eq!(len, expected_len, "Wrong length for: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_phone_formatter_parse_with_text() {
+        let formatter = PhoneFormatter;
+        
+        let input = "+1234567890 call me";
+        let result = formatter.parse(input);
+        assert!(result.is_some());
+        let (parsed, len) = result.unwrap();
+        assert_eq!(parsed.trim(), "+1234567890");
+        assert_eq!(len, 12); // includes the trailing space
+    }
+
+    #[test]
+    fn test_phone_formatter_parse_empty() {
+        let formatter = PhoneFormatter;
+        
+        let input = "";
+        let result = formatter.parse(input);
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_phone_formatter_parse_non_phone() {
+        let formatter = PhoneFormatter;
+        
+        let input = "abc def";
+        let result = formatter.parse(input);
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_phone_formatter_format_markdown() {
+        let formatter = PhoneFormatter;
+        let ctx = FormatContext::default();
+        
+        let test_cases = vec![
+            ("+7 999 735-35-74", "`\\+7\\(999\\) 735\\-35\\-74`"),
+            ("8 (999) 735-35-74", "`\\+7\\(999\\) 735\\-35\\-74`"),
+            ("+1 (202) 555-0123", "`\\+1 \\(202\\) 555\\-0123`"),
+            ("+49 170 1234 5678", "`\\+4917012345678`"),
+        ];
+
+        for (input, expected) in test_cases {
+            let result = formatter.format(input, ParseMode::MarkdownV2, &ctx).unwrap();
+            assert_eq!(result, expected, "Failed for input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_phone_formatter_format_html() {
+        let formatter = PhoneFormatter;
+        let ctx = FormatContext::default();
+        
+        let test_cases = vec![
+            ("+7 999 735-35-74", "<code>+7(999) 735-35-74</code>"),
+            ("8 (999) 735-35-74", "<code>+7(999) 735-35-74</code>"),
+            ("+1 (202) 555-0123", "<code>+1 (202) 555-0123</code>"),
+            ("+49 170 1234 5678", "<code>+4917012345678</code>"),
+        ];
+
+        for (input, expected) in test_cases {
+            let result = formatter.format(input, ParseMode::Html, &ctx).unwrap();
+            assert_eq!(result, expected, "Failed for input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_phone_formatter_format_rejects_invalid() {
+        let formatter = PhoneFormatter;
+        let ctx = FormatContext::default();
+        
+        for phone in ["", "+1_234*567#890", "+1<234>567&890", "<123>"] {
+            for mode in [ParseMode::MarkdownV2, ParseMode::Html] {
+                let result = formatter.format(phone, mode, &ctx);
+                assert!(
+                    matches!(result, Err(Error::InvalidFormatterValue { .. })),
+                    "Accepted: {}",
+                    phone
+                );
+            }
+            assert!(formatter.validate(phone).is_err());
+        }
+    }
+
+    #[test]
+    fn test_phone_formatter_various_formats() {
+        let formatter = PhoneFormatter;
+        let ctx = FormatContext::default();
+        
+        let phone_numbers = vec![
+            "+44 20 7946 0958",      // UK
+            "+33 1 42 86 82 00",      // France
+            "+49 30 2594 1000",       // Germany
+            "+81 3-1234-5678",        // Japan
+            "+86 10 1234 5678",       // China
+            "+61 2 9374 4000",        // Australia
+            "+55 11 98765-4321",      // Brazil
+            "+91 22 2278 2278",       // India
+            "+7 495 123-45-67",       // Russia
+            "+1-800-FLOWERS",         // US with letters
+        ];
+
+        for phone in phone_numbers {
+            let result = formatter.parse(phone);
+            assert!(result.is_some(), "Failed to parse: {}", phone);
+            
+            let (parsed, _) = result.unwrap();
+            assert!(!parsed.is_empty(), "Empty result for: {}", phone);
+            
+            let formatted_md = formatter.format(&parsed, ParseMode::MarkdownV2, &ctx).unwrap();
+            assert!(formatted_md.starts_with("`"), "Markdown format should start with backtick");
+            assert!(formatted_md.ends_with("`"), "Markdown format should end with backtick");
+            
+            let formatted_html = formatter.format(&parsed, ParseMode::Html, &ctx).unwrap();
+            assert!(formatted_html.starts_with("<code>"), "HTML format should start with <code>");
+            assert!(formatted_html.ends_with("</code>"), "HTML format should end with </code>");
+        }
+    }
+
+    #[test]
+    fn test_currency_formatter_locale() {
+        let formatter = CurrencyFormatter::new("€".to_string(), "EUR".to_string());
+
+        let en = FormatContext::new("en-US");
+        let result = formatter.format("1500.5", ParseMode::Html, &en).unwrap();
+        assert_eq!(result, "<code>1,500.50 €</code>");
+
+        let de = FormatContext::new("de-DE");
+        let result = formatter.format("1500.5", ParseMode::Html, &de).unwrap();
+        assert_eq!(result, "<code>1.500,50 €</code>");
+
+        let result = formatter.format("1500.5", ParseMode::MarkdownV2, &de).unwrap();
+        assert_eq!(result, "`1\\.500,50 €`");
+    }
+
+    #[test]
+    fn test_percent_formatter_locale() {
+        let formatter = PercentFormatter;
+
+        let en = FormatContext::new("en-US");
+        let result = formatter.format("1500.5", ParseMode::Html, &en).unwrap();
+        assert_eq!(result, "<code>1,500.5%</code>");
+
+        let de = FormatContext::new("de-DE");
+        let result = formatter.format("1500.5", ParseMode::Html, &de).unwrap();
+        assert_eq!(result, "<code>1.500,5%</code>");
+    }
+
+    #[test]
+    fn test_generator_locale() {
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.register_formatter(Box::new(PercentFormatter));
+        let element = Element::Custom {
+            formatter: "percent".to_string(),
+            value: "1500.5".to_string(),
+        };
+
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "<code>1500.5%</code>");
+
+        generator.set_locale("de-DE");
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "<code>1.500,5%</code>");
+    }
+
+    #[test]
+    fn test_date_formatter_validate() {
+        let formatter = DateFormatter;
+        assert!(formatter.validate("2024-01-15").is_ok());
+        assert!(matches!(
+            formatter.validate("2024-13-45"),
+            Err(Error::InvalidFormatterValue { .. })
+        ));
+        assert!(formatter.validate("tomorrow").is_err());
+    }
+
+    #[test]
+    fn test_percent_formatter_validate() {
+        let formatter = PercentFormatter;
+        assert!(formatter.validate("15.5").is_ok());
+        assert!(formatter.validate("fifteen").is_err());
+    }
+
+    #[test]
+    fn test_generator_validate() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.register_formatter(Box::new(DateFormatter));
+
+        let valid = Element::bold(vec![Element::Custom {
+            formatter: "date".to_string(),
+            value: "2024-01-15".to_string(),
+        }]);
+        assert!(generator.validate(&valid).is_ok());
+
+        let invalid = Element::group(vec![
+            Element::text("Due: "),
+            Element::italic(vec![Element::Custom {
+                formatter: "date".to_string(),
+                value: "15/01/2024".to_string(),
+            }]),
+        ]);
+        assert!(matches!(
+            generator.validate(&invalid),
+            Err(Error::InvalidFormatterValue { .. })
+        ));
+
+        let missing = Element::Custom {
+            formatter: "unknown".to_string(),
+            value: "1".to_string(),
+        };
+        assert!(matches!(
+            generator.validate(&missing),
+            Err(Error::FormatterNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_currency_formatter_rejects_non_numeric() {
+        let formatter = CurrencyFormatter::new("₽".to_string(), "RUB".to_string());
+        let ctx = FormatContext::default();
+
+        match formatter.format("abc", ParseMode::MarkdownV2, &ctx) {
+            Err(Error::InvalidFormatterValue { formatter, value }) => {
+                assert_eq!(formatter, "RUB");
+                assert_eq!(value, "abc");
+            }
+            other => panic!("Expected InvalidFormatterValue, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_percent_formatter_rejects_non_numeric() {
+        let formatter = PercentFormatter;
+        let ctx = FormatContext::default();
+
+        match formatter.format("ten", ParseMode::Html, &ctx) {
+            Err(Error::InvalidFormatterValue { formatter, value }) => {
+                assert_eq!(formatter, "percent");
+                assert_eq!(value, "ten");
+            }
+            other => panic!("Expected InvalidFormatterValue, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_generator_reports_invalid_formatter_value() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.register_formatter(Box::new(PercentFormatter));
+        let element = Element::Custom {
+            formatter: "percent".to_string(),
+            value: "n/a".to_string(),
+        };
+
+        let mut result = String::new();
+        let err = generator.generate(&mut result, &element).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid value for formatter percent: \"n/a\""
+        );
+    }
+
+    #[test]
+    fn test_registered_formatters() {
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        generator.register_formatter(Box::new(PercentFormatter));
+        generator.register_formatter(Box::new(DateFormatter));
+
+        let mut names: Vec<&str> = generator.registered_formatters().collect();
+        names.sort();
+        assert_eq!(names, vec!["date", "percent"]);
+        assert!(generator.has_formatter("date"));
+        assert!(!generator.has_formatter("phone"));
+    }
+
+    #[test]
+    fn test_override_and_unregister_formatter() {
+        let mut generator = Generator::new(ParseMode::Html);
+        let element = Element::Custom {
+            formatter: "RUB".to_string(),
+            value: "10".to_string(),
+        };
+
+        let previous = generator.register_formatter(Box::new(CurrencyFormatter::new(
+            "₽".to_string(),
+            "RUB".to_string(),
+        )));
+        assert!(previous.is_none());
+
+        let previous = generator.register_formatter(Box::new(CurrencyFormatter::new(
+            "руб.".to_string(),
+            "RUB".to_string(),
+        )));
+        let previous = previous.expect("override should return the previous formatter");
+        let ctx = FormatContext::default();
+        assert_eq!(
+            previous.format("10", ParseMode::Html, &ctx).unwrap(),
+            "<code>10.00 ₽</code>"
+        );
+
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "<code>10.00 руб.</code>");
+
+        assert!(generator.unregister_formatter("RUB").is_some());
+        assert!(generator.unregister_formatter("RUB").is_none());
+        assert!(!generator.has_formatter("RUB"));
+
+        let mut result = String::new();
+        assert!(matches!(
+            generator.generate(&mut result, &element),
+            Err(Error::FormatterNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_relative_time_formatter() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-01-20T12:00:00+00:00").unwrap();
+        let formatter = RelativeTimeFormatter::with_now(now);
+        let ctx = FormatContext::default();
+
+        let cases = [
+            ("2025-01-20T11:59:30Z", "just now"),
+            ("2025-01-20T11:55:00Z", "5 minutes ago"),
+            ("2025-01-20T11:00:00Z", "1 hour ago"),
+            ("2025-01-17T12:00:00Z", "3 days ago"),
+            ("2025-01-20T17:00:00+03:00", "in 2 hours"),
+            ("2023-01-01T00:00:00Z", "2 years ago"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                formatter.format(value, ParseMode::Html, &ctx).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_relative_time_formatter_rejects_invalid_timestamp() {
+        let formatter = RelativeTimeFormatter::new();
+        assert!(formatter.validate("yesterday").is_err());
+        assert!(matches!(
+            formatter.format("yesterday", ParseMode::Html, &FormatContext::default()),
+            Err(Error::InvalidFormatterValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_relative_time_formatter_with_frozen_clock() {
+        use crate::clock::{Clock, FixedClock};
+
+        let frozen = chrono::DateTime::parse_from_rfc3339("2025-01-20T12:00:00Z").unwrap();
+        let clock = FixedClock::new(frozen);
+        assert_eq!(clock.now(), clock.now());
+
+        let formatter = RelativeTimeFormatter::with_clock(clock);
+        let result = formatter
+            .format(
+                "2025-01-20T09:30:00Z",
+                ParseMode::MarkdownV2,
+                &FormatContext::default(),
+            )
+            .unwrap();
+        assert_eq!(result, "2 hours ago");
+    }
+
+    #[test]
+    fn test_formatter_lookup_is_case_insensitive() {
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.register_formatter(Box::new(CurrencyFormatter::new(
+            "$".to_string(),
+            "USD".to_string(),
+        )));
+
+        assert!(generator.has_formatter("usd"));
+        let element = Element::Custom {
+            formatter: "usd".to_string(),
+            value: "5".to_string(),
+        };
+        let mut result = String::new();
+        generator.generate(&mut result, &element).unwrap();
+        assert_eq!(result, "<code>5.00 $</code>");
+
+        let displaced = generator.register_formatter(Box::new(CurrencyFormatter::new(
+            "US$".to_string(),
+            "usd".to_string(),
+        )));
+        assert_eq!(
+            displaced.map(|f| f.name().to_string()),
+            Some("USD".to_string())
+        );
+        assert_eq!(
+            generator.registered_formatters().collect::<Vec<_>>(),
+            vec!["usd"]
+        );
+        assert!(generator.unregister_formatter("Usd").is_some());
+    }
+
+    #[test]
+    fn test_formatter_lookup_folds_unicode_case() {
+        let mut generator = Generator::new(ParseMode::Html);
+        generator.register_formatter(Box::new(CurrencyFormatter::new(
+            "₴".to_string(),
+            "ГРН".to_string(),
+        )));
+        assert!(generator.has_formatter("грн"));
+    }
+
+    #[test]
+    fn test_flag_formatter() {
+        let formatter = FlagFormatter;
+        let ctx = FormatContext::default();
+
+        assert_eq!(
+            formatter.format("US", ParseMode::MarkdownV2, &ctx).unwrap(),
+            "🇺🇸"
+        );
+        assert_eq!(formatter.format("de", ParseMode::Html, &ctx).unwrap(), "🇩🇪");
+        assert_eq!(
+            formatter.format("XX", ParseMode::MarkdownV2, &ctx).unwrap(),
+            "XX"
+        );
+        assert_eq!(
+            formatter
+                .format("U.S.", ParseMode::MarkdownV2, &ctx)
+                .unwrap(),
+            "U\\.S\\."
+        );
+    }
+
+    #[test]
+    fn test_format_phone_russian() {
+        use crate::formatter::format_phone;
+
+        let expected = Some((
+            "+7(999) 735-35-74".to_string(),
+            "tel:+79997353574".to_string(),
+        ));
+        assert_eq!(format_phone("79997353574", None), expected);
+        assert_eq!(format_phone("8 999 735 35 74", None), expected);
+        assert_eq!(format_phone("9997353574", None), expected);
+        assert_eq!(
+            format_phone("9997353574", Some("+7")),
+            Some((
+                "+7 (999) 735-35-74".to_string(),
+                "tel:+79997353574".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_format_phone_international() {
+        use crate::formatter::format_phone;
+
+        assert_eq!(
+            format_phone("491701234567890", None),
+            Some((
+                "+491701234567890".to_string(),
+                "tel:+491701234567890".to_string()
+            ))
+        );
+        let nanp = Some((
+            "+1 (202) 555-0123".to_string(),
+            "tel:+12025550123".to_string(),
+        ));
+        assert_eq!(format_phone("12025550123", None), nanp);
+        assert_eq!(format_phone("2025550123", Some("+1")), nanp);
+        assert_eq!(format_phone("12025550123", Some("+1")), nanp);
+    }
+
+    #[test]
+    fn test_format_phone_without_digits() {
+        use crate::formatter::format_phone;
+
+        assert_eq!(format_phone("", None), None);
+        assert_eq!(format_phone("call me", Some("+7")), None);
+    }
+
+    #[test]
+    fn test_numeric_formatters_reject_out_of_range_values() {
+        let ctx = FormatContext::default();
+        let currency = CurrencyFormatter::new("€".to_string(), "EUR".to_string());
+        let cases: Vec<(&dyn CustomFormatter, &[&str])> = vec![
+            (&currency, &["NaN", "inf", "-inf", "abc"]),
+            (&PercentFormatter, &["NaN", "inf", "-inf", "abc"]),
+            (&ProgressFormatter, &["101", "255", "-1", "NaN"]),
+        ];
+
+        for (formatter, values) in cases {
+            for value in values {
+                assert!(formatter.validate(value).is_err(), "{}", value);
+                assert!(
+                    matches!(
+                        formatter.format(value, ParseMode::Html, &ctx),
+                        Err(Error::InvalidFormatterValue { .. })
+                    ),
+                    "{}",
+                    value
+                );
+            }
+        }
+
+        assert!(ProgressFormatter.validate("100").is_ok());
+        assert_eq!(
+            ProgressFormatter
+                .format("100", ParseMode::Html, &ctx)
+                .unwrap(),
+            "<code>▓▓▓▓▓▓▓▓▓▓ 100%</code>"
+        );
+    }
+}
+
+#[cfg(test)]
+mod split_tests {
+    use crate::ast::*;
+    use crate::generator::{Generator, ParseMode};
+    use crate::split::{message_count, message_count_with, split_message, split_message_with};
+
+    #[test]
+    fn test_message_count_short() {
+        let message = vec![
+            Element::text("Hello, "),
+            Element::bold(vec![Element::text("world")]),
+        ];
+        assert_eq!(message_count(&message, ParseMode::Html, 4096).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_message_count_three_parts() {
+        let line = "a".repeat(30);
+        let message = vec![
+            Element::text(line.as_str()),
+            Element::text(line.as_str()),
+            Element::group(vec![Element::text(line.as_str())]),
+        ];
+
+        assert_eq!(message_count(&message, ParseMode::Html, 40).unwrap(), 3);
+        let chunks = split_message(&message, ParseMode::Html, 40).unwrap();
+        assert_eq!(chunks, vec![line.clone(), line.clone(), line]);
+    }
+
+    #[test]
+    fn test_split_long_text_at_words() {
+        let message = vec![Element::text("one two three four five six")];
+        let chunks = split_message(&message, ParseMode::Html, 10).unwrap();
+        assert_eq!(chunks, vec!["one two ", "three ", "four five ", "six"]);
+        assert_eq!(message_count(&message, ParseMode::Html, 10).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_split_uses_generator_settings() {
+        let message = vec![Element::text("x. y.")];
+
+        let mut generator = Generator::new(ParseMode::MarkdownV2);
+        let chunks = split_message_with(&message, &generator, 6).unwrap();
+        assert_eq!(chunks, vec!["x\\. ", "y\\."]);
+
+        generator.markdown_escape_chars(std::collections::HashSet::new());
+        let chunks = split_message_with(&message, &generator, 6).unwrap();
+        assert_eq!(chunks, vec!["x. y."]);
+    }
+
+    #[test]
+    fn test_split_long_text_into_full_chunks() {
+        let text = "word ".repeat(20_000);
+        let message = vec![Element::text(text.as_str())];
+        let generator = Generator::new(ParseMode::Html);
+
+        let chunks = split_message_with(&message, &generator, 4096).unwrap();
+        assert_eq!(chunks.concat(), text);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 4096));
+        assert_eq!(chunks.len(), 25);
+        assert_eq!(message_count_with(&message, &generator, 4096).unwrap(), 25);
+    }
+
+    #[test]
+    fn test_split_counts_utf16_code_units() {
+        let message = vec![Element::text("😀😀😀")];
+        let chunks = split_message(&message, ParseMode::Html, 4).unwrap();
+        assert_eq!(chunks, vec!["😀😀", "😀"]);
+        assert_eq!(message_count(&message, ParseMode::Html, 4).unwrap(), 2);
+
+        let message = vec![Element::text("😀 a"), Element::text("b")];
+        assert_eq!(message_count(&message, ParseMode::Html, 4).unwrap(), 2);
+    }
+}
+
+#[cfg(test)]
+mod width_tests {
+    use crate::width::{display_width, grapheme_count, wrap_text};
+
+    #[test]
+    fn test_combining_accent_is_one_cluster() {
+        let text = "Cafe\u{301}";
+        assert_eq!(text.chars().count(), 5);
+        assert_eq!(grapheme_count(text), 4);
+        assert_eq!(display_width(text), 4);
+    }
+
+    #[test]
+    fn test_family_emoji_is_one_wide_cluster() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+        assert_eq!(grapheme_count(family), 1);
+        assert_eq!(display_width(family), 2);
+        assert_eq!(display_width("🇺🇦🇩🇪"), 4);
+        assert_eq!(grapheme_count("🇺🇦🇩🇪"), 2);
+    }
+
+    #[test]
+    fn test_wrap_counts_clusters() {
+        assert_eq!(
+            wrap_text("e\u{301}e\u{301}e\u{301}e\u{301}", 2),
+            "e\u{301}e\u{301}\ne\u{301}e\u{301}"
+        );
+    }
+
+    #[test]
+    fn test_format_characters_are_not_extenders() {
+        assert_eq!(grapheme_count("a\u{200B}b"), 3);
+        assert_eq!(display_width("a\u{200B}b"), 2);
+        assert_eq!(grapheme_count("\u{200E}x"), 2);
+        assert_eq!(display_width("\u{200E}x"), 1);
+    }
+
+    #[test]
+    fn test_hangul_jamo_and_indic_marks_cluster() {
+        let jamo = "\u{1100}\u{1161}\u{11A8}";
+        assert_eq!(grapheme_count(jamo), 1);
+        assert_eq!(display_width(jamo), 2);
+
+        assert_eq!(grapheme_count("नमस्ते"), 3);
+    }
+
+    #[test]
+    fn test_emoji_presentation_symbols_are_wide() {
+        assert_eq!(display_width("✅"), 2);
+        assert_eq!(display_width("⚡"), 2);
+        assert_eq!(display_width("☀"), 1);
+        assert_eq!(display_width("☀\u{FE0F}"), 2);
+        assert_eq!(wrap_text("✅✅✅", 4), "✅✅\n✅");
+    }
+}
+
+#[cfg(test)]
+mod message_tests {
+    use crate::ast::*;
+    use crate::generator::{Generate, ParseMode};
+    use crate::message::Message;
+
+    #[test]
+    fn test_builder_bold_link_newline() {
+        let message = Message::builder()
+            .bold("News")
+            .newline()
+            .text("Read ")
+            .link("more", "https://example.com")
+            .build();
+
+        assert_eq!(
+            message.elements(),
+            &[
+                Element::bold(vec![Element::text("News")]),
+                Element::text("\n"),
+                Element::text("Read "),
+                Element::link(vec![Element::text("more")], "https://example.com"),
+            ]
+        );
+        assert_eq!(
+            message.generate(ParseMode::Html).unwrap(),
+            "<b>News</b>\nRead <a href=\"https://example.com\">more</a>"
+        );
+    }
+
+    #[test]
+    fn test_builder_list_and_table() {
+        let message = Message::builder()
+            .list(["one", "two"])
+            .table(["Name", "Qty"], [["Apple", "3"]])
+            .build();
+
+        match message.elements() {
+            [Element::List(list), Element::Table(table)] => {
+                assert_eq!(list.items.len(), 2);
+                assert_eq!(table.headers.len(), 2);
+                assert_eq!(table.rows[0].cells[1].content, vec![Element::text("3")]);
+            }
+            other => panic!("unexpected elements: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_message_iteration() {
+        let mut message: Message = vec![Element::text("a"), Element::code("b")]
+            .into_iter()
+            .collect();
+        message.extend([Element::text("c")]);
+
+        let mut borrowed = Vec::new();
+        for element in &message {
+            borrowed.push(element.clone());
+        }
+        assert_eq!(borrowed, message.iter().cloned().collect::<Vec<_>>());
+
+        let chained: Vec<Element> = message.into_iter().chain([Element::text("d")]).collect();
+        assert_eq!(
+            chained,
+            vec![
+                Element::text("a"),
+                Element::code("b"),
+                Element::text("c"),
+                Element::text("d")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_with_caps_whole_message() {
+        use crate::generator::Generator;
+
+        let message: Message = (0..10).map(|_| Element::text("abc")).collect();
+        let mut generator = Generator::new(ParseMode::Html);
+        assert_eq!(message.generate_with(&generator).unwrap().len(), 30);
+
+        generator.max_output_len(Some(20));
+        assert!(message.generate_with(&generator).is_err());
+    }
+}
+
+#[cfg(test)]
+mod telegraph_tests {
+    use crate::ast::*;
+    use crate::telegraph::render_telegraph;
+
+    #[test]
+    fn test_telegraph_paragraph_with_link() {
+        let elements = vec![
+            Element::text("Read "),
+            Element::link(
+                vec![Element::bold(vec![Element::text("the docs")])],
+                "https://example.com/?a=1&b=2",
+            ),
+            Element::text(" first.\n"),
+        ];
+        assert_eq!(
+            render_telegraph(&elements).unwrap(),
+            "<p>Read <a href=\"https://example.com/?a=1&amp;b=2\"><b>the docs</b></a> first.</p>"
+        );
+    }
+
+    #[test]
+    fn test_telegraph_lists() {
+        let elements = vec![
+            Element::text("Steps:\n"),
+            Element::List(ListNode {
+                style: ListStyle::Numbered,
+                items: vec![
+                    ListItem {
+                        content: vec![Element::text("Install")],
+                        nested: Some(Box::new(ListNode {
+                            style: ListStyle::Bullet,
+                            items: vec![ListItem {
+                                content: vec![Element::code("cargo add msg")],
+                                nested: None,
+                            }],
+                        })),
+                    },
+                    ListItem {
+                        content: vec![Element::text("Run")],
+                        nested: None,
+                    },
+                ],
+            }),
+        ];
+        assert_eq!(
+            render_telegraph(&elements).unwrap(),
+            "<p>Steps:</p><ol><li>Install<ul><li><code>cargo add msg</code></li></ul></li><li>Run</li></ol>"
+        );
+    }
+
+    #[test]
+    fn test_telegraph_table_and_quote() {
+        let elements = vec![
+            Element::Table(TableNode {
+                headers: vec![TableCell {
+                    content: vec![Element::text("A")],
+                    ..TableCell::default()
+                }],
+                rows: vec![],
+                style: TableStyle::Html,
+                rules: Vec::new(),
+                default_align: CellAlign::Left,
+            }),
+            Element::quote_with_cite(vec![Element::text("Be brief")], "Editor"),
+        ];
+        let html = render_telegraph(&elements).unwrap();
+        assert!(html.starts_with("<pre>"));
+        assert!(!html.contains("<table>"));
+        assert!(html.ends_with("<blockquote>Be brief<br><i>— Editor</i></blockquote>"));
+    }
+
+    #[test]
+    fn test_telegraph_image() {
+        let elements = vec![Element::image(
+            "https://example.com/a.png",
+            "Chart & legend",
+        )];
+        assert_eq!(
+            render_telegraph(&elements).unwrap(),
+            "<p><img src=\"https://example.com/a.png\" alt=\"Chart &amp; legend\"></p>"
+        );
+    }
 }
 
 #[cfg(test)]
-mod formatter_tests {
-    use crate::formatter::{CustomFormatter, PhoneFormatter};
-    use crate::generator::ParseMode;
+mod stats_tests {
+    use crate::ast::*;
+    use crate::stats::{stats, MessageStats};
 
     #[test]
-    fn test_phone_formatter_name() {
-        let formatter = PhoneFormatter;
-        assert_eq!(formatter.name(), "phone");
+    fn test_stats_counts_entities_and_words() {
+        let message = vec![
+            Element::text("Hi "),
+            Element::mention("alice"),
+            Element::text(", see "),
+            Element::link(vec![Element::text("the docs")], "https://example.com"),
+            Element::text(" and "),
+            Element::TextLink {
+                text: "changelog".to_string(),
+                url: "https://example.com/changes".to_string(),
+            },
+            Element::text(" "),
+            Element::hashtag("release"),
+        ];
+
+        assert_eq!(
+            stats(&message),
+            MessageStats {
+                chars: 46,
+                words: 8,
+                links: 2,
+                mentions: 1,
+                hashtags: 1,
+            }
+        );
     }
 
     #[test]
-    fn test_phone_formatter_parse_international() {
-        let formatter = PhoneFormatter;
-        
-        let test_cases = vec![
-            ("+1234567890", "+1234567890", 11),
-            ("+1 234 567 890", "+1 234 567 890", 14),
-            ("+1-234-567-890", "+1-234-567-890", 14),
-            ("+1(234)567-890", "+1(234)567-890", 14),
-            ("+7 (495) 123-45-67", "+7 (495) 123-45-67", 18),
-            ("+380 44 123 4567", "+380 44 123 4567", 16),
-        ];
+    fn test_stats_counts_grapheme_clusters() {
+        let message = vec![Element::bold(vec![Element::text(
+            "Cafe\u{301} 👨\u{200D}👩\u{200D}👧",
+        )])];
+        assert_eq!(stats(&message).chars, 6);
+    }
+}
 
-        for (input, expected, expected_len) in test_cases {
-            let result = formatter.parse(input);
-            assert!(result.is_some(), "Failed to parse: {}", input);
-            let (parsed, len) = result.unwrap();
-            assert_eq!(parsed, expected, "Failed for input: {}", input);
-            assert_eq!(len, expected_len, "Wrong length for: {}", input);
-        }
+#[cfg(test)]
+mod template_tests {
+    use crate::ast::*;
+    use crate::template::template;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_template_substitutes_elements() {
+        let mut args = HashMap::new();
+        args.insert("name", vec![Element::bold(vec![Element::text("Ann")])]);
+        args.insert("count", vec![Element::text("3")]);
+
+        let elements = template("Hello, {name}! You have {count} messages", &args).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                Element::text("Hello, "),
+                Element::bold(vec![Element::text("Ann")]),
+                Element::text("! You have "),
+                Element::text("3"),
+                Element::text(" messages"),
+            ]
+        );
     }
 
     #[test]
-    fn test_phone_formatter_parse_local() {
-        let formatter = PhoneFormatter;
-        
-        let test_cases = vec![
-            ("1234567890", "1234567890", 10),
-            ("123-456-7890", "123-456-7890", 12),
-            ("(123) 456-7890", "(123) 456-7890", 14),
-            ("123 456 7890", "123 456 7890", 12),
-            ("8 800 555 35 35", "8 800 555 35 35", 15),
-        ];
+    fn test_template_escaped_braces() {
+        let mut args = HashMap::new();
+        args.insert("x", vec![Element::code("42")]);
 
-        for (input, expected, expected_len) in test_cases {
-            let result = formatter.parse(input);
-            assert!(result.is_some(), "Failed to parse: {}", input);
-            let (parsed, len) = result.unwrap();
-            assert_eq!(parsed, expected, "Failed for input: {}", input);
-            assert_eq!(len, expected_len, "Wrong length for: {}", input);
-        }
+        let elements = template("{{x}} = {x}", &args).unwrap();
+        assert_eq!(elements, vec![Element::text("{x} = "), Element::code("42")]);
     }
 
     #[test]
-    fn test_phone_formatter_parse_with_text() {
-        let formatter = PhoneFormatter;
-        
-        let input = "+1234567890 call me";
-        let result = formatter.parse(input);
-        assert!(result.is_some());
-        let (parsed, len) = result.unwrap();
-        assert_eq!(parsed.trim(), "+1234567890");
-        assert_eq!(len, 12); // includes the trailing space
+    fn test_template_errors() {
+        let args = HashMap::new();
+        assert!(template("Hi {name}", &args).is_err());
+        assert!(template("Hi {name", &args).is_err());
+        assert!(template("a } b", &args).is_err());
+    }
+}
+
+#[cfg(test)]
+mod entities_tests {
+    use crate::ast::*;
+    use crate::entities::{
+        check_entity_limits, to_entities, EntityKind, MessageEntity, MAX_ENTITY_DEPTH,
+    };
+
+    // Separated by plain text, so each word is its own entity
+    fn bold_words(count: usize) -> Vec<Element> {
+        (0..count)
+            .flat_map(|i| {
+                [
+                    Element::bold(vec![Element::text(format!("w{}", i))]),
+                    Element::text(" "),
+                ]
+            })
+            .collect()
     }
 
     #[test]
-    fn test_phone_formatter_parse_empty() {
-        let formatter = PhoneFormatter;
-        
-        let input = "";
-        let result = formatter.parse(input);
-        assert!(result.is_none());
+    fn test_entity_count_boundary() {
+        assert!(check_entity_limits(&bold_words(100)).is_ok());
+        assert!(check_entity_limits(&bold_words(101)).is_err());
     }
 
     #[test]
-    fn test_phone_formatter_parse_non_phone() {
-        let formatter = PhoneFormatter;
-        
-        let input = "abc def";
-        let result = formatter.parse(input);
-        assert!(result.is_none());
+    fn test_entity_depth_boundary() {
+        let nested = |depth: usize| {
+            let mut element = Element::link(vec![Element::text("deep")], "https://example.com");
+            for _ in 1..depth {
+                element = Element::bold(vec![element]);
+            }
+            vec![element]
+        };
+        assert!(check_entity_limits(&nested(MAX_ENTITY_DEPTH)).is_ok());
+        assert!(check_entity_limits(&nested(MAX_ENTITY_DEPTH + 1)).is_err());
+
+        // Every entity type that may nest, each used once
+        let element = Element::quote(vec![Element::bold(vec![Element::italic(vec![
+            Element::underline(vec![Element::strikethrough(vec![Element::spoiler(vec![
+                Element::link(vec![Element::text("x")], "https://example.com"),
+            ])])]),
+        ])])]);
+        assert!(check_entity_limits(&[element]).is_ok());
     }
 
     #[test]
-    fn test_phone_formatter_format_markdown() {
-        let formatter = PhoneFormatter;
-        
-        let test_cases = vec![
-            ("+1234567890", "`\\+1234567890`"),
-            ("+1-234-567-890", "`\\+1\\-234\\-567\\-890`"),
-            ("+1 (234) 567-890", "`\\+1 \\(234\\) 567\\-890`"),
-            ("123-456-7890", "`123\\-456\\-7890`"),
-            ("(123) 456-7890", "`\\(123\\) 456\\-7890`"),
-        ];
+    fn test_entity_count_merges_adjacent_runs() {
+        let mut elements = bold_words(99);
+        elements.extend(
+            (0..50).map(|i| Element::italic(vec![Element::text(format!("i{}", i))])),
+        );
+        assert_eq!(to_entities(&elements).unwrap().1.len(), 100);
+        assert!(check_entity_limits(&elements).is_ok());
 
-        for (input, expected) in test_cases {
-            let result = formatter.format(input, ParseMode::MarkdownV2).unwrap();
-            assert_eq!(result, expected, "Failed for input: {}", input);
-        }
+        elements.push(Element::text(" "));
+        elements.push(Element::code("x"));
+        assert!(check_entity_limits(&elements).is_err());
     }
 
     #[test]
-    fn test_phone_formatter_format_html() {
-        let formatter = PhoneFormatter;
-        
-        let test_cases = vec![
-            ("+1234567890", "<code>+1234567890</code>"),
-            ("+1-234-567-890", "<code>+1-234-567-890</code>"),
-            ("+1 (234) 567-890", "<code>+1 (234) 567-890</code>"),
-            ("123-456-7890", "<code>123-456-7890</code>"),
-            ("(123) 456-7890", "<code>(123) 456-7890</code>"),
-            ("<123>", "<code>&lt;123&gt;</code>"),
+    fn test_entity_count_includes_nested_and_grouped() {
+        let mut elements = bold_words(98);
+        elements.push(Element::Group(vec![Element::italic(vec![
+            Element::code("x"),
+            Element::hashtag("tag"),
+        ])]));
+        assert!(check_entity_limits(&elements).is_err());
+    }
+
+    #[test]
+    fn test_entity_counts() {
+        let message = Element::Group(vec![
+            Element::bold(vec![Element::text("one")]),
+            Element::text(" "),
+            Element::bold(vec![Element::link(
+                vec![Element::text("two")],
+                "https://example.com",
+            )]),
+        ]);
+        let counts = message.entity_counts();
+        assert_eq!(counts.get("bold"), Some(&2));
+        assert_eq!(counts.get("text_link"), Some(&1));
+        assert_eq!(counts.len(), 2);
+    }
+
+    #[test]
+    fn test_to_entities_merges_grouped_runs() {
+        let group = Element::group(vec![
+            Element::bold(vec![Element::text("a")]),
+            Element::bold(vec![Element::text("b")]),
+        ]);
+        let (text, entities) = to_entities(&[group]).unwrap();
+        assert_eq!(text, "ab");
+        assert_eq!(
+            entities,
+            vec![MessageEntity {
+                kind: EntityKind::Bold,
+                offset: 0,
+                length: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_to_entities_table_is_plain_text() {
+        let cell = |text: &str, style| TableCell {
+            content: vec![Element::text(text)],
+            style,
+            ..Default::default()
+        };
+        let table = Element::Table(TableNode {
+            headers: vec![cell("a<b", CellStyle::Bold)],
+            rows: vec![TableRow {
+                cells: vec![cell("x & y", CellStyle::Normal)],
+            }],
+            style: TableStyle::Compact,
+            rules: vec![],
+            default_align: CellAlign::Left,
+        });
+        let (text, entities) = to_entities(&[table]).unwrap();
+        assert_eq!(text, "  a<b    \n  x & y  ");
+        assert_eq!(entities[0].kind, EntityKind::Pre { language: None });
+    }
+
+    #[test]
+    fn test_to_entities_utf16_offsets() {
+        let elements = vec![
+            Element::text("😀 "),
+            Element::link(vec![Element::text("go")], "https://example.com"),
         ];
+        let (text, entities) = to_entities(&elements).unwrap();
+        assert_eq!(text, "😀 go");
+        assert_eq!(entities[0].offset, 3);
+        assert_eq!(entities[0].length, 2);
+        assert_eq!(
+            entities[0].kind,
+            EntityKind::TextLink {
+                url: "https://example.com".to_string()
+            }
+        );
+    }
 
-        for (input, expected) in test_cases {
-            let result = formatter.format(input, ParseMode::Html).unwrap();
-            assert_eq!(result, expected, "Failed for input: {}", input);
-        }
+    #[test]
+    fn test_from_entities_nested_bold_italic() {
+        use crate::entities::from_entities;
+
+        let entities = [
+            MessageEntity {
+                kind: EntityKind::Bold,
+                offset: 0,
+                length: 5,
+            },
+            MessageEntity {
+                kind: EntityKind::Italic,
+                offset: 3,
+                length: 2,
+            },
+        ];
+        assert_eq!(
+            from_entities("ab cd!", &entities).unwrap(),
+            vec![
+                Element::bold(vec![
+                    Element::text("ab "),
+                    Element::italic(vec![Element::text("cd")]),
+                ]),
+                Element::text("!"),
+            ]
+        );
     }
 
     #[test]
-    fn test_phone_formatter_format_special_chars_markdown() {
-        let formatter = PhoneFormatter;
-        
-        let phone = "+1_234*567#890";
-        let result = formatter.format(phone, ParseMode::MarkdownV2).unwrap();
-        assert_eq!(result, "`\\+1\\_234\\*567\\#890`");
+    fn test_from_entities_text_link_utf16() {
+        use crate::entities::from_entities;
+
+        let entities = [MessageEntity {
+            kind: EntityKind::TextLink {
+                url: "https://example.com".to_string(),
+            },
+            offset: 3,
+            length: 4,
+        }];
+        assert_eq!(
+            from_entities("😀 docs", &entities).unwrap(),
+            vec![
+                Element::text("😀 "),
+                Element::link(vec![Element::text("docs")], "https://example.com"),
+            ]
+        );
     }
 
     #[test]
-    fn test_phone_formatter_format_special_chars_html() {
-        let formatter = PhoneFormatter;
-        
-        let phone = "+1<234>567&890";
-        let result = formatter.format(phone, ParseMode::Html).unwrap();
-        assert_eq!(result, "<code>+1&lt;234&gt;567&amp;890</code>");
+    fn test_from_entities_splits_overlaps() {
+        use crate::entities::from_entities;
+
+        let entities = [
+            MessageEntity {
+                kind: EntityKind::Bold,
+                offset: 0,
+                length: 4,
+            },
+            MessageEntity {
+                kind: EntityKind::Italic,
+                offset: 2,
+                length: 4,
+            },
+        ];
+        assert_eq!(
+            from_entities("abcdef", &entities).unwrap(),
+            vec![
+                Element::bold(vec![
+                    Element::text("ab"),
+                    Element::italic(vec![Element::text("cd")]),
+                ]),
+                Element::italic(vec![Element::text("ef")]),
+            ]
+        );
     }
 
     #[test]
-    fn test_phone_formatter_format_empty() {
-        let formatter = PhoneFormatter;
-        
-        let result_md = formatter.format("", ParseMode::MarkdownV2).unwrap();
-        assert_eq!(result_md, "``");
-        
-        let result_html = formatter.format("", ParseMode::Html).unwrap();
-        assert_eq!(result_html, "<code></code>");
+    fn test_from_entities_rejects_split_surrogate() {
+        use crate::entities::from_entities;
+
+        let entities = [MessageEntity {
+            kind: EntityKind::Bold,
+            offset: 1,
+            length: 1,
+        }];
+        assert!(from_entities("😀", &entities).is_err());
+        let entities = [MessageEntity {
+            kind: EntityKind::Bold,
+            offset: 0,
+            length: usize::MAX,
+        }];
+        assert!(from_entities("a", &entities).is_err());
     }
 
     #[test]
-    fn test_phone_formatter_various_formats() {
-        let formatter = PhoneFormatter;
-        
-        let phone_numbers = vec![
-            "+44 20 7946 0958",      // UK
-            "+33 1 42 86 82 00",      // France
-            "+49 30 2594 1000",       // Germany
-            "+81 3-1234-5678",        // Japan
-            "+86 10 1234 5678",       // China
-            "+61 2 9374 4000",        // Australia
-            "+55 11 98765-4321",      // Brazil
-            "+91 22 2278 2278",       // India
-            "+7 495 123-45-67",       // Russia
-            "+1-800-FLOWERS",         // US with letters
+    fn test_entities_round_trip() {
+        use crate::entities::from_entities;
+
+        let elements = vec![
+            Element::text("Hi "),
+            Element::bold(vec![
+                Element::text("🎉 "),
+                Element::italic(vec![Element::text("all")]),
+            ]),
+            Element::text(", see "),
+            Element::code("x"),
         ];
+        let (text, entities) = to_entities(&elements).unwrap();
+        assert_eq!(from_entities(&text, &entities).unwrap(), elements);
+    }
+}
 
-        for phone in phone_numbers {
-            let result = formatter.parse(phone);
-            assert!(result.is_some(), "Failed to parse: {}", phone);
-            
-            let (parsed, _) = result.unwrap();
-            assert!(!parsed.is_empty(), "Empty result for: {}", phone);
-            
-            let formatted_md = formatter.format(&parsed, ParseMode::MarkdownV2).unwrap();
-            assert!(formatted_md.starts_with("`"), "Markdown format should start with backtick");
-            assert!(formatted_md.ends_with("`"), "Markdown format should end with backtick");
-            
-            let formatted_html = formatter.format(&parsed, ParseMode::Html).unwrap();
-            assert!(formatted_html.starts_with("<code>"), "HTML format should start with <code>");
-            assert!(formatted_html.ends_with("</code>"), "HTML format should end with </code>");
-        }
+#[cfg(test)]
+mod normalize_tests {
+    use crate::ast::*;
+    use crate::normalize::{collapse_nested, merge_adjacent};
+
+    #[test]
+    fn test_collapse_nested_bold() {
+        let nested = vec![Element::bold(vec![Element::bold(vec![Element::text("x")])])];
+        assert_eq!(
+            collapse_nested(nested),
+            vec![Element::bold(vec![Element::text("x")])]
+        );
+    }
+
+    #[test]
+    fn test_collapse_nested_keeps_other_types() {
+        let nested = vec![Element::italic(vec![
+            Element::text("a "),
+            Element::bold(vec![Element::italic(vec![Element::italic(vec![
+                Element::text("b"),
+            ])])]),
+        ])];
+        assert_eq!(
+            collapse_nested(nested),
+            vec![Element::italic(vec![
+                Element::text("a "),
+                Element::bold(vec![Element::italic(vec![Element::text("b")])]),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_merge_adjacent_bolds() {
+        use crate::generator::{Generator, ParseMode};
+
+        let merged = merge_adjacent(vec![
+            Element::bold(vec![Element::text("a")]),
+            Element::bold(vec![Element::text("b")]),
+            Element::italic(vec![Element::text("c")]),
+            Element::link(vec![Element::text("d")], "https://a.example"),
+            Element::link(vec![Element::text("e")], "https://b.example"),
+        ]);
+        assert_eq!(merged.len(), 4);
+        assert_eq!(
+            merged[0],
+            Element::bold(vec![Element::text("a"), Element::text("b")])
+        );
+
+        let message = Element::Group(merged[..2].to_vec());
+        assert_eq!(message.entity_counts().get("bold"), Some(&1));
+        let mut output = String::new();
+        Generator::new(ParseMode::MarkdownV2)
+            .generate(&mut output, &message)
+            .unwrap();
+        assert_eq!(output, "*ab*_c_");
+    }
+}
+
+#[cfg(test)]
+mod conditional_tests {
+    use crate::ast::Condition;
+
+    #[test]
+    fn test_condition_empty() {
+        assert!(Condition::Empty.evaluate(""));
+        assert!(Condition::Empty.evaluate("  "));
+        assert!(!Condition::Empty.evaluate("x"));
+    }
+
+    #[test]
+    fn test_condition_non_empty() {
+        assert!(Condition::NonEmpty.evaluate("x"));
+        assert!(!Condition::NonEmpty.evaluate(""));
+        assert!(!Condition::NonEmpty.evaluate(" \t"));
+    }
+
+    #[test]
+    fn test_condition_length_greater_than() {
+        let condition = Condition::LengthGreaterThan(3);
+        assert!(condition.evaluate("abcd"));
+        assert!(!condition.evaluate("abc"));
+        assert!(!condition.evaluate("äöü"));
+        assert!(!condition.evaluate("a\u{301}o\u{308}u"));
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use crate::ast::*;
+    use crate::cache::RenderCache;
+    use crate::generator::ParseMode;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = RenderCache::new(2);
+        let (a, b, c) = (Element::text("a"), Element::text("b"), Element::text("c"));
+
+        cache.insert(ParseMode::Html, &a, "A".to_string());
+        cache.insert(ParseMode::Html, &b, "B".to_string());
+        assert_eq!(cache.get(ParseMode::Html, &a), Some("A".to_string()));
+
+        cache.insert(ParseMode::Html, &c, "C".to_string());
+        assert_eq!(cache.get(ParseMode::Html, &b), None);
+        assert_eq!(cache.get(ParseMode::Html, &a), Some("A".to_string()));
+        assert_eq!(cache.get(ParseMode::Html, &c), Some("C".to_string()));
+
+        cache.insert(ParseMode::Html, &c, "C2".to_string());
+        cache.insert(ParseMode::Html, &b, "B".to_string());
+        assert_eq!(cache.get(ParseMode::Html, &a), None);
+        assert_eq!(cache.get(ParseMode::Html, &c), Some("C2".to_string()));
+
+        cache.clear();
+        assert_eq!(cache.get(ParseMode::Html, &c), None);
+    }
+
+    #[test]
+    fn test_keys_on_mode_and_structure() {
+        let mut cache = RenderCache::new(4);
+        let element = Element::bold(vec![Element::text("x")]);
+
+        cache.insert(ParseMode::Html, &element, "<b>x</b>".to_string());
+        assert_eq!(cache.get(ParseMode::MarkdownV2, &element), None);
+        assert_eq!(
+            cache.get(ParseMode::Html, &Element::italic(vec![Element::text("x")])),
+            None
+        );
+        assert_eq!(
+            cache.get(ParseMode::Html, &element.clone()),
+            Some("<b>x</b>".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tables_with_rules_are_never_hits() {
+        let mut cache = RenderCache::new(1);
+        let table = Element::Table(TableNode {
+            headers: vec![],
+            rows: vec![],
+            style: TableStyle::Ascii,
+            rules: vec![ConditionalFormat {
+                condition: Condition::Empty,
+                format: |content| content,
+            }],
+            default_align: CellAlign::Left,
+        });
+
+        cache.insert(ParseMode::Html, &table, "table".to_string());
+        assert_eq!(cache.get(ParseMode::Html, &table), None);
+        cache.insert(ParseMode::Html, &table, "table".to_string());
+        cache.insert(ParseMode::Html, &Element::text("a"), "a".to_string());
+        assert_eq!(
+            cache.get(ParseMode::Html, &Element::text("a")),
+            Some("a".to_string())
+        );
     }
 }
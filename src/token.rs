@@ -62,16 +62,49 @@ impl Token {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LexerOptions {
+    pub mentions: bool,
+    pub hashtags: bool,
+    pub commands: bool,
+    /// Bare `http://` and `https://` URLs become `Token::Link`.
+    pub urls: bool,
+}
+
+impl Default for LexerOptions {
+    fn default() -> Self {
+        Self {
+            mentions: true,
+            hashtags: true,
+            commands: true,
+            urls: true,
+        }
+    }
+}
+
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
+    command_end: Option<usize>,
+    // Delimiter runs seen so far that no later run of the same length closed
+    open_runs: Vec<(char, usize)>,
+    run_end: usize,
+    options: LexerOptions,
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
+        Self::new_with(input, LexerOptions::default())
+    }
+
+    pub fn new_with(input: &str, options: LexerOptions) -> Self {
         Self {
             input: input.chars().collect(),
             position: 0,
+            command_end: None,
+            open_runs: Vec::new(),
+            run_end: 0,
+            options,
         }
     }
 
@@ -90,6 +123,9 @@ impl Lexer {
 
     fn next_token(&mut self) -> Option<Token> {
         let ch = self.current_char()?;
+        if matches!(ch, '*' | '_' | '`' | '~' | '|') {
+            self.track_run(ch);
+        }
 
         match ch {
             '*' => {
@@ -112,6 +148,9 @@ impl Lexer {
                 self.advance();
                 Some(Token::Pipe)
             }
+            '@' | '#' | '/' if !self.sigil_enabled(ch) || !self.at_entity_start() => {
+                Some(self.read_text())
+            }
             '@' => {
                 self.advance();
                 if let Some(mention) = self.read_mention() {
@@ -131,6 +170,7 @@ impl Lexer {
             '/' => {
                 self.advance();
                 if let Some(command) = self.read_command() {
+                    self.command_end = Some(self.position);
                     Some(command)
                 } else {
                     Some(Token::Slash)
@@ -169,10 +209,26 @@ impl Lexer {
                     Some(Token::Text("\\".to_string()))
                 }
             }
+            '!' => {
+                if let Some(emoji) = self.read_custom_emoji() {
+                    Some(emoji)
+                } else {
+                    self.advance();
+                    Some(Token::Text("!".to_string()))
+                }
+            }
             '\n' => {
                 self.advance();
                 Some(Token::LineBreak)
             }
+            '\r' => {
+                self.advance();
+                if self.current_char() == Some('\n') {
+                    self.advance();
+                }
+                Some(Token::LineBreak)
+            }
+            _ if self.at_url_start() => Some(self.read_url()),
             _ => Some(self.read_text()),
         }
     }
@@ -187,9 +243,6 @@ impl Lexer {
                     | '`'
                     | '~'
                     | '|'
-                    | '@'
-                    | '#'
-                    | '/'
                     | '('
                     | ')'
                     | '['
@@ -198,9 +251,24 @@ impl Lexer {
                     | '}'
                     | '\\'
                     | '\n'
+                    | '\r'
             ) {
                 break;
             }
+            // A sigil this read started on is text already, see next_token
+            if matches!(ch, '@' | '#' | '/')
+                && !text.is_empty()
+                && self.sigil_enabled(ch)
+                && self.at_entity_start()
+            {
+                break;
+            }
+            if ch == '!' && self.input.get(self.position + 1) == Some(&'[') {
+                break;
+            }
+            if !text.is_empty() && self.at_url_start() {
+                break;
+            }
             text.push(ch);
             self.advance();
         }
@@ -208,6 +276,133 @@ impl Lexer {
         Token::Text(text)
     }
 
+    // Like the macro's literal autolinking, a URL runs to the next whitespace.
+    // Trailing markup stays outside it only when it closes a span opened
+    // before the URL, and, as in GFM, an unbalanced closing bracket is left
+    // out, so `(see https://a.com)` and `*https://a.com*` work as expected.
+    fn at_url_start(&self) -> bool {
+        if !self.options.urls {
+            return false;
+        }
+        let boundary = self.position == 0 || {
+            let prev = self.input[self.position - 1];
+            prev.is_whitespace() || matches!(prev, '*' | '_' | '~' | '|' | '{')
+        };
+        let rest = &self.input[self.position..];
+        let scheme = ["https://", "http://"]
+            .into_iter()
+            .find(|scheme| rest.iter().copied().take(scheme.len()).eq(scheme.chars()));
+        boundary
+            && scheme.is_some_and(|scheme| {
+                rest.get(scheme.len())
+                    .is_some_and(|&ch| !ch.is_whitespace() && !is_markup(ch))
+            })
+    }
+
+    fn read_url(&mut self) -> Token {
+        // Each char with the input position just past it; escaped chars are
+        // never trimmed
+        let mut chars: Vec<(char, bool, usize)> = Vec::new();
+        let mut position = self.position;
+
+        while let Some(&ch) = self.input.get(position) {
+            if ch.is_whitespace() {
+                break;
+            }
+            position += 1;
+            if ch == '\\' {
+                match self.input.get(position) {
+                    Some(&escaped) => {
+                        position += 1;
+                        chars.push((escaped, true, position));
+                    }
+                    None => chars.push((ch, false, position)),
+                }
+            } else {
+                chars.push((ch, false, position));
+            }
+        }
+
+        while let Some(trim) = self.url_trailing_markup(&chars) {
+            chars.truncate(chars.len() - trim);
+        }
+
+        self.position = chars.last().map_or(self.position, |&(_, _, end)| end);
+        Token::Link(chars.into_iter().map(|(ch, _, _)| ch).collect())
+    }
+
+    // How many trailing chars of a URL belong to the surrounding markup
+    fn url_trailing_markup(&self, chars: &[(char, bool, usize)]) -> Option<usize> {
+        let &(last, escaped, _) = chars.last()?;
+        if escaped {
+            return None;
+        }
+
+        let opening = match last {
+            ')' => Some('('),
+            ']' => Some('['),
+            '}' => Some('{'),
+            _ => None,
+        };
+        if let Some(opening) = opening {
+            let count = |target| {
+                chars
+                    .iter()
+                    .filter(|&&(ch, escaped, _)| ch == target && !escaped)
+                    .count()
+            };
+            return (count(last) > count(opening)).then_some(1);
+        }
+
+        let run = chars
+            .iter()
+            .rev()
+            .take_while(|&&(ch, escaped, _)| ch == last && !escaped)
+            .count();
+        self.open_runs
+            .iter()
+            .rev()
+            .find(|&&(ch, len)| ch == last && len <= run)
+            .map(|&(_, len)| len)
+    }
+
+    // A run of delimiters closes the latest open run of the same char and
+    // length, or opens a new one
+    fn track_run(&mut self, ch: char) {
+        if self.position < self.run_end {
+            return;
+        }
+        let len = self.input[self.position..]
+            .iter()
+            .take_while(|&&c| c == ch)
+            .count();
+        self.run_end = self.position + len;
+
+        match self.open_runs.iter().rposition(|&run| run == (ch, len)) {
+            Some(index) => self.open_runs.truncate(index),
+            None => self.open_runs.push((ch, len)),
+        }
+    }
+
+    fn sigil_enabled(&self, sigil: char) -> bool {
+        match sigil {
+            '@' => self.options.mentions,
+            '#' => self.options.hashtags,
+            '/' => self.options.commands,
+            _ => false,
+        }
+    }
+
+    // Mentions, hashtags and commands only start after whitespace or markup,
+    // so `C#` and `a/b` stay text. `/start@bot` may continue with a mention.
+    fn at_entity_start(&self) -> bool {
+        if self.position == 0 || self.command_end == Some(self.position) {
+            return true;
+        }
+        let prev = self.input[self.position - 1];
+        prev.is_whitespace() || matches!(prev, '*' | '_' | '~' | '|' | '(' | '[' | '{')
+    }
+
     fn read_mention(&mut self) -> Option<Token> {
         let start = self.position;
         let mut username = String::new();
@@ -271,6 +466,33 @@ impl Lexer {
         }
     }
 
+    // Matches `![emoji](tg://emoji?id=123)` as a whole, leaving the position
+    // untouched otherwise so other image-link forms lex as plain text and a link.
+    fn read_custom_emoji(&mut self) -> Option<Token> {
+        const TARGET: &str = "](tg://emoji?id=";
+
+        let input = &self.input[self.position..];
+        let body = input.strip_prefix(&['!', '['])?;
+        let len = body.iter().position(|&c| matches!(c, '[' | ']' | '\n'))?;
+        if len == 0 {
+            return None;
+        }
+
+        let target = &body[len..];
+        if !target.iter().copied().take(TARGET.len()).eq(TARGET.chars()) {
+            return None;
+        }
+        let after = &target[TARGET.len()..];
+        let digits = after.iter().take_while(|c| c.is_ascii_digit()).count();
+        if after.get(digits) != Some(&')') {
+            return None;
+        }
+        let id = after[..digits].iter().collect::<String>().parse().ok()?;
+
+        self.position += 2 + len + TARGET.len() + digits + 1;
+        Some(Token::CustomEmoji(body[..len].iter().collect(), id))
+    }
+
     fn current_char(&self) -> Option<char> {
         self.input.get(self.position).copied()
     }
@@ -283,3 +505,10 @@ impl Lexer {
         self.position >= self.input.len()
     }
 }
+
+fn is_markup(ch: char) -> bool {
+    matches!(
+        ch,
+        '*' | '_' | '`' | '~' | '|' | '(' | ')' | '[' | ']' | '{' | '}'
+    )
+}
@@ -0,0 +1,71 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Monospace columns `s` takes up, measured per grapheme cluster so a
+/// combining accent or a family emoji counts once.
+pub fn display_width(s: &str) -> usize {
+    s.graphemes(true).map(cluster_width).sum()
+}
+
+pub(crate) fn grapheme_count(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+// `unicode-width` sizes emoji sequences, flags and Hangul syllables as a
+// whole, but gives control characters a width of 1
+fn cluster_width(cluster: &str) -> usize {
+    if cluster.chars().all(char::is_control) {
+        0
+    } else {
+        cluster.width()
+    }
+}
+
+pub(crate) fn wrap_text(text: &str, width: usize) -> String {
+    let width = width.max(1);
+    let mut wrapped = String::with_capacity(text.len());
+
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            wrapped.push('\n');
+        }
+
+        let mut column = 0;
+        for segment in line.split_inclusive(char::is_whitespace) {
+            let word = segment.trim_end();
+            let word_width = display_width(word);
+            let has_wide = word.graphemes(true).any(|c| cluster_width(c) > 1);
+
+            if word_width > width || has_wide {
+                for cluster in segment.graphemes(true) {
+                    let cw = cluster_width(cluster);
+                    if column + cw > width && column > 0 {
+                        break_line(&mut wrapped);
+                        column = 0;
+                        if cluster.chars().all(char::is_whitespace) {
+                            continue;
+                        }
+                    }
+                    wrapped.push_str(cluster);
+                    column += cw;
+                }
+                continue;
+            }
+
+            if column > 0 && column + word_width > width {
+                break_line(&mut wrapped);
+                column = 0;
+            }
+            wrapped.push_str(segment);
+            column += display_width(segment);
+        }
+    }
+
+    wrapped
+}
+
+fn break_line(wrapped: &mut String) {
+    let trimmed = wrapped.trim_end_matches([' ', '\t']).len();
+    wrapped.truncate(trimmed);
+    wrapped.push('\n');
+}
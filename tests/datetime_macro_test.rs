@@ -0,0 +1,53 @@
+use chrono::{FixedOffset, NaiveDate, NaiveTime, TimeZone};
+use msg::{msg, Element};
+
+fn text(element: &Element) -> &str {
+    match element {
+        Element::Text(text) => text,
+        other => panic!("Expected Text element, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_datetime_default_format() {
+    let dt = NaiveDate::from_ymd_opt(2025, 1, 20)
+        .unwrap()
+        .and_hms_opt(15, 30, 5)
+        .unwrap();
+    let message = msg! { datetime(dt) };
+
+    assert_eq!(text(&message[0]), "2025-01-20 15:30:05");
+}
+
+#[test]
+fn test_datetime_custom_format() {
+    let dt = NaiveDate::from_ymd_opt(2025, 1, 20)
+        .unwrap()
+        .and_hms_opt(15, 30, 5)
+        .unwrap();
+    let message = msg! { datetime(dt, "%d.%m.%Y %H:%M") };
+
+    assert_eq!(text(&message[0]), "20.01.2025 15:30");
+}
+
+#[test]
+fn test_date_and_time_custom_format() {
+    let date = NaiveDate::from_ymd_opt(2025, 1, 20).unwrap();
+    let time = NaiveTime::from_hms_opt(9, 5, 0).unwrap();
+    let message = msg! { date(date, "%d/%m/%Y") " " time(time, "%H:%M") };
+
+    assert_eq!(text(&message[0]), "20/01/2025");
+    assert_eq!(text(&message[2]), "09:05");
+}
+
+#[test]
+fn test_datetime_tz_keeps_offset() {
+    let dt = FixedOffset::east_opt(3 * 3600)
+        .unwrap()
+        .with_ymd_and_hms(2025, 1, 20, 15, 30, 0)
+        .unwrap();
+    let message = msg! { datetime_tz(dt) " " datetime_tz(dt, "%H:%M %z") };
+
+    assert_eq!(text(&message[0]), "2025-01-20 15:30:00 +03:00");
+    assert_eq!(text(&message[2]), "15:30 +0300");
+}
@@ -0,0 +1,214 @@
+use msg::{el, el_one, msg, Element, Generator, ParseMode};
+
+#[test]
+fn test_el_one_plain_literal() {
+    let element = el_one!("Hello");
+    assert_eq!(element, Element::text("Hello"));
+}
+
+#[test]
+fn test_el_one_collapses_single_child_group() {
+    assert!(matches!(el!("\n"), Element::Group(_)));
+    assert_eq!(el_one!("\n"), Element::text("\n"));
+}
+
+#[test]
+fn test_el_one_url_literal() {
+    assert_eq!(
+        el_one!("https://example.com"),
+        Element::TextLink {
+            text: "https://example.com".to_string(),
+            url: "https://example.com".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_html_raw_item() {
+    let tag = "<tg-spoiler>hidden</tg-spoiler>";
+    let message = msg! { "Secret: " html_raw(tag) };
+
+    assert_eq!(message[1], Element::RawHtml(tag.to_string()));
+
+    let mut html = String::new();
+    for element in &message {
+        Generator::new(ParseMode::Html)
+            .generate(&mut html, element)
+            .unwrap();
+    }
+    assert_eq!(html, "Secret: <tg-spoiler>hidden</tg-spoiler>");
+}
+
+#[test]
+fn test_bold_with_newline_has_no_nested_group() {
+    let message = msg! { bold { "a\nb" } };
+
+    assert_eq!(
+        message,
+        vec![Element::Bold(vec![
+            Element::text("a"),
+            Element::text("\n"),
+            Element::text("b"),
+        ])]
+    );
+}
+
+#[test]
+fn test_nested_containers_splice_groups_and_references() {
+    let inner = msg! { "x" "y" };
+    let message = msg! { strikethrough { "see https://example.com" #inner } };
+
+    assert_eq!(
+        message,
+        vec![Element::Strikethrough(vec![
+            Element::text("see "),
+            Element::TextLink {
+                text: "https://example.com".to_string(),
+                url: "https://example.com".to_string(),
+            },
+            Element::text("x"),
+            Element::text("y"),
+        ])]
+    );
+}
+
+#[test]
+fn test_mention_single_argument() {
+    let username = "rustlang";
+    let message = msg! { mention { username } mention("ferris") };
+
+    assert_eq!(
+        message,
+        vec![Element::mention("rustlang"), Element::mention("ferris")]
+    );
+}
+
+#[test]
+fn test_mention_with_user_id_and_name() {
+    let user_id = 123456789;
+    let message = msg! { mention(user_id, "Ferris Crab") };
+
+    assert_eq!(message, vec![Element::mention_id(123456789, "Ferris Crab")]);
+
+    let mut html = String::new();
+    Generator::new(ParseMode::Html)
+        .generate(&mut html, &message[0])
+        .unwrap();
+    assert_eq!(html, "<a href=\"tg://user?id=123456789\">Ferris Crab</a>");
+}
+
+#[test]
+fn test_tme_user_link() {
+    let username = "@durov";
+    let message = msg! { tme(username) };
+
+    assert_eq!(
+        message,
+        vec![Element::text_link("@durov", "https://t.me/durov")]
+    );
+}
+
+#[test]
+fn test_tme_channel_post_link() {
+    let message = msg! { tme("telegram", 42) };
+
+    assert_eq!(
+        message,
+        vec![Element::text_link(
+            "t.me/telegram/42",
+            "https://t.me/telegram/42"
+        )]
+    );
+}
+
+#[test]
+fn test_quote_with_cite() {
+    let author = "Linus";
+    let message = msg! { quote(cite = author) { "Talk is cheap." } quote { "Anonymous" } };
+
+    assert_eq!(
+        message,
+        vec![
+            Element::quote_with_cite(vec![Element::text("Talk is cheap.")], "Linus"),
+            Element::quote(vec![Element::text("Anonymous")]),
+        ]
+    );
+}
+
+#[test]
+fn test_command_with_and_without_bot() {
+    let bot = "OtherBot";
+    let message = msg! { command("start") " " command("help")@MyBot " " command("stop")@(bot) };
+
+    assert_eq!(
+        message,
+        vec![
+            Element::command("start"),
+            Element::text(" "),
+            Element::command_for_bot("help", "MyBot"),
+            Element::text(" "),
+            Element::command_for_bot("stop", "OtherBot"),
+        ]
+    );
+}
+
+#[test]
+fn test_keyword_named_variables_render_as_text() {
+    let command = "start";
+    let image = "cat.png";
+    let message = msg! { "run " command " on " image };
+
+    assert_eq!(
+        message,
+        vec![
+            Element::text("run "),
+            Element::text("start"),
+            Element::text(" on "),
+            Element::text("cat.png"),
+        ]
+    );
+}
+
+#[test]
+fn test_image_macro() {
+    let url = "https://example.com/cat.png";
+    let message = msg! { image(url) { "A cat" } };
+
+    assert_eq!(
+        message,
+        vec![Element::image("https://example.com/cat.png", "A cat")]
+    );
+}
+
+#[test]
+fn test_nbsp_macro() {
+    let message = msg! { "100" nbsp "km" };
+
+    assert_eq!(message[1], Element::nbsp());
+    assert_eq!(message[1].text_iter().collect::<String>(), "\u{00A0}");
+}
+
+#[test]
+fn test_raw_el_macro() {
+    let highlighted = Element::bold(vec![Element::text("important")]);
+    let message = msg! { "This is " raw_el(highlighted.clone()) "!" };
+
+    assert_eq!(
+        message,
+        vec![Element::text("This is "), highlighted, Element::text("!")]
+    );
+}
+
+#[test]
+fn test_blank_lines_and_edge_spaces_survive() {
+    let message = msg! { "  a\n\nb  " bold { "\n\nc" } "\n" };
+
+    let mut output = String::new();
+    for element in &message {
+        Generator::new(ParseMode::Html)
+            .generate(&mut output, element)
+            .unwrap();
+    }
+    assert_eq!(output, "  a\n\nb  <b>\n\nc</b>\n");
+    assert_eq!(output.matches('\n').count(), 5);
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "intern")]
+
+use msg::intern::Interner;
+use msg::Element;
+use std::sync::Arc;
+
+#[test]
+fn test_interned_texts_share_storage() {
+    let mut interner = Interner::new(16);
+    let first = interner.text("Total");
+    let second = interner.text("Total");
+
+    match (&first, &second) {
+        (Element::Text(a), Element::Text(b)) => assert!(Arc::ptr_eq(a, b)),
+        _ => panic!("Expected Text elements"),
+    }
+    assert_eq!(interner.len(), 1);
+}
+
+#[test]
+fn test_interner_respects_limit() {
+    let mut interner = Interner::new(1);
+    interner.intern("a");
+    let first = interner.intern("b");
+    let second = interner.intern("b");
+
+    assert_eq!(first, second);
+    assert!(!Arc::ptr_eq(&first, &second));
+    assert_eq!(interner.len(), 1);
+}
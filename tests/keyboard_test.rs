@@ -0,0 +1,33 @@
+#![cfg(feature = "serde")]
+
+use msg::keyboard::label;
+use msg::{msg, Button, Keyboard};
+
+#[test]
+fn test_keyboard_serializes_to_inline_keyboard_markup() {
+    let keyboard = Keyboard::new()
+        .row([
+            Button::callback("Yes", "vote:yes"),
+            Button::callback("No", "vote:no"),
+        ])
+        .row([
+            Button::url("Docs", "https://example.com/docs"),
+            Button::url(label(&msg! { bold { "Help" } }), "https://example.com/help"),
+        ]);
+
+    assert_eq!(
+        serde_json::to_value(&keyboard).unwrap(),
+        serde_json::json!({
+            "inline_keyboard": [
+                [
+                    { "text": "Yes", "callback_data": "vote:yes" },
+                    { "text": "No", "callback_data": "vote:no" }
+                ],
+                [
+                    { "text": "Docs", "url": "https://example.com/docs" },
+                    { "text": "Help", "url": "https://example.com/help" }
+                ]
+            ]
+        })
+    );
+}
@@ -37,7 +37,7 @@ fn test_phone_formatter_with_prefix() {
     
     assert_eq!(message.len(), 2);
     match &message[0] {
-        Element::Text(text) => assert_eq!(text, "Call us: "),
+        Element::Text(text) => assert_eq!(&**text, "Call us: "),
         _ => panic!("Expected Text element first"),
     }
     match &message[1] {
@@ -133,7 +133,7 @@ fn test_phone_formatter_empty_string() {
     assert_eq!(message.len(), 1);
     match &message[0] {
         Element::Text(text) => {
-            assert_eq!(text, "-");
+            assert_eq!(&**text, "-");
         }
         _ => panic!("Expected Text element with '-' for empty phone, got: {:?}", message[0]),
     }
@@ -174,4 +174,81 @@ fn test_phone_formatter_as_requested() {
     }
     // In HTML, it renders as an anchor tag
     assert_eq!(output_html, "<a href=\"tel:+79997353574\">+7(999) 735-35-74</a>");
-}
\ No newline at end of file
+}
+#[test]
+fn test_phone_formatter_with_integer() {
+    let phone: u64 = 79997353574;
+    let message = msg! { +(phone) };
+
+    match &message[0] {
+        Element::TextLink { text, url } => {
+            assert_eq!(text, "+7(999) 735-35-74");
+            assert_eq!(url, "tel:+79997353574");
+        }
+        _ => panic!("Expected TextLink element for phone, got: {:?}", message[0]),
+    }
+}
+
+#[test]
+fn test_phone_formatter_long_international_string() {
+    let phone = "491701234567890";
+    let message = msg! { +(phone) };
+
+    match &message[0] {
+        Element::TextLink { text, url } => {
+            assert_eq!(text, "+491701234567890");
+            assert_eq!(url, "tel:+491701234567890");
+        }
+        _ => panic!("Expected TextLink element for phone, got: {:?}", message[0]),
+    }
+}
+
+#[test]
+fn test_phone_formatter_nanp_number() {
+    let phone = "12025550123";
+    let message = msg! { +(phone) };
+
+    match &message[0] {
+        Element::TextLink { text, url } => {
+            assert_eq!(text, "+1 (202) 555-0123");
+            assert_eq!(url, "tel:+12025550123");
+        }
+        _ => panic!("Expected TextLink element for phone, got: {:?}", message[0]),
+    }
+}
+
+#[test]
+fn test_phone_formatter_nanp_explicit_prefix() {
+    for phone in ["2025550123", "12025550123"] {
+        let message = msg! { +1(phone) };
+
+        match &message[0] {
+            Element::TextLink { text, url } => {
+                assert_eq!(text, "+1 (202) 555-0123");
+                assert_eq!(url, "tel:+12025550123");
+            }
+            _ => panic!("Expected TextLink element for phone, got: {:?}", message[0]),
+        }
+    }
+}
+
+#[test]
+fn test_phone_strict_valid_number() {
+    let phone = "+7 999 123 45 67";
+    let message = msg! { phone_strict(phone) };
+
+    match &message[0] {
+        Element::TextLink { text, url } => {
+            assert_eq!(text, "+7(999) 123-45-67");
+            assert_eq!(url, "tel:+79991234567");
+        }
+        _ => panic!("Expected TextLink element for phone, got: {:?}", message[0]),
+    }
+}
+
+#[test]
+#[should_panic(expected = "phone_strict: invalid phone number \"n/a\"")]
+fn test_phone_strict_panics_on_invalid_input() {
+    let phone = "n/a";
+    let _ = msg! { phone_strict(phone) };
+}
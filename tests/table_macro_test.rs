@@ -0,0 +1,73 @@
+use msg::{el, msg, Element, Generator, ParseMode};
+
+fn cell_text(cell: &msg::TableCell) -> String {
+    cell.content
+        .iter()
+        .map(|e| match e {
+            Element::Text(t) => t.to_string(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_table_from_vectors() {
+    let headers = vec!["Name".to_string(), "Age".to_string()];
+    let rows = vec![
+        vec!["Alice".to_string(), "30".to_string()],
+        vec!["Bob".to_string(), "25".to_string()],
+    ];
+
+    let element = el!(table_from(headers, rows));
+
+    match &element {
+        Element::Table(table) => {
+            let header_texts: Vec<String> = table.headers.iter().map(cell_text).collect();
+            assert_eq!(header_texts, vec!["Name", "Age"]);
+            assert_eq!(table.rows.len(), 2);
+            let first_row: Vec<String> = table.rows[0].cells.iter().map(cell_text).collect();
+            assert_eq!(first_row, vec!["Alice", "30"]);
+        }
+        _ => panic!("Expected Table element, got: {:?}", element),
+    }
+}
+
+#[test]
+fn test_table_from_in_message() {
+    let scores: Vec<(&str, u32)> = vec![("Alice", 10), ("Bob", 7)];
+    let rows: Vec<Vec<String>> = scores
+        .iter()
+        .map(|(name, score)| vec![name.to_string(), score.to_string()])
+        .collect();
+
+    let message = msg! {
+        "Scores:\n"
+        table_from(["Player", "Score"], rows)
+    };
+
+    let generator = Generator::new(ParseMode::Html);
+    let mut output = String::new();
+    for element in &message {
+        generator.generate(&mut output, element).unwrap();
+    }
+
+    assert!(output.contains("Player"));
+    assert!(output.contains("Alice"));
+    assert!(output.contains("<pre>"));
+}
+
+#[test]
+fn test_table_rows_with_commas() {
+    let element = el!(table {
+        headers: ["Name", "Age"]
+        rows: [
+            ["Alice", "30"],
+            ["Bob", "25"],
+        ]
+    });
+
+    match &element {
+        Element::Table(table) => assert_eq!(table.rows.len(), 2),
+        _ => panic!("Expected Table element, got: {:?}", element),
+    }
+}